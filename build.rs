@@ -0,0 +1,201 @@
+// Generates the information elements of `ie` from the IANA registry in
+// registry/ipfix-information-elements.csv. The file can be replaced with a
+// newer copy of https://www.iana.org/assignments/ipfix/ipfix-information-elements.csv,
+// columns are found by their header. Constants are named after the registry,
+// but carry the name records are keyed by.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const REGISTRY: &str = "registry/ipfix-information-elements.csv";
+
+// abstract data types with a constant in `ie`, others are untyped
+const DATA_TYPES: &[&str] = &[
+    "unsigned8",
+    "unsigned16",
+    "unsigned32",
+    "unsigned64",
+    "signed8",
+    "signed16",
+    "signed32",
+    "signed64",
+    "float32",
+    "float64",
+    "boolean",
+    "macAddress",
+    "octetArray",
+    "string",
+    "dateTimeSeconds",
+    "dateTimeMilliseconds",
+    "dateTimeMicroseconds",
+    "dateTimeNanoseconds",
+    "ipv4Address",
+    "ipv6Address",
+    "basicList",
+    "subTemplateList",
+    "subTemplateMultiList",
+];
+
+// the names `formatter::get_default_parsers` keys records by where they
+// differ from the registry, kept as they were before the registry
+const RECORD_KEYS: &[(u16, &str)] = &[
+    (5, "classOfServiceIPv4"),
+    (9, "sourceIPv4Mask"),
+    (13, "destinationIPv4Mask"),
+    (25, "minimumPacketLength"),
+    (26, "maximumPacketLength"),
+    (29, "sourceIPv6Mask"),
+    (30, "destinationIPv6Mask"),
+    (36, "flowActiveTimeOut"),
+    (37, "flowInactiveTimeout"),
+    (42, "exportedFlowTotalCount"),
+    (52, "minimumTtl"),
+    (53, "maximumTtl"),
+    (54, "identificationIPv4"),
+    (55, "postClassOfServiceIPv4"),
+    (57, "postDestinationMacAddr"),
+    (70, "mplsTopLabelStackEntry"),
+    (71, "mplsLabelStackEntry2"),
+    (72, "mplsLabelStackEntry3"),
+    (73, "mplsLabelStackEntry4"),
+    (74, "mplsLabelStackEntry5"),
+    (75, "mplsLabelStackEntry6"),
+    (76, "mplsLabelStackEntry7"),
+    (77, "mplsLabelStackEntry8"),
+    (78, "mplsLabelStackEntry9"),
+    (79, "mplsLabelStackEntry10"),
+    (88, "fragmentOffsetIPv4"),
+    (137, "classOfServiceIPv6"),
+    (138, "postClassOfServiceIPv6"),
+    (147, "wlanSsid"),
+    (149, "sourceId"),
+    (152, "flowStartMilliSeconds"),
+    (153, "flowEndMilliSeconds"),
+    (154, "flowStartMicroSeconds"),
+    (155, "flowEndMicroSeconds"),
+    (156, "flowStartNanoSeconds"),
+    (157, "flowEndNanoSeconds"),
+    (158, "flowStartDeltaMicroSeconds"),
+    (159, "flowEndDeltaMicroSeconds"),
+    (160, "systemInitTimeMilliSeconds"),
+    (161, "flowDurationMilliSeconds"),
+    (162, "flowDurationMicroSeconds"),
+    (192, "ipTimeToLive"),
+    (194, "ipClassOfService"),
+    (197, "fragmentFlagsIPv4"),
+    (200, "mplsTopLabelTtl"),
+    (207, "internetHeaderLengthIPv4"),
+    (213, "headerLengthIPv4"),
+    (214, "mplsPayloadLength"),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", REGISTRY);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let registry = fs::read_to_string(REGISTRY).expect("read the IANA registry");
+    let mut rows = records(&registry).into_iter();
+    let header = rows.next().expect("registry header");
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h == name)
+            .unwrap_or_else(|| panic!("registry has no {} column", name))
+    };
+    let (id, name, data_type) = (
+        column("ElementID"),
+        column("Name"),
+        column("Abstract Data Type"),
+    );
+
+    let mut out = String::from("information_elements! {\n");
+    let mut seen = Vec::new();
+    for row in rows {
+        let field = |i: usize| row.get(i).map(|s| s.trim()).unwrap_or_default();
+        // reserved, unassigned and ranges kept for NetFlow v9
+        let id: u16 = match field(id).parse() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        let name = field(name);
+        if name.is_empty() || name.contains(' ') || id == 0 {
+            continue;
+        }
+        let konst = constant(name);
+        let name = RECORD_KEYS
+            .iter()
+            .find(|&&(key_id, _)| key_id == id)
+            .map_or(name, |&(_, key)| key);
+        assert!(
+            !seen.contains(&konst),
+            "{} is defined twice in the registry",
+            konst
+        );
+        let data_type = match field(data_type) {
+            t if DATA_TYPES.contains(&t) => format!("data_type::{}", constant(t)),
+            _ => "data_type::UNTYPED".to_string(),
+        };
+        writeln!(out, "    {} = ({}, {:?}, {}),", konst, id, name, data_type).unwrap();
+        seen.push(konst);
+    }
+    out.push_str("}\n");
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("information_elements.rs");
+    fs::write(path, out).expect("write the information elements");
+}
+
+// the rows of a CSV file, quoted fields may hold commas, quotes and newlines
+fn records(csv: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let (mut row, mut field, mut quoted) = (Vec::new(), String::new(), false);
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            '\r' if !quoted => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+// the constant of an IANA name, e.g. SOURCE_IPV4_ADDRESS for
+// sourceIPv4Address, acronyms kept together
+fn constant(name: &str) -> String {
+    // spelled irregularly in the registry
+    let name = name
+        .replace("IPv", "Ipv")
+        .replace("MCast", "Mcast")
+        .replace("VRFname", "VrfName");
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 8);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
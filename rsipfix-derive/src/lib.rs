@@ -0,0 +1,174 @@
+//! `#[derive(IpfixRecord)]`, implementing `rsipfix::record::IpfixRecord`
+//! for structs with named fields. Every field takes an `ipfix` attribute:
+//!
+//! - `#[ipfix(ie = 8)]` the IANA information element 8
+//! - `#[ipfix(pen = 35632, ie = 205)]` field 205 of enterprise 35632
+//! - `#[ipfix(ie = 82, default)]` `Default::default()` when missing, not
+//!   allowed on `Option` fields
+//! - `#[ipfix(skip)]` not read from records, always `Default::default()`,
+//!   alone
+//!
+//! Fields of type `Option<T>` are `None` when missing, other fields are
+//! required. Values are converted with `rsipfix::record::FromValue`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{
+    Data, DataStruct, DeriveInput, Error, Field, Fields, GenericArgument, Lit, Meta, NestedMeta,
+    PathArguments, Type,
+};
+
+/// where a struct field is read from
+enum Source {
+    Field { pen: u32, ie: u16, default: bool },
+    Skip,
+}
+
+#[proc_macro_derive(IpfixRecord, attributes(ipfix))]
+pub fn derive_ipfix_record(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(ref fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "IpfixRecord can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let mut inits = Vec::with_capacity(fields.len());
+    for f in fields {
+        let ident = f.ident.as_ref().expect("named field");
+        let label = ident.to_string();
+        let value = match source(f)? {
+            Source::Skip => quote!(::std::default::Default::default()),
+            Source::Field { pen, ie, default } => match option_type(&f.ty) {
+                Some(inner) => quote! {
+                    ::rsipfix::record::field::<#inner>(parser, record, #pen, #ie, #label)?
+                },
+                None if default => quote! {
+                    ::rsipfix::record::field(parser, record, #pen, #ie, #label)?
+                        .unwrap_or_default()
+                },
+                None => quote! {
+                    ::rsipfix::record::required(parser, record, #pen, #ie, #label)?
+                },
+            },
+        };
+        inits.push(quote!(#ident: #value));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rsipfix::record::IpfixRecord for #name #ty_generics #where_clause {
+            fn from_record(
+                parser: &::rsipfix::parser::Parser,
+                record: &::rsipfix::parser::DataRecord,
+            ) -> ::std::result::Result<Self, ::rsipfix::parser::ValueError> {
+                ::std::result::Result::Ok(#name {
+                    #(#inits),*
+                })
+            }
+        }
+    })
+}
+
+// the source of a field from its #[ipfix(..)] attribute
+fn source(f: &Field) -> syn::Result<Source> {
+    let mut source = None;
+    for attr in f.attrs.iter().filter(|a| a.path.is_ident("ipfix")) {
+        if source.is_some() {
+            return Err(Error::new_spanned(attr, "duplicate #[ipfix(..)] attribute"));
+        }
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[ipfix(ie = ..)]")),
+        };
+
+        let (mut pen, mut ie, mut default, mut skip) = (0, None, false, false);
+        for nested in &list.nested {
+            match *nested {
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("ie") => {
+                    ie = Some(integer(&nv.lit)?)
+                }
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("pen") => {
+                    pen = integer(&nv.lit)?
+                }
+                NestedMeta::Meta(Meta::Path(ref p)) if p.is_ident("default") => default = true,
+                NestedMeta::Meta(Meta::Path(ref p)) if p.is_ident("skip") => skip = true,
+                ref other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        "unknown ipfix attribute, expected ie, pen, default or skip",
+                    ))
+                }
+            }
+        }
+        if skip && list.nested.len() > 1 {
+            return Err(Error::new_spanned(
+                list,
+                "skip can't be combined with ie, pen or default",
+            ));
+        }
+        if default && option_type(&f.ty).is_some() {
+            return Err(Error::new_spanned(
+                list,
+                "default has no effect on an Option field, it is None when missing",
+            ));
+        }
+        source = Some(match ie {
+            _ if skip => Source::Skip,
+            Some(ie) => Source::Field { pen, ie, default },
+            None => return Err(Error::new_spanned(list, "missing ie = <id>")),
+        });
+    }
+    source.ok_or_else(|| Error::new_spanned(f, "missing #[ipfix(ie = ..)] or #[ipfix(skip)]"))
+}
+
+fn integer<N>(lit: &Lit) -> syn::Result<N>
+where
+    N: std::str::FromStr,
+    N::Err: std::fmt::Display,
+{
+    match *lit {
+        Lit::Int(ref i) => i.base10_parse(),
+        _ => Err(Error::new_spanned(lit, "expected an integer")),
+    }
+}
+
+// T of an Option<T> field
+fn option_type(ty: &Type) -> Option<&Type> {
+    let path = match *ty {
+        Type::Path(ref p) if p.qself.is_none() => &p.path,
+        _ => return None,
+    };
+    let last = path.segments.last()?;
+    if last.ident != "Option" {
+        return None;
+    }
+    match last.arguments {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => match args.args[0] {
+            GenericArgument::Type(ref t) => Some(t),
+            _ => None,
+        },
+        _ => None,
+    }
+}
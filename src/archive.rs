@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::SchemaRef;
+use columnar;
+use parquet::arrow::ArrowWriter;
+use rustc_hash::FxHashMap as HashMap;
+use std::fs::{self, File};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use timestamp;
+
+const SECONDS_PER_DAY: u32 = 86_400;
+
+/// archive configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// directory the partitions are created in
+    pub dir: PathBuf,
+    /// length of a time bucket, buckets have to evenly divide a day
+    pub bucket: Duration,
+    /// size a file is closed at, a new one is started for the next batch
+    pub max_file_size: usize,
+}
+
+/// Sink archives decoded data sets to Parquet files partitioned by exporter
+/// and time bucket, as
+/// `<dir>/exporter=<address>/date=<yyyy-mm-dd>/hour=<hh>/<domain>-<template>-<hhmm>-<n>.parquet`.
+///
+/// Batches go to the file of their exporter, observation domain and
+/// template id, bucketed by the export time of their message. Batches of a
+/// bucket the exporter already moved on from go to the current bucket's
+/// file. A file is closed when the bucket is over, it grows past
+/// `Config::max_file_size` or its template is redefined with other fields,
+/// so every file has a single schema. Files are written under a `.tmp` name
+/// and only renamed once they are complete.
+pub struct Sink {
+    config: Config,
+    bucket: u32,
+    open: HashMap<(IpAddr, u32, u16), OpenFile>,
+    exporters: HashMap<IpAddr, Clock>,
+    completed: Vec<PathBuf>,
+}
+
+// the current bucket of an exporter and how far its clock is behind ours
+struct Clock {
+    bucket: u32,
+    skew: i64,
+}
+
+struct OpenFile {
+    writer: ArrowWriter<File>,
+    schema: SchemaRef,
+    bucket: u32,
+    tmp: PathBuf,
+    path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("."),
+            bucket: Duration::from_secs(3600),
+            max_file_size: 128 << 20,
+        }
+    }
+}
+
+impl Sink {
+    pub fn new(config: Config) -> Result<Self> {
+        let bucket = config.bucket.as_secs() as u32;
+        if bucket == 0 || SECONDS_PER_DAY / bucket * bucket != SECONDS_PER_DAY {
+            return Err(anyhow!(
+                "bucket of {:?} doesn't evenly divide a day",
+                config.bucket
+            ));
+        }
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self {
+            config,
+            bucket,
+            open: HashMap::default(),
+            exporters: HashMap::default(),
+            completed: Vec::new(),
+        })
+    }
+
+    /// append a batch to the file of its exporter, domain and template
+    pub fn write(&mut self, exporter: IpAddr, batch: &columnar::Batch) -> Result<()> {
+        if batch.batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let skew = unix_seconds(SystemTime::now()) - i64::from(batch.export_time);
+        let mut bucket = batch.export_time - batch.export_time % self.bucket;
+        match self.exporters.get_mut(&exporter) {
+            // late, keep writing to the current files
+            Some(clock) if clock.bucket >= bucket => {
+                bucket = clock.bucket;
+                clock.skew = skew;
+            }
+            Some(clock) => {
+                *clock = Clock { bucket, skew };
+                // the exporter moved on to a new bucket, so did all of its
+                // templates
+                self.close_where(|key, f| key.0 == exporter && f.bucket < bucket)?;
+            }
+            None => {
+                self.exporters.insert(exporter, Clock { bucket, skew });
+            }
+        }
+
+        let key = (exporter, batch.observation_domain_id, batch.template_id);
+        let schema = batch.batch.schema();
+        let stale = match self.open.get(&key) {
+            Some(f) => f.bucket != bucket || f.schema != schema,
+            None => false,
+        };
+        if stale {
+            self.close_where(|k, _| *k == key)?;
+        }
+        if !self.open.contains_key(&key) {
+            let file = self.create(key, bucket, schema)?;
+            self.open.insert(key, file);
+        }
+
+        let full = match self.open.get_mut(&key) {
+            Some(f) => {
+                f.writer.write(&batch.batch)?;
+                f.writer.bytes_written() + f.writer.in_progress_size() >= self.config.max_file_size
+            }
+            None => false,
+        };
+        if full {
+            self.close_where(|k, _| *k == key)?;
+        }
+        Ok(())
+    }
+
+    /// close the files of buckets over by `now`, for exporters that stopped
+    /// sending. `now` is taken back to the exporter's clock by how far it
+    /// was behind at its last write. Returns the files completed since the
+    /// last call, rollovers by `write` included.
+    pub fn close_expired(&mut self, now: SystemTime) -> Result<Vec<PathBuf>> {
+        let now = unix_seconds(now);
+        let length = i64::from(self.bucket);
+        let exporters = &self.exporters;
+        let expired = |exporter: &IpAddr, bucket: u32| match exporters.get(exporter) {
+            Some(clock) => i64::from(bucket) + length <= now - clock.skew,
+            None => true,
+        };
+        let keys: Vec<(IpAddr, u32, u16)> = self
+            .open
+            .iter()
+            .filter(|(key, f)| expired(&key.0, f.bucket))
+            .map(|(key, _)| *key)
+            .collect();
+        self.close_where(|key, _| keys.contains(key))?;
+
+        // forget exporters without open files
+        let open = &self.open;
+        self.exporters
+            .retain(|exporter, _| open.keys().any(|key| key.0 == *exporter));
+        Ok(self.take_completed())
+    }
+
+    /// close every open file, returns the files completed since the last
+    /// `take_completed` or `close_expired`
+    pub fn close(mut self) -> Result<Vec<PathBuf>> {
+        self.close_where(|_, _| true)?;
+        Ok(self.take_completed())
+    }
+
+    /// the files completed since the last call
+    pub fn take_completed(&mut self) -> Vec<PathBuf> {
+        self.completed.split_off(0)
+    }
+
+    /// number of files being written
+    pub fn open_files(&self) -> usize {
+        self.open.len()
+    }
+
+    fn close_where<F: Fn(&(IpAddr, u32, u16), &OpenFile) -> bool>(&mut self, f: F) -> Result<()> {
+        let keys: Vec<(IpAddr, u32, u16)> = self
+            .open
+            .iter()
+            .filter(|(key, file)| f(key, file))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            if let Some(file) = self.open.remove(&key) {
+                file.writer.close()?;
+                fs::rename(&file.tmp, &file.path)?;
+                self.completed.push(file.path);
+            }
+        }
+        Ok(())
+    }
+
+    fn create(&self, key: (IpAddr, u32, u16), bucket: u32, schema: SchemaRef) -> Result<OpenFile> {
+        let (exporter, domain, template_id) = key;
+        let (date, hour, minute) = civil(bucket);
+        let dir = self
+            .config
+            .dir
+            .join(format!("exporter={}", exporter))
+            .join(format!("date={}", date))
+            .join(format!("hour={:02}", hour));
+        fs::create_dir_all(&dir)?;
+
+        // never overwrite files of an earlier run
+        let name = format!("{}-{}-{:02}{:02}", domain, template_id, hour, minute);
+        let mut n = 0;
+        let (tmp, path) = loop {
+            let path = dir.join(format!("{}-{}.parquet", name, n));
+            let tmp = dir.join(format!("{}-{}.parquet.tmp", name, n));
+            if !exists(&path) && !exists(&tmp) && !self.is_open(&tmp) {
+                break (tmp, path);
+            }
+            n += 1;
+        };
+        let writer = ArrowWriter::try_new(File::create(&tmp)?, schema.clone(), None)?;
+        Ok(OpenFile {
+            writer,
+            schema,
+            bucket,
+            tmp,
+            path,
+        })
+    }
+
+    fn is_open(&self, tmp: &Path) -> bool {
+        self.open.values().any(|f| f.tmp == tmp)
+    }
+}
+
+impl Drop for Sink {
+    /// close the open files, errors are lost, `close` reports them
+    fn drop(&mut self) {
+        let _ = self.close_where(|_, _| true);
+    }
+}
+
+fn unix_seconds(t: SystemTime) -> i64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn exists(path: &Path) -> bool {
+    fs::metadata(path).is_ok()
+}
+
+// date, hour and minute (UTC) of UNIX seconds
+fn civil(seconds: u32) -> (String, u32, u32) {
+    let (year, month, day) = timestamp::civil_from_days(i64::from(seconds / SECONDS_PER_DAY));
+    let time = seconds % SECONDS_PER_DAY;
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        time / 3600,
+        time % 3600 / 60,
+    )
+}
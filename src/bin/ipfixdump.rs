@@ -0,0 +1,383 @@
+extern crate anyhow;
+extern crate rsipfix;
+
+use anyhow::{anyhow, Result};
+use rsipfix::{csv, file, jsonl, parser, pcap, state};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::process;
+
+const USAGE: &str = "usage: ipfixdump [options] <input>
+
+input (one of):
+  -u, --udp <addr>         listen for IPFIX over UDP on <addr>
+  -r, --pcap <path>        read a pcap/pcapng capture
+  -f, --file <path>        read an IPFIX file (RFC 5655), - for stdin
+
+options:
+  -o, --output <format>    tree (default), json or csv
+  -p, --port <port>        capture destination port, repeatable (default 4739)
+      --exporter <ip>      only show messages from this exporter, not with -f
+      --domain <id>        only show this observation domain
+      --template <id>      only show this template and its records
+      --strict             reject messages violating RFC 7011";
+
+enum Input {
+    Udp(SocketAddr),
+    Pcap(String),
+    File(String),
+}
+
+enum Output {
+    Tree,
+    Json,
+    Csv,
+}
+
+struct Options {
+    input: Input,
+    output: Output,
+    ports: Vec<u16>,
+    exporter: Option<IpAddr>,
+    domain: Option<u32>,
+    template: Option<u16>,
+    strict: bool,
+}
+
+struct Printer<'p, W: Write> {
+    opts: Options,
+    out: W,
+    sessions: state::Sessions<Option<SocketAddr>>,
+    // keeps the current section across messages, written out after each
+    csv: csv::Writer<'p, Vec<u8>>,
+}
+
+fn main() {
+    let opts = match parse_args(env::args().skip(1).collect()) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(opts) {
+        eprintln!("ipfixdump: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options> {
+    let mut input = None;
+    let mut output = Output::Tree;
+    let mut ports = Vec::new();
+    let mut exporter = None;
+    let mut domain = None;
+    let mut template = None;
+    let mut strict = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-u" | "--udp" => {
+                let addr = value()?;
+                input = Some(Input::Udp(
+                    addr.parse()
+                        .map_err(|_| anyhow!("invalid address {}", addr))?,
+                ))
+            }
+            "-r" | "--pcap" => input = Some(Input::Pcap(value()?)),
+            "-f" | "--file" => input = Some(Input::File(value()?)),
+            "-o" | "--output" => {
+                output = match value()?.as_str() {
+                    "tree" => Output::Tree,
+                    "json" => Output::Json,
+                    "csv" => Output::Csv,
+                    other => return Err(anyhow!("unknown output format {}", other)),
+                }
+            }
+            "-p" | "--port" => ports.push(parse_number(&value()?)?),
+            "--exporter" => {
+                let ip = value()?;
+                exporter = Some(ip.parse().map_err(|_| anyhow!("invalid address {}", ip))?)
+            }
+            "--domain" => domain = Some(parse_number(&value()?)?),
+            "--template" => template = Some(parse_number(&value()?)?),
+            "--strict" => strict = true,
+            other => return Err(anyhow!("unknown argument {}", other)),
+        }
+    }
+
+    let input = input.ok_or_else(|| anyhow!("no input given"))?;
+    // IPFIX files don't record the exporter of their messages
+    if let (Some(_), &Input::File(_)) = (exporter, &input) {
+        return Err(anyhow!("--exporter can't filter an IPFIX file"));
+    }
+    if ports.is_empty() {
+        ports.push(pcap::IPFIX_PORT);
+    }
+    Ok(Options {
+        input,
+        output,
+        ports,
+        exporter,
+        domain,
+        template,
+        strict,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T> {
+    s.parse().map_err(|_| anyhow!("invalid number {}", s))
+}
+
+fn run(opts: Options) -> Result<()> {
+    let parser = if opts.strict {
+        parser::Parser::with_mode(parser::Mode::Strict)
+    } else {
+        parser::Parser::new()
+    };
+    let stdout = io::stdout();
+    let mut printer = Printer {
+        opts,
+        out: BufWriter::new(stdout.lock()),
+        sessions: state::Sessions::new(),
+        csv: csv::Writer::new(&parser, Vec::new()),
+    };
+
+    match printer.opts.input {
+        Input::Udp(addr) => {
+            let socket = UdpSocket::bind(addr)?;
+            let mut buf = vec![0u8; 65535];
+            loop {
+                let (n, exporter) = socket.recv_from(&mut buf)?;
+                printer.message(&parser, Some(exporter), &buf[..n])?;
+                printer.out.flush()?;
+            }
+        }
+        Input::Pcap(ref path) => {
+            let capture = BufReader::new(File::open(path)?);
+            let reader = pcap::Reader::with_ports(capture, &printer.opts.ports)?;
+            for datagram in reader {
+                let datagram = datagram?;
+                printer.message(&parser, Some(datagram.source), &datagram.payload)?;
+            }
+        }
+        Input::File(ref path) => {
+            let input: Box<dyn io::Read> = if path == "-" {
+                Box::new(io::stdin())
+            } else {
+                Box::new(BufReader::new(File::open(path)?))
+            };
+            let mut reader = file::Reader::new(input);
+            while let Some(buf) = reader.read_raw()? {
+                let buf = buf.to_vec();
+                printer.message(&parser, None, &buf)?;
+            }
+            if reader.is_truncated() {
+                eprintln!("ipfixdump: input ends with a truncated message");
+            }
+        }
+    }
+    printer.out.flush()?;
+    Ok(())
+}
+
+impl<'p, W: Write> Printer<'p, W> {
+    fn message(
+        &mut self,
+        parser: &parser::Parser,
+        exporter: Option<SocketAddr>,
+        buf: &[u8],
+    ) -> Result<()> {
+        if let Some(ip) = self.opts.exporter {
+            if exporter.map(|e| e.ip()) != Some(ip) {
+                return Ok(());
+            }
+        }
+
+        let msg = {
+            let state = self.sessions.get_or_insert(exporter)?;
+            match parser.parse_message(state, buf) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    eprintln!("ipfixdump: {}", e);
+                    return Ok(());
+                }
+            }
+        };
+        if let Some(domain) = self.opts.domain {
+            if msg.observation_domain_id != domain {
+                return Ok(());
+            }
+        }
+
+        match self.opts.output {
+            Output::Tree => self.tree(parser, exporter, &msg),
+            Output::Json => self.json(exporter, &msg),
+            Output::Csv => self.csv(exporter, &msg),
+        }
+    }
+
+    fn tree(
+        &mut self,
+        parser: &parser::Parser,
+        exporter: Option<SocketAddr>,
+        msg: &parser::Message,
+    ) -> Result<()> {
+        let filter = self.opts.template;
+        writeln!(
+            self.out,
+            "message exporter={} length={} export_time={} sequence={} domain={}",
+            exporter.map_or("-".to_string(), |e| e.to_string()),
+            msg.length,
+            msg.export_time,
+            msg.sequence_number,
+            msg.observation_domain_id
+        )?;
+
+        for set in &msg.sets {
+            match set.stype {
+                parser::SetType::Template => {
+                    let (_, templates) = parser::Template::parse_many(set.buf)
+                        .map_err(|e| anyhow!("failed parsing templates : {}", e))?;
+                    for t in templates
+                        .iter()
+                        .filter(|t| wanted(filter, t.header.template_id))
+                    {
+                        writeln!(
+                            self.out,
+                            "  template id={} fields={}",
+                            t.header.template_id, t.header.field_count
+                        )?;
+                        self.fields(parser, &t.field_specifiers)?;
+                    }
+                }
+                parser::SetType::OptionTemplate => {
+                    let (_, templates) = parser::OptionsTemplate::parse_many(set.buf)
+                        .map_err(|e| anyhow!("failed parsing options templates : {}", e))?;
+                    for t in templates.iter().filter(|t| wanted(filter, t.header.id)) {
+                        writeln!(
+                            self.out,
+                            "  options template id={} fields={} scope fields={}",
+                            t.header.id, t.header.field_count, t.header.scope_field_count
+                        )?;
+                        self.fields(parser, &t.field_specifiers)?;
+                    }
+                }
+                parser::SetType::DataSet => {
+                    if !wanted(filter, set.hdr.set_id) {
+                        continue;
+                    }
+                    if set.data.is_empty() {
+                        writeln!(self.out, "  data set id={} (no template)", set.hdr.set_id)?;
+                    }
+                    for ds in &set.data {
+                        for record in &ds.records {
+                            writeln!(
+                                self.out,
+                                "  {} id={}",
+                                if ds.is_options {
+                                    "options record"
+                                } else {
+                                    "record"
+                                },
+                                ds.header.set_id
+                            )?;
+                            let mut values = record
+                                .values
+                                .iter()
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect::<Vec<(String, String)>>();
+                            values.sort();
+                            for (k, v) in values {
+                                writeln!(self.out, "    {} = {}", k, v)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fields(&mut self, parser: &parser::Parser, fields: &[parser::FieldSpecifier]) -> Result<()> {
+        for fs in fields {
+            let pen = fs.enterprise_number.unwrap_or(0);
+            writeln!(
+                self.out,
+                "    {} ie={} pen={} length={}",
+                parser.get_field_name(pen, fs.ident).unwrap_or("unknown"),
+                fs.ident,
+                pen,
+                fs.field_length
+            )?;
+        }
+        Ok(())
+    }
+
+    fn json(&mut self, exporter: Option<SocketAddr>, msg: &parser::Message) -> Result<()> {
+        let filter = self.opts.template;
+        let options = jsonl::Options {
+            metadata: true,
+            ..Default::default()
+        };
+        let mut writer = jsonl::Writer::new(&mut self.out, options);
+        for set in &msg.sets {
+            for ds in set
+                .data
+                .iter()
+                .filter(|ds| wanted(filter, ds.header.set_id))
+            {
+                let meta = jsonl::Metadata {
+                    exporter,
+                    observation_domain_id: msg.observation_domain_id,
+                    template_id: ds.header.set_id,
+                    export_time: msg.export_time,
+                };
+                for record in &ds.records {
+                    writer.write_record(&meta, record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn csv(&mut self, exporter: Option<SocketAddr>, msg: &parser::Message) -> Result<()> {
+        let filter = self.opts.template;
+        let state = self.sessions.get_or_insert(exporter)?;
+        for set in &msg.sets {
+            if set.stype != parser::SetType::DataSet || !wanted(filter, set.hdr.set_id) {
+                continue;
+            }
+            if let Some(fields) =
+                state.get_domain_field_specifiers(msg.observation_domain_id, &set.hdr.set_id)
+            {
+                let meta = csv::Metadata {
+                    exporter,
+                    export_time: msg.export_time,
+                    observation_domain_id: msg.observation_domain_id,
+                    template_id: set.hdr.set_id,
+                };
+                self.csv.write_set(&meta, fields, set.buf)?;
+            }
+        }
+        self.out.write_all(self.csv.get_ref())?;
+        self.csv.get_mut().clear();
+        Ok(())
+    }
+}
+
+// whether the template id passes the --template filter
+fn wanted(filter: Option<u16>, template_id: u16) -> bool {
+    filter.is_none() || filter == Some(template_id)
+}
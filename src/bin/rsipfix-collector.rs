@@ -0,0 +1,68 @@
+extern crate rsipfix;
+
+use rsipfix::{collector, parser};
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::sync::Arc;
+
+fn usage() -> ! {
+    eprintln!("usage: rsipfix-collector [listen address, default 0.0.0.0:4739] [workers]");
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() > 2 || args.iter().any(|a| a == "-h" || a == "--help") {
+        usage();
+    }
+
+    let mut config = collector::Config::default();
+    if let Some(listen) = args.first() {
+        config.listen = listen.parse().unwrap_or_else(|_| usage());
+    }
+    if let Some(workers) = args.get(1) {
+        config.workers = workers.parse().unwrap_or_else(|_| usage());
+    }
+
+    let collector = match collector::Collector::bind(config) {
+        Ok(collector) => collector,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let (handle, records) = match collector.spawn_channel(Arc::new(parser::Parser::new()), 4096) {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    // e.g. RSIPFIX_METRICS=127.0.0.1:9464 to be scraped on /metrics
+    #[cfg(feature = "metrics")]
+    let _metrics = match env::var("RSIPFIX_METRICS") {
+        Ok(listen) => {
+            let listen = listen.parse().unwrap_or_else(|_| usage());
+            match handle.serve_metrics(listen) {
+                Ok(server) => Some(server),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Err(_) => None,
+    };
+
+    // one JSON record per line
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for record in records {
+        if writeln!(out, "{}", record.json).is_err() {
+            break;
+        }
+    }
+    drop(handle);
+}
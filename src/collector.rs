@@ -0,0 +1,584 @@
+use anyhow::{anyhow, Result};
+#[cfg(feature = "metrics")]
+use metrics;
+use parser;
+use policy;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet, FxHasher};
+use state;
+use stats;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// callback invoked by the workers for every decoded message
+pub type Handler = Arc<dyn Fn(SocketAddr, &parser::Message) + Send + Sync>;
+
+/// collector configuration
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// address to listen on
+    pub listen: SocketAddr,
+    /// number of parsing threads, exporters are pinned to a worker by their address
+    pub workers: usize,
+    /// datagrams queued per worker before new ones are dropped
+    pub queue_size: usize,
+    /// largest datagram accepted
+    pub max_datagram_size: usize,
+    /// exporters and observation domains accepted, and templates pinned per exporter
+    pub policy: policy::Policy,
+    /// resources each exporter's templates can take up
+    pub limits: state::Limits,
+    /// exporters whose templates are kept at once, datagrams of exporters
+    /// over the limit are dropped and counted in `limit_errors`
+    pub max_sessions: usize,
+    /// approximate memory the templates of all exporters can take up together
+    pub max_memory: usize,
+    /// file the templates are restored from when the collector starts and
+    /// saved to while it runs and when it shuts down, so exporters decode
+    /// right after a restart
+    pub state_file: Option<PathBuf>,
+    /// how often the templates are saved to `state_file` while running
+    pub save_interval: Duration,
+    /// how often the workers publish the statistics of their exporters
+    pub stats_interval: Duration,
+    /// exporters that sent nothing for this long lose their session, along
+    /// with its templates and statistics
+    pub idle_timeout: Duration,
+}
+
+/// a decoded data record along with where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub exporter: SocketAddr,
+    pub observation_domain_id: u32,
+    pub export_time: u32,
+    pub template_id: u16,
+    /// the record serialized with `DataRecord::to_json`
+    pub json: String,
+}
+
+/// collector counters, updated by the receive loop and workers
+#[derive(Debug, Default)]
+pub struct Counters {
+    received: AtomicU64,
+    dropped: AtomicU64,
+    parse_errors: AtomicU64,
+    messages: AtomicU64,
+    rejected: AtomicU64,
+    refused_templates: AtomicU64,
+    limit_errors: AtomicU64,
+    restore_errors: AtomicU64,
+    save_errors: AtomicU64,
+}
+
+/// point in time copy of `Counters`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CountersSnapshot {
+    /// datagrams received on the socket
+    pub received: u64,
+    /// datagrams dropped because the worker queue was full
+    pub dropped: u64,
+    /// datagrams that couldn't be parsed as a message
+    pub parse_errors: u64,
+    /// messages handed to the handler
+    pub messages: u64,
+    /// datagrams rejected by the policy
+    pub rejected: u64,
+    /// template definitions refused because their id is pinned
+    pub refused_templates: u64,
+    /// datagrams dropped because an exporter went over its limits, or
+    /// the collector over its sessions or memory
+    pub limit_errors: u64,
+    /// templates of the state file that couldn't be restored, refused or
+    /// over the limits
+    pub restore_errors: u64,
+    /// failed attempts to save the state file while running
+    pub save_errors: u64,
+}
+
+// statistics of every exporter with a session, published by the workers
+type ExporterStats = Arc<Mutex<HashMap<SocketAddr, stats::Stats>>>;
+
+// templates of every worker, `None` until the worker published them
+type Snapshots = Arc<Mutex<Vec<Option<Vec<(SocketAddr, state::StateSnapshot)>>>>>;
+
+// how often idle workers wake up to publish their templates and statistics
+const WAKEUP: Duration = Duration::from_millis(100);
+
+/// Collector listens for IPFIX over UDP and parses messages on worker threads,
+/// keeping templates per exporter address.
+pub struct Collector {
+    socket: UdpSocket,
+    config: Config,
+    counters: Arc<Counters>,
+    stats: ExporterStats,
+    shutdown: Arc<AtomicBool>,
+    // templates loaded from the state file
+    restored: Vec<(SocketAddr, state::StateSnapshot)>,
+}
+
+/// handle to a collector running in the background
+pub struct CollectorHandle {
+    local_addr: SocketAddr,
+    counters: Arc<Counters>,
+    stats: ExporterStats,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 4739)),
+            workers: 4,
+            queue_size: 1024,
+            max_datagram_size: 65535,
+            policy: policy::Policy::default(),
+            limits: state::Limits::default(),
+            max_sessions: 65536,
+            max_memory: 1 << 30,
+            state_file: None,
+            save_interval: Duration::from_secs(60),
+            stats_interval: Duration::from_secs(1),
+            idle_timeout: Duration::from_secs(1800),
+        }
+    }
+}
+
+impl Counters {
+    pub fn snapshot(&self) -> CountersSnapshot {
+        CountersSnapshot {
+            received: self.received.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+            messages: self.messages.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            refused_templates: self.refused_templates.load(Ordering::Relaxed),
+            limit_errors: self.limit_errors.load(Ordering::Relaxed),
+            restore_errors: self.restore_errors.load(Ordering::Relaxed),
+            save_errors: self.save_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Collector {
+    /// bind the collector socket
+    pub fn bind(config: Config) -> Result<Self> {
+        if config.workers == 0 {
+            return Err(anyhow!("collector needs at least one worker"));
+        }
+        let socket = UdpSocket::bind(config.listen)
+            .map_err(|e| anyhow!("failed to bind {} : {}", config.listen, e))?;
+        // wake up periodically to notice shutdown
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        let restored = match config.state_file {
+            Some(ref path) if path.exists() => load_state(path)?,
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            socket,
+            config,
+            counters: Arc::new(Counters::default()),
+            stats: ExporterStats::default(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            restored,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn counters(&self) -> Arc<Counters> {
+        self.counters.clone()
+    }
+
+    /// receive and parse messages until shut down, blocking the calling thread
+    pub fn run(mut self, parser: Arc<parser::Parser>, handler: Handler) -> Result<()> {
+        let mut queues = Vec::with_capacity(self.config.workers);
+        let mut workers = Vec::with_capacity(self.config.workers);
+        let policy = Arc::new(self.config.policy.clone());
+        let limits = self.config.limits;
+        let session_policy = policy.clone();
+        let new_state = move || {
+            let mut state = session_policy.new_state();
+            state.set_limits(limits);
+            state
+        };
+
+        // the workers share a budget, restored templates go to the worker
+        // their exporter is pinned to
+        let budget = Arc::new(state::Budget::new(
+            self.config.max_sessions,
+            self.config.max_memory,
+        ));
+        let mut restored = vec![Vec::new(); self.config.workers];
+        for (exporter, snapshot) in self.restored.drain(..) {
+            restored[worker_index(&exporter, self.config.workers)].push((exporter, snapshot));
+        }
+
+        let save_interval = self
+            .config
+            .state_file
+            .as_ref()
+            .map(|_| self.config.save_interval);
+        let snapshots: Snapshots = Arc::new(Mutex::new(vec![None; self.config.workers]));
+        for (index, entries) in restored.into_iter().enumerate() {
+            let mut sessions = state::Sessions::with_budget(budget.clone());
+            let failed = sessions.restore(entries, &new_state);
+            self.counters
+                .restore_errors
+                .fetch_add(failed as u64, Ordering::Relaxed);
+
+            let (tx, rx) = mpsc::sync_channel::<(SocketAddr, Vec<u8>)>(self.config.queue_size);
+            let worker = Worker {
+                index,
+                parser: parser.clone(),
+                new_state: new_state.clone(),
+                handler: handler.clone(),
+                counters: self.counters.clone(),
+                stats: self.stats.clone(),
+                snapshots: snapshots.clone(),
+                save_interval,
+                stats_interval: self.config.stats_interval,
+                idle_timeout: self.config.idle_timeout,
+            };
+            queues.push(tx);
+            workers.push(thread::spawn(move || worker.run(sessions, rx)));
+        }
+
+        let mut buf = vec![0u8; self.config.max_datagram_size];
+        let mut saved = Instant::now();
+        let result = loop {
+            if self.shutdown.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            if let Some(ref path) = self.config.state_file {
+                if saved.elapsed() >= self.config.save_interval {
+                    saved = Instant::now();
+                    self.save_published(path, &snapshots);
+                }
+            }
+            let (n, exporter) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut
+                        || e.kind() == io::ErrorKind::Interrupted =>
+                {
+                    continue
+                }
+                Err(e) => break Err(anyhow!("failed to receive : {}", e)),
+            };
+            self.counters.received.fetch_add(1, Ordering::Relaxed);
+            if policy.admit(exporter.ip(), &buf[..n]).is_err() {
+                self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let queue = &queues[worker_index(&exporter, queues.len())];
+            match queue.try_send((exporter, buf[..n].to_vec())) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    break Err(anyhow!("collector worker exited"));
+                }
+            }
+        };
+
+        // closing the queues stops the workers once they're drained
+        drop(queues);
+        let mut sessions = Vec::new();
+        for worker in workers {
+            if let Ok(worker_sessions) = worker.join() {
+                sessions.extend(worker_sessions.snapshot());
+            }
+        }
+        match self.config.state_file {
+            Some(ref path) => result.and(save_state(path, sessions)),
+            None => result,
+        }
+    }
+
+    // save the templates published by the workers, once they all have
+    fn save_published(&self, path: &Path, snapshots: &Snapshots) {
+        let sessions: Option<Vec<_>> = match snapshots.lock() {
+            Ok(snapshots) => snapshots.iter().cloned().collect(),
+            Err(_) => None,
+        };
+        let saved = match sessions {
+            Some(sessions) => save_state(path, sessions.concat()),
+            None => return,
+        };
+        // tried again at the next interval
+        if saved.is_err() {
+            self.counters.save_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// run the collector on a background thread
+    pub fn spawn(self, parser: Arc<parser::Parser>, handler: Handler) -> Result<CollectorHandle> {
+        let local_addr = self.local_addr()?;
+        let counters = self.counters.clone();
+        let stats = self.stats.clone();
+        let shutdown = self.shutdown.clone();
+        let thread = thread::Builder::new()
+            .name("ipfix-collector".to_string())
+            .spawn(move || self.run(parser, handler))?;
+        Ok(CollectorHandle {
+            local_addr,
+            counters,
+            stats,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// run the collector on a background thread delivering data records over a
+    /// channel of `capacity` records. A full channel blocks the workers, once
+    /// their queues fill up datagrams are dropped and counted in `dropped`.
+    pub fn spawn_channel(
+        self,
+        parser: Arc<parser::Parser>,
+        capacity: usize,
+    ) -> Result<(CollectorHandle, Receiver<Record>)> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let handle = self.spawn(parser, record_sender(tx))?;
+        Ok((handle, rx))
+    }
+}
+
+impl CollectorHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn counters(&self) -> CountersSnapshot {
+        self.counters.snapshot()
+    }
+
+    /// statistics of every exporter with a session, as last published by
+    /// the workers
+    pub fn exporter_stats(&self) -> BTreeMap<SocketAddr, stats::Stats> {
+        match self.stats.lock() {
+            Ok(stats) => stats.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    /// serve the counters and exporter statistics for Prometheus on
+    /// `listen`, until the returned server is shut down or dropped.
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(&self, listen: SocketAddr) -> Result<metrics::Server> {
+        let counters = self.counters.clone();
+        let stats = self.stats.clone();
+        metrics::Server::bind(
+            listen,
+            Arc::new(move || {
+                let exporters = match stats.lock() {
+                    Ok(stats) => stats.iter().map(|(k, v)| (*k, v.clone())).collect(),
+                    Err(_) => BTreeMap::new(),
+                };
+                metrics::render(&counters.snapshot(), &exporters)
+            }),
+        )
+    }
+
+    /// stop the collector and wait for it to finish
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.shutdown.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow!("collector thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CollectorHandle {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// handler that serializes every data record and sends it over `tx`,
+/// records are silently discarded once the receiving side is gone.
+pub fn record_sender(tx: SyncSender<Record>) -> Handler {
+    Arc::new(move |exporter: SocketAddr, msg: &parser::Message| {
+        for set in &msg.sets {
+            for ds in &set.data {
+                for record in &ds.records {
+                    let json = match record.to_json() {
+                        Ok(json) => json,
+                        Err(_) => continue,
+                    };
+                    let _ = tx.send(Record {
+                        exporter,
+                        observation_domain_id: msg.observation_domain_id,
+                        export_time: msg.export_time,
+                        template_id: ds.header.set_id,
+                        json,
+                    });
+                }
+            }
+        }
+    })
+}
+
+// a parsing thread and what it shares with the receive loop
+struct Worker<F> {
+    index: usize,
+    parser: Arc<parser::Parser>,
+    new_state: F,
+    handler: Handler,
+    counters: Arc<Counters>,
+    stats: ExporterStats,
+    snapshots: Snapshots,
+    // how often the templates are published for saving, if they're saved
+    save_interval: Option<Duration>,
+    stats_interval: Duration,
+    idle_timeout: Duration,
+}
+
+impl<F: Fn() -> state::State> Worker<F> {
+    // parse the datagrams of the exporters pinned to this worker, returning
+    // their sessions once the queue is closed
+    fn run(
+        self,
+        mut sessions: state::Sessions<SocketAddr>,
+        rx: Receiver<(SocketAddr, Vec<u8>)>,
+    ) -> state::Sessions<SocketAddr> {
+        let mut published: Option<Instant> = None;
+        // exporters whose statistics changed since they were last published
+        let mut changed = HashSet::default();
+        let mut stats_published = Instant::now();
+        loop {
+            if stats_published.elapsed() >= self.stats_interval {
+                changed.extend(sessions.evict_idle(self.idle_timeout));
+                self.publish_stats(&sessions, &mut changed);
+                stats_published = Instant::now();
+            }
+            if let Some(interval) = self.save_interval {
+                let due = match published {
+                    Some(at) => at.elapsed() >= interval,
+                    None => true,
+                };
+                if due {
+                    self.publish(&sessions);
+                    published = Some(Instant::now());
+                }
+            }
+            match rx.recv_timeout(WAKEUP.min(self.stats_interval)) {
+                Ok((exporter, buf)) => {
+                    self.parse(&mut sessions, exporter, &buf);
+                    changed.insert(exporter);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.publish_stats(&sessions, &mut changed);
+        sessions
+    }
+
+    fn parse(&self, sessions: &mut state::Sessions<SocketAddr>, exporter: SocketAddr, buf: &[u8]) {
+        let counters = &self.counters;
+        let state = match sessions.get_or_insert_with(exporter, &self.new_state) {
+            Ok(state) => state,
+            Err(_) => {
+                counters.limit_errors.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        let refused = state.refused_templates();
+        let parsed = self.parser.parse_message(state, buf);
+        counters
+            .refused_templates
+            .fetch_add(state.refused_templates() - refused, Ordering::Relaxed);
+        match parsed {
+            Ok(msg) => {
+                counters.messages.fetch_add(1, Ordering::Relaxed);
+                (self.handler)(exporter, &msg);
+            }
+            Err(ref e) if e.is::<state::LimitError>() => {
+                counters.limit_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                counters.parse_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // copy the statistics of the exporters that changed, dropping those of
+    // exporters without a session
+    fn publish_stats(
+        &self,
+        sessions: &state::Sessions<SocketAddr>,
+        changed: &mut HashSet<SocketAddr>,
+    ) {
+        let mut stats = match self.stats.lock() {
+            Ok(stats) => stats,
+            Err(_) => return,
+        };
+        for exporter in changed.drain() {
+            match sessions.get(&exporter) {
+                Some(state) => stats
+                    .entry(exporter)
+                    .or_insert_with(stats::Stats::default)
+                    .clone_from(state.get_stats()),
+                None => {
+                    stats.remove(&exporter);
+                }
+            }
+        }
+    }
+
+    // hand the templates to the receive loop, which saves them
+    fn publish(&self, sessions: &state::Sessions<SocketAddr>) {
+        if let Ok(mut snapshots) = self.snapshots.lock() {
+            snapshots[self.index] = Some(sessions.snapshot());
+        }
+    }
+}
+
+// read the sessions saved by `save_state`
+fn load_state(path: &Path) -> Result<Vec<(SocketAddr, state::StateSnapshot)>> {
+    let file = File::open(path)
+        .map_err(|e| anyhow!("failed to open state file {} : {}", path.display(), e))?;
+    state::read_snapshot(BufReader::new(file))
+        .map_err(|e| anyhow!("failed to load state file {} : {}", path.display(), e))
+}
+
+// write to a temporary file first so a crash never leaves a truncated state file
+fn save_state(path: &Path, sessions: Vec<(SocketAddr, state::StateSnapshot)>) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    state::write_snapshot(&mut writer, sessions)?;
+    writer.flush()?;
+    fs::rename(&tmp, path)
+        .map_err(|e| anyhow!("failed to save state file {} : {}", path.display(), e))
+}
+
+// pin an exporter to a worker so its templates live in a single place
+fn worker_index(exporter: &SocketAddr, workers: usize) -> usize {
+    let mut hasher = FxHasher::default();
+    exporter.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
@@ -0,0 +1,349 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{
+    ArrayRef, BinaryBuilder, FixedSizeBinaryBuilder, StringBuilder, TimestampMicrosecondBuilder,
+    TimestampMillisecondBuilder, TimestampNanosecondBuilder, TimestampSecondBuilder, UInt16Builder,
+    UInt32Builder, UInt64Builder, UInt8Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use formatter::{self, be_uint};
+use parser::{self, FieldSpecifier};
+use rustc_hash::FxHashMap as HashMap;
+use state;
+use std::sync::Arc;
+use timestamp;
+
+// timestamps are always UTC
+const TIMEZONE: &str = "UTC";
+
+/// how a field is laid out in its column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    U8,
+    U16,
+    U32,
+    U64,
+    /// the address as a big-endian UInt32
+    Ipv4,
+    /// the 16 address bytes
+    Ipv6,
+    /// the 6 address bytes
+    Mac,
+    String,
+    Bytes,
+    Seconds,
+    Millis,
+    /// NTP timestamps converted to the UNIX epoch
+    Micros,
+    Nanos,
+}
+
+/// Batch is the records of a data set decoded into columns
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub observation_domain_id: u32,
+    pub export_time: u32,
+    pub template_id: u16,
+    pub batch: RecordBatch,
+}
+
+/// Decoder decodes data sets into Arrow record batches, one schema per
+/// template. Fields are appended to their column straight from the set
+/// buffer, no `DataRecord` is built on the way.
+pub struct Decoder<'p> {
+    parser: &'p parser::Parser,
+    // observation domain and template id -> the fields the schema was derived from
+    schemas: HashMap<(u32, u16), Derived>,
+}
+
+// the fields of a template, the kinds of their columns and the schema
+type Derived = (Vec<FieldSpecifier>, Vec<Kind>, SchemaRef);
+
+enum Column {
+    U8(UInt8Builder),
+    U16(UInt16Builder),
+    U32(UInt32Builder),
+    U64(UInt64Builder),
+    FixedSize(FixedSizeBinaryBuilder),
+    String(StringBuilder),
+    Bytes(BinaryBuilder),
+    Seconds(TimestampSecondBuilder),
+    Millis(TimestampMillisecondBuilder),
+    Micros(TimestampMicrosecondBuilder),
+    Nanos(TimestampNanosecondBuilder),
+}
+
+/// derive the schema of a template, one non-nullable column per field
+/// specifier in template order, named by `Parser::field_names`.
+///
+/// Fields of enterprise number 0 get a column of their IANA data type:
+/// unsigned integers as the matching UInt, IPv4 addresses as UInt32, IPv6
+/// and MAC addresses as fixed size binary, strings as Utf8 and timestamps
+/// as UTC timestamps. Everything else, including fields whose length
+/// doesn't fit their type, is kept as Binary.
+pub fn schema(parser: &parser::Parser, fields: &[FieldSpecifier]) -> Schema {
+    let kinds: Vec<Kind> = fields.iter().map(kind).collect();
+    build_schema(parser, fields, &kinds)
+}
+
+fn build_schema(parser: &parser::Parser, fields: &[FieldSpecifier], kinds: &[Kind]) -> Schema {
+    let columns: Vec<Field> = parser
+        .field_names(fields)
+        .into_iter()
+        .zip(kinds)
+        .map(|(name, kind)| Field::new(name, data_type(*kind), false))
+        .collect();
+    Schema::new(columns)
+}
+
+fn kind(fs: &FieldSpecifier) -> Kind {
+    let t = match fs.enterprise_number {
+        None => formatter::default_type(fs.ident),
+        Some(_) => None,
+    };
+    let t = match t {
+        Some(t) => t,
+        None => return Kind::Bytes,
+    };
+    if !t.fits(fs.field_length) {
+        return Kind::Bytes;
+    }
+    match t {
+        formatter::DataType::Unsigned8 => Kind::U8,
+        formatter::DataType::Unsigned16 => Kind::U16,
+        formatter::DataType::Unsigned32 => Kind::U32,
+        formatter::DataType::Unsigned64 => Kind::U64,
+        formatter::DataType::MacAddress => Kind::Mac,
+        formatter::DataType::OctetArray
+        | formatter::DataType::Signed8
+        | formatter::DataType::Signed16
+        | formatter::DataType::Signed32
+        | formatter::DataType::Signed64
+        | formatter::DataType::Float32
+        | formatter::DataType::Float64
+        | formatter::DataType::Boolean
+        | formatter::DataType::BasicList
+        | formatter::DataType::SubTemplateList
+        | formatter::DataType::SubTemplateMultiList => Kind::Bytes,
+        formatter::DataType::String => Kind::String,
+        formatter::DataType::DateTimeSeconds => Kind::Seconds,
+        formatter::DataType::DateTimeMilliseconds => Kind::Millis,
+        formatter::DataType::DateTimeMicroseconds => Kind::Micros,
+        formatter::DataType::DateTimeNanoseconds => Kind::Nanos,
+        formatter::DataType::Ipv4Address => Kind::Ipv4,
+        formatter::DataType::Ipv6Address => Kind::Ipv6,
+    }
+}
+
+fn data_type(kind: Kind) -> DataType {
+    match kind {
+        Kind::U8 => DataType::UInt8,
+        Kind::U16 => DataType::UInt16,
+        Kind::U32 | Kind::Ipv4 => DataType::UInt32,
+        Kind::U64 => DataType::UInt64,
+        Kind::Ipv6 => DataType::FixedSizeBinary(16),
+        Kind::Mac => DataType::FixedSizeBinary(6),
+        Kind::String => DataType::Utf8,
+        Kind::Bytes => DataType::Binary,
+        Kind::Seconds => DataType::Timestamp(TimeUnit::Second, Some(TIMEZONE.into())),
+        Kind::Millis => DataType::Timestamp(TimeUnit::Millisecond, Some(TIMEZONE.into())),
+        Kind::Micros => DataType::Timestamp(TimeUnit::Microsecond, Some(TIMEZONE.into())),
+        Kind::Nanos => DataType::Timestamp(TimeUnit::Nanosecond, Some(TIMEZONE.into())),
+    }
+}
+
+impl Column {
+    fn new(kind: Kind, capacity: usize) -> Self {
+        match kind {
+            Kind::U8 => Column::U8(UInt8Builder::with_capacity(capacity)),
+            Kind::U16 => Column::U16(UInt16Builder::with_capacity(capacity)),
+            Kind::U32 | Kind::Ipv4 => Column::U32(UInt32Builder::with_capacity(capacity)),
+            Kind::U64 => Column::U64(UInt64Builder::with_capacity(capacity)),
+            Kind::Ipv6 => Column::FixedSize(FixedSizeBinaryBuilder::with_capacity(capacity, 16)),
+            Kind::Mac => Column::FixedSize(FixedSizeBinaryBuilder::with_capacity(capacity, 6)),
+            Kind::String => Column::String(StringBuilder::with_capacity(capacity, capacity * 16)),
+            Kind::Bytes => Column::Bytes(BinaryBuilder::with_capacity(capacity, capacity * 16)),
+            Kind::Seconds => Column::Seconds(
+                TimestampSecondBuilder::with_capacity(capacity).with_timezone(TIMEZONE),
+            ),
+            Kind::Millis => Column::Millis(
+                TimestampMillisecondBuilder::with_capacity(capacity).with_timezone(TIMEZONE),
+            ),
+            Kind::Micros => Column::Micros(
+                TimestampMicrosecondBuilder::with_capacity(capacity).with_timezone(TIMEZONE),
+            ),
+            Kind::Nanos => Column::Nanos(
+                TimestampNanosecondBuilder::with_capacity(capacity).with_timezone(TIMEZONE),
+            ),
+        }
+    }
+
+    // the field lengths were checked against the kind by `kind`
+    fn append(&mut self, buf: &[u8]) -> Result<()> {
+        match self {
+            Column::U8(b) => b.append_value(be_uint(buf) as u8),
+            Column::U16(b) => b.append_value(be_uint(buf) as u16),
+            Column::U32(b) => b.append_value(be_uint(buf) as u32),
+            Column::U64(b) => b.append_value(be_uint(buf)),
+            Column::FixedSize(b) => b.append_value(buf)?,
+            Column::String(b) => b.append_value(String::from_utf8_lossy(buf)),
+            Column::Bytes(b) => b.append_value(buf),
+            Column::Seconds(b) => b.append_value(be_uint(buf) as i64),
+            Column::Millis(b) => b.append_value(be_uint(buf) as i64),
+            Column::Micros(b) => b.append_value(timestamp::ntp_to_unix(be_uint(buf), 1_000_000)),
+            Column::Nanos(b) => b.append_value(timestamp::ntp_to_unix(be_uint(buf), 1_000_000_000)),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Column::U8(b) => Arc::new(b.finish()),
+            Column::U16(b) => Arc::new(b.finish()),
+            Column::U32(b) => Arc::new(b.finish()),
+            Column::U64(b) => Arc::new(b.finish()),
+            Column::FixedSize(b) => Arc::new(b.finish()),
+            Column::String(b) => Arc::new(b.finish()),
+            Column::Bytes(b) => Arc::new(b.finish()),
+            Column::Seconds(b) => Arc::new(b.finish()),
+            Column::Millis(b) => Arc::new(b.finish()),
+            Column::Micros(b) => Arc::new(b.finish()),
+            Column::Nanos(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+impl<'p> Decoder<'p> {
+    pub fn new(parser: &'p parser::Parser) -> Self {
+        Self {
+            parser,
+            schemas: HashMap::default(),
+        }
+    }
+
+    /// the schema of the data sets of `template_id` in observation domain
+    /// `domain`, `None` when the template isn't known to `state`
+    pub fn schema(
+        &mut self,
+        state: &state::State,
+        domain: u32,
+        template_id: u16,
+    ) -> Option<SchemaRef> {
+        self.lookup(state, domain, template_id)
+            .map(|(_, _, s)| s.clone())
+    }
+
+    // the fields, column kinds and schema of a template, derived again
+    // when the template was redefined with other fields
+    fn lookup(&mut self, state: &state::State, domain: u32, template_id: u16) -> Option<&Derived> {
+        let key = (domain, template_id);
+        let fields = state.get_domain_field_specifiers(domain, &template_id)?;
+        let stale = match self.schemas.get(&key) {
+            Some((cached, _, _)) => cached[..] != *fields,
+            None => true,
+        };
+        if stale {
+            let kinds: Vec<Kind> = fields.iter().map(kind).collect();
+            let schema = Arc::new(build_schema(self.parser, fields, &kinds));
+            self.schemas.insert(key, (fields.to_vec(), kinds, schema));
+        }
+        self.schemas.get(&key)
+    }
+
+    /// decode the records of a data set of observation domain `domain` with
+    /// its template from `state`, trailing bytes too short for a record are
+    /// taken as padding.
+    pub fn decode_set(
+        &mut self,
+        state: &state::State,
+        domain: u32,
+        set: &parser::Set,
+    ) -> Result<RecordBatch> {
+        let set_id = set.hdr.set_id;
+        if set.stype != parser::SetType::DataSet {
+            return Err(anyhow!("set {} isn't a data set", set_id));
+        }
+        let max_records = state.get_limits().max_records_per_set;
+        let (fields, kinds, schema) = self
+            .lookup(state, domain, set_id)
+            .ok_or_else(|| anyhow!("no template for data set {}", set_id))?;
+        if fields.is_empty() {
+            return Err(anyhow!("template {} has no fields", set_id));
+        }
+
+        // fixed length records tell how many there are up front
+        let record_len: usize = fields.iter().map(|f| f.field_length as usize).sum();
+        let capacity = if fields.iter().any(|f| f.is_variable()) {
+            0
+        } else {
+            (set.buf.len() / record_len.max(1)).min(max_records)
+        };
+        let mut columns: Vec<Column> = kinds.iter().map(|k| Column::new(*k, capacity)).collect();
+        let mut values: Vec<&[u8]> = Vec::with_capacity(fields.len());
+        let mut rest = set.buf;
+        let mut records = 0;
+        while !rest.is_empty() {
+            // take a whole record before appending so columns stay aligned
+            values.clear();
+            let mut input = rest;
+            for fs in fields {
+                match parser::DataSet::take_field(input, fs.field_length) {
+                    Ok((more, value)) => {
+                        values.push(value);
+                        input = more;
+                    }
+                    Err(_) => break,
+                }
+            }
+            if values.len() < fields.len() || input.len() == rest.len() {
+                break;
+            }
+            if records >= max_records {
+                return Err(state::LimitError(format!(
+                    "data set {} has more than {} records",
+                    set_id, max_records
+                ))
+                .into());
+            }
+            for (column, value) in columns.iter_mut().zip(&values) {
+                column.append(value)?;
+            }
+            records += 1;
+            rest = input;
+        }
+
+        let arrays = columns.iter_mut().map(Column::finish).collect();
+        Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+    }
+
+    /// decode the data sets of a message, templates are learned into
+    /// `state` in order as `Parser::parse_message` would. Data sets of
+    /// unknown templates are skipped.
+    pub fn decode_message(&mut self, state: &mut state::State, input: &[u8]) -> Result<Vec<Batch>> {
+        let (body, msg) = parser::Message::parse(input)
+            .map_err(|e| anyhow!("failed while parsing ipfix header : {:?}", e))?;
+        let (_, mut sets) = parser::Set::parse_many(body)
+            .map_err(|e| anyhow!("failed while extracting sets {:?}", e))?;
+
+        let domain = msg.observation_domain_id;
+        let mut batches = Vec::new();
+        for set in &mut sets {
+            let result = match set.stype {
+                parser::SetType::DataSet => self.decode_set(state, domain, set).map(|batch| {
+                    batches.push(Batch {
+                        observation_domain_id: domain,
+                        export_time: msg.export_time,
+                        template_id: set.hdr.set_id,
+                        batch,
+                    })
+                }),
+                _ => set.process_set_body(&self.parser.pen_formatter, state, domain),
+            };
+            match result {
+                Err(err) if err.is::<state::LimitError>() => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(batches)
+    }
+}
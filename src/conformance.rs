@@ -0,0 +1,370 @@
+use anyhow::Result;
+use formatter;
+use parser;
+use pcap;
+use serde::Serialize;
+use state;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::net::SocketAddr;
+
+/// RFC 7011 10.3.6 suggests refreshing templates every 10 minutes over UDP
+pub const DEFAULT_TEMPLATE_REFRESH: u32 = 600;
+
+/// kinds of deviation from the protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Deviation {
+    /// data set received before its template
+    MissingTemplate,
+    /// template not refreshed within the refresh timeout
+    MissingTemplateRefresh,
+    /// sequence number doesn't count the data records exported before
+    SequenceNumber,
+    /// field length doesn't fit the IANA type of the information element
+    LengthMismatch,
+    /// field sent in fewer bytes than its IANA type
+    ReducedSize,
+    /// information element unknown to the parser
+    UnknownField,
+    /// padding that isn't zeros or is as long as a record
+    Padding,
+    /// record cut short by the end of its data set
+    TruncatedRecord,
+    /// any other RFC 7011 violation found by the strict parser
+    Violation,
+}
+
+/// a deviation along with how often it was seen
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub kind: Deviation,
+    pub count: u64,
+    /// description of the first occurrence
+    pub detail: String,
+}
+
+/// conformance of a single template
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateReport {
+    pub observation_domain_id: u32,
+    pub template_id: u16,
+    /// times the template was defined or refreshed
+    pub definitions: u64,
+    pub data_sets: u64,
+    pub findings: Vec<Finding>,
+}
+
+/// conformance of a single exporter
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExporterReport {
+    pub exporter: SocketAddr,
+    pub messages: u64,
+    /// findings that aren't tied to a template
+    pub findings: Vec<Finding>,
+    pub templates: Vec<TemplateReport>,
+}
+
+/// Report summarizes the deviations of every exporter checked
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub exporters: Vec<ExporterReport>,
+}
+
+/// Checker looks for protocol deviations in the messages of exporters,
+/// messages are decoded leniently so one deviation doesn't hide the rest.
+pub struct Checker {
+    parser: parser::Parser,
+    template_refresh: Option<u32>,
+    exporters: BTreeMap<SocketAddr, ExporterCheck>,
+}
+
+// findings keyed by kind and a caller chosen key
+#[derive(Default)]
+struct Findings(BTreeMap<(Deviation, String), Finding>);
+
+struct ExporterCheck {
+    state: state::State,
+    messages: u64,
+    findings: Findings,
+    domains: BTreeMap<u32, DomainCheck>,
+}
+
+#[derive(Default)]
+struct DomainCheck {
+    // unknown until a message with only decodable data sets was seen
+    next_sequence: Option<u32>,
+    templates: BTreeMap<u16, TemplateCheck>,
+}
+
+#[derive(Default)]
+struct TemplateCheck {
+    definitions: u64,
+    data_sets: u64,
+    last_defined: Option<u32>,
+    fields: Option<Vec<parser::FieldSpecifier>>,
+    findings: Findings,
+}
+
+impl Report {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self)
+    }
+}
+
+impl Findings {
+    fn add(&mut self, kind: Deviation, key: String, detail: String) {
+        self.0
+            .entry((kind, key))
+            .or_insert_with(|| Finding {
+                kind,
+                count: 0,
+                detail,
+            })
+            .count += 1;
+    }
+
+    fn to_vec(&self) -> Vec<Finding> {
+        self.0.values().cloned().collect()
+    }
+}
+
+impl Checker {
+    /// create a checker decoding with `parser`, which keeps its custom fields
+    pub fn new(mut parser: parser::Parser) -> Self {
+        parser.set_mode(parser::Mode::Lenient);
+        Self {
+            parser,
+            template_refresh: Some(DEFAULT_TEMPLATE_REFRESH),
+            exporters: BTreeMap::new(),
+        }
+    }
+
+    /// seconds after which a template must have been refreshed, `None`
+    /// disables the check for transports without refreshes such as TCP.
+    pub fn set_template_refresh(&mut self, seconds: Option<u32>) {
+        self.template_refresh = seconds;
+    }
+
+    /// check a message from `exporter`, errors are only returned for
+    /// messages that can't be decoded at all.
+    pub fn check(&mut self, exporter: SocketAddr, buf: &[u8]) -> Result<()> {
+        let Checker {
+            ref parser,
+            template_refresh,
+            ref mut exporters,
+        } = *self;
+        let ec = exporters.entry(exporter).or_insert_with(|| ExporterCheck {
+            state: state::State::new(),
+            messages: 0,
+            findings: Findings::default(),
+            domains: BTreeMap::new(),
+        });
+
+        let msg = parser.parse_message(&mut ec.state, buf)?;
+        // the strict checks stop at the first violation of the message, they
+        // run on the decoded sets so the templates are learned once, leniently
+        if let Err(e) = parser.check_violations(&ec.state, &msg, buf) {
+            if let Some(v) = e.downcast_ref::<parser::Violation>() {
+                add_violation(&mut ec.findings, v);
+            }
+        }
+        ec.messages += 1;
+        let domain = ec.domains.entry(msg.observation_domain_id).or_default();
+
+        let mut records = Some(0u32);
+        for set in &msg.sets {
+            match set.stype {
+                parser::SetType::Template => {
+                    if let Ok((_, templates)) = parser::Template::parse_many(set.buf) {
+                        for t in templates {
+                            domain
+                                .templates
+                                .entry(t.header.template_id)
+                                .or_default()
+                                .defined(parser, msg.export_time, t.field_specifiers);
+                        }
+                    }
+                }
+                parser::SetType::OptionTemplate => {
+                    if let Ok((_, templates)) = parser::OptionsTemplate::parse_many(set.buf) {
+                        for t in templates {
+                            domain.templates.entry(t.header.id).or_default().defined(
+                                parser,
+                                msg.export_time,
+                                t.field_specifiers,
+                            );
+                        }
+                    }
+                }
+                parser::SetType::DataSet => {
+                    let tc = domain.templates.entry(set.hdr.set_id).or_default();
+                    tc.data_sets += 1;
+                    if set.data.is_empty() {
+                        records = None;
+                        tc.findings.add(
+                            Deviation::MissingTemplate,
+                            String::new(),
+                            format!("data set {} received before its template", set.hdr.set_id),
+                        );
+                        continue;
+                    }
+                    records = records.map(|n| {
+                        n.wrapping_add(set.data.iter().map(|ds| ds.records.len() as u32).sum())
+                    });
+                    if let (Some(refresh), Some(defined)) = (template_refresh, tc.last_defined) {
+                        let age = msg.export_time.saturating_sub(defined);
+                        if age > refresh {
+                            tc.findings.add(
+                                Deviation::MissingTemplateRefresh,
+                                String::new(),
+                                format!(
+                                    "template {} used {} seconds after it was last sent",
+                                    set.hdr.set_id, age
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // the sequence number counts the data records sent before the message
+        if let Some(expected) = domain.next_sequence {
+            if expected != msg.sequence_number {
+                ec.findings.add(
+                    Deviation::SequenceNumber,
+                    msg.observation_domain_id.to_string(),
+                    format!(
+                        "observation domain {} sent sequence number {}, expected {}",
+                        msg.observation_domain_id, msg.sequence_number, expected
+                    ),
+                );
+            }
+        }
+        domain.next_sequence = records.map(|n| msg.sequence_number.wrapping_add(n));
+        Ok(())
+    }
+
+    /// check every IPFIX message of a pcap/pcapng capture
+    pub fn check_capture<R: Read>(&mut self, capture: pcap::Reader<R>) -> Result<()> {
+        for datagram in capture {
+            let datagram = datagram?;
+            // undecodable messages are reported by the strict parser
+            let _ = self.check(datagram.source, &datagram.payload);
+        }
+        Ok(())
+    }
+
+    /// summary of everything checked so far
+    pub fn report(&self) -> Report {
+        let exporters = self
+            .exporters
+            .iter()
+            .map(|(exporter, ec)| ExporterReport {
+                exporter: *exporter,
+                messages: ec.messages,
+                findings: ec.findings.to_vec(),
+                templates: ec
+                    .domains
+                    .iter()
+                    .flat_map(|(domain, dc)| {
+                        dc.templates.iter().map(move |(id, tc)| TemplateReport {
+                            observation_domain_id: *domain,
+                            template_id: *id,
+                            definitions: tc.definitions,
+                            data_sets: tc.data_sets,
+                            findings: tc.findings.to_vec(),
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+        Report { exporters }
+    }
+}
+
+impl TemplateCheck {
+    // a (re)definition, fields are checked when the definition changes
+    fn defined(
+        &mut self,
+        parser: &parser::Parser,
+        export_time: u32,
+        fields: Vec<parser::FieldSpecifier>,
+    ) {
+        self.definitions += 1;
+        self.last_defined = Some(export_time);
+        if self.fields.as_ref() == Some(&fields) {
+            return;
+        }
+        for fs in &fields {
+            check_field(&mut self.findings, parser, fs);
+        }
+        self.fields = Some(fields);
+    }
+}
+
+// compare a field specifier with what's known about its information element
+fn check_field(findings: &mut Findings, parser: &parser::Parser, fs: &parser::FieldSpecifier) {
+    let pen = fs.enterprise_number.unwrap_or(0);
+    let key = format!("{}/{}", pen, fs.ident);
+    let name = match parser.get_field_name(pen, fs.ident) {
+        Some(name) => name,
+        None => {
+            findings.add(
+                Deviation::UnknownField,
+                key,
+                format!("field {} of enterprise {} is unknown", fs.ident, pen),
+            );
+            return;
+        }
+    };
+    let data_type = match (pen, formatter::default_type(fs.ident)) {
+        (0, Some(data_type)) => data_type,
+        _ => return,
+    };
+    let expected = match data_type.length() {
+        Some(length) => length,
+        None => return,
+    };
+
+    if fs.field_length == expected {
+        return;
+    }
+    if fs.field_length < expected
+        && data_type.allows_reduced_size()
+        && [1, 2, 4].contains(&fs.field_length)
+    {
+        findings.add(
+            Deviation::ReducedSize,
+            key,
+            format!(
+                "{} ({}) is sent in {} bytes instead of {}",
+                name, fs.ident, fs.field_length, expected
+            ),
+        );
+    } else {
+        findings.add(
+            Deviation::LengthMismatch,
+            key,
+            format!(
+                "{} ({}) has length {}, its {:?} type takes {}",
+                name, fs.ident, fs.field_length, data_type, expected
+            ),
+        );
+    }
+}
+
+fn add_violation(findings: &mut Findings, v: &parser::Violation) {
+    let kind = match *v {
+        parser::Violation::Padding { .. } => Deviation::Padding,
+        parser::Violation::TruncatedRecord { .. } => Deviation::TruncatedRecord,
+        parser::Violation::UnknownPen { .. } => Deviation::UnknownField,
+        _ => Deviation::Violation,
+    };
+    findings.add(kind, v.to_string(), v.to_string());
+}
@@ -0,0 +1,387 @@
+use anyhow::Result;
+use formatter::{self, be_uint, DataType};
+use parser::{self, DataRecordValue, FieldSpecifier};
+use rustc_hash::FxHashMap as HashMap;
+use state;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use timestamp;
+
+/// how many files `Files` keeps open by default
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// the columns written before the fields of a template
+pub const METADATA_COLUMNS: [&str; 4] = [
+    "exporter",
+    "exportTime",
+    "observationDomainId",
+    "templateId",
+];
+
+/// where the records of a data set came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub exporter: Option<SocketAddr>,
+    pub export_time: u32,
+    pub observation_domain_id: u32,
+    pub template_id: u16,
+}
+
+/// Writer writes data records as CSV, one section per template. A section
+/// starts with a header of the template's fields in template order, named
+/// by `Parser::field_names`, and is separated from the previous one by an
+/// empty line. Values are rendered from the field bytes by their IANA data
+/// type: addresses in their usual notation, timestamps in RFC 3339,
+/// integers and floats in decimal, booleans as `true` or `false` and
+/// anything else in hex.
+pub struct Writer<'p, W: Write> {
+    parser: &'p parser::Parser,
+    out: W,
+    // the section records are currently written to
+    section: Option<Section>,
+}
+
+/// Files writes a CSV file per observation domain and template id, as
+/// `<domain>-<template>.csv` in a directory. A template redefined with
+/// other fields goes to `<domain>-<template>-2.csv` and so on, so every
+/// file has a single header. At most `max_open` files are kept open, the
+/// least recently written one is closed and later appended to.
+pub struct Files<'p> {
+    parser: &'p parser::Parser,
+    dir: PathBuf,
+    max_open: usize,
+    // the file of every observation domain, template id and columns
+    names: HashMap<Section, PathBuf>,
+    // open writers and when they were last written to
+    open: HashMap<Section, (Writer<'p, BufWriter<File>>, u64)>,
+    writes: u64,
+    paths: Vec<PathBuf>,
+}
+
+// observation domain, template id and columns
+type Section = (u32, u16, Vec<String>);
+
+impl<'p, W: Write> Writer<'p, W> {
+    pub fn new(parser: &'p parser::Parser, out: W) -> Self {
+        Self {
+            parser,
+            out,
+            section: None,
+        }
+    }
+
+    /// write the data records of a message with the templates of `state`,
+    /// data sets of unknown templates are skipped
+    pub fn write_message(
+        &mut self,
+        state: &state::State,
+        exporter: Option<SocketAddr>,
+        msg: &parser::Message,
+    ) -> Result<()> {
+        for set in &msg.sets {
+            if set.stype != parser::SetType::DataSet {
+                continue;
+            }
+            if let Some(fields) =
+                state.get_domain_field_specifiers(msg.observation_domain_id, &set.hdr.set_id)
+            {
+                let meta = Metadata {
+                    exporter,
+                    export_time: msg.export_time,
+                    observation_domain_id: msg.observation_domain_id,
+                    template_id: set.hdr.set_id,
+                };
+                self.write_set(&meta, fields, set.buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// write the records of a data set body decoded with `fields`
+    pub fn write_set(
+        &mut self,
+        meta: &Metadata,
+        fields: &[FieldSpecifier],
+        buf: &[u8],
+    ) -> Result<()> {
+        let columns = self.parser.field_names(fields);
+        let section = (meta.observation_domain_id, meta.template_id, columns);
+        if self.section.as_ref() != Some(&section) {
+            if self.section.is_some() {
+                writeln!(self.out)?;
+            }
+            let header: Vec<&str> = METADATA_COLUMNS
+                .iter()
+                .cloned()
+                .chain(section.2.iter().map(|c| c.as_str()))
+                .collect();
+            write_row(&mut self.out, &header)?;
+            self.section = Some(section);
+        }
+
+        let mut row: Vec<String> = vec![
+            meta.exporter.map_or(String::new(), |e| e.to_string()),
+            timestamp::to_rfc3339(i64::from(meta.export_time), 0, 0),
+            meta.observation_domain_id.to_string(),
+            meta.template_id.to_string(),
+        ];
+        let mut rest = buf;
+        'records: while !rest.is_empty() {
+            // a record too short for the template is padding
+            row.truncate(METADATA_COLUMNS.len());
+            let mut input = rest;
+            for fs in fields {
+                match parser::DataSet::take_field(input, fs.field_length) {
+                    Ok((more, value)) => {
+                        row.push(self.render(fs, value));
+                        input = more;
+                    }
+                    Err(_) => break 'records,
+                }
+            }
+            if input.len() == rest.len() {
+                break;
+            }
+            write_row(&mut self.out, &row)?;
+            rest = input;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // the value of a field in human form
+    fn render(&self, fs: &FieldSpecifier, buf: &[u8]) -> String {
+        let t = match fs.enterprise_number {
+            None => formatter::default_type(fs.ident).filter(|t| t.fits(fs.field_length)),
+            Some(_) => None,
+        };
+        match t {
+            Some(DataType::Unsigned8)
+            | Some(DataType::Unsigned16)
+            | Some(DataType::Unsigned32)
+            | Some(DataType::Unsigned64) => be_uint(buf).to_string(),
+            Some(DataType::Signed8)
+            | Some(DataType::Signed16)
+            | Some(DataType::Signed32)
+            | Some(DataType::Signed64) => {
+                // sign extended from the bytes sent
+                let shift = 64 - 8 * buf.len() as u32;
+                (be_uint(buf) as i64)
+                    .wrapping_shl(shift)
+                    .wrapping_shr(shift)
+                    .to_string()
+            }
+            Some(DataType::Float32) => f32::from_bits(be_uint(buf) as u32).to_string(),
+            Some(DataType::Float64) if buf.len() == 4 => {
+                f32::from_bits(be_uint(buf) as u32).to_string()
+            }
+            Some(DataType::Float64) => f64::from_bits(be_uint(buf)).to_string(),
+            Some(DataType::Boolean) => match buf[0] {
+                1 => "true".to_string(),
+                2 => "false".to_string(),
+                b => b.to_string(),
+            },
+            Some(DataType::MacAddress) => buf
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+            Some(DataType::String) => String::from_utf8_lossy(buf).into_owned(),
+            Some(DataType::Ipv4Address) => Ipv4Addr::from(be_uint(buf) as u32).to_string(),
+            Some(DataType::Ipv6Address) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(buf);
+                Ipv6Addr::from(octets).to_string()
+            }
+            Some(DataType::DateTimeSeconds) => timestamp::to_rfc3339(be_uint(buf) as i64, 0, 0),
+            Some(DataType::DateTimeMilliseconds) => {
+                let millis = be_uint(buf) as i64;
+                timestamp::to_rfc3339(
+                    millis.div_euclid(1000),
+                    millis.rem_euclid(1000) as u32 * 1_000_000,
+                    3,
+                )
+            }
+            Some(DataType::DateTimeMicroseconds) => ntp_to_rfc3339(be_uint(buf), 6),
+            Some(DataType::DateTimeNanoseconds) => ntp_to_rfc3339(be_uint(buf), 9),
+            Some(DataType::OctetArray)
+            | Some(DataType::BasicList)
+            | Some(DataType::SubTemplateList)
+            | Some(DataType::SubTemplateMultiList)
+            | None => {
+                match self
+                    .parser
+                    .decode_field(fs.enterprise_number.unwrap_or(0), fs.ident, buf)
+                {
+                    Some((_, value)) => value.to_string(),
+                    None => DataRecordValue::Bytes(buf).to_string(),
+                }
+            }
+        }
+    }
+}
+
+impl<'p> Files<'p> {
+    /// write the files to `dir`, created when missing, keeping up to
+    /// `DEFAULT_MAX_OPEN_FILES` open
+    pub fn new(parser: &'p parser::Parser, dir: &Path) -> Result<Self> {
+        Self::with_max_open(parser, dir, DEFAULT_MAX_OPEN_FILES)
+    }
+
+    /// write the files to `dir` keeping up to `max_open` (at least one) open
+    pub fn with_max_open(parser: &'p parser::Parser, dir: &Path, max_open: usize) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            parser,
+            dir: dir.to_path_buf(),
+            max_open: max_open.max(1),
+            names: HashMap::default(),
+            open: HashMap::default(),
+            writes: 0,
+            paths: Vec::new(),
+        })
+    }
+
+    /// write the data records of a message to the file of their template
+    pub fn write_message(
+        &mut self,
+        state: &state::State,
+        exporter: Option<SocketAddr>,
+        msg: &parser::Message,
+    ) -> Result<()> {
+        for set in &msg.sets {
+            if set.stype != parser::SetType::DataSet {
+                continue;
+            }
+            let fields = match state
+                .get_domain_field_specifiers(msg.observation_domain_id, &set.hdr.set_id)
+            {
+                Some(fields) => fields,
+                None => continue,
+            };
+            let key = (
+                msg.observation_domain_id,
+                set.hdr.set_id,
+                self.parser.field_names(fields),
+            );
+            self.open_file(&key)?;
+            let meta = Metadata {
+                exporter,
+                export_time: msg.export_time,
+                observation_domain_id: msg.observation_domain_id,
+                template_id: set.hdr.set_id,
+            };
+            self.writes += 1;
+            if let Some(&mut (ref mut writer, ref mut last_write)) = self.open.get_mut(&key) {
+                *last_write = self.writes;
+                writer.write_set(&meta, fields, set.buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        for (writer, _) in self.open.values_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// the files written, in the order they were created
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// how many files are open
+    pub fn open_files(&self) -> usize {
+        self.open.len()
+    }
+
+    // open the file of a section, closing the least recently written one
+    // when too many are open
+    fn open_file(&mut self, key: &Section) -> Result<()> {
+        if self.open.contains_key(key) {
+            return Ok(());
+        }
+        if self.open.len() >= self.max_open {
+            let oldest = self
+                .open
+                .iter()
+                .min_by_key(|(_, (_, last_write))| *last_write)
+                .map(|(k, _)| k.clone());
+            if let Some((mut writer, _)) = oldest.and_then(|k| self.open.remove(&k)) {
+                writer.flush()?;
+            }
+        }
+
+        let writer = match self.names.get(key) {
+            // reopened, the header is already there
+            Some(path) => {
+                let file = OpenOptions::new().append(true).open(path)?;
+                let mut writer = Writer::new(self.parser, BufWriter::new(file));
+                writer.section = Some(key.clone());
+                writer
+            }
+            None => {
+                let n = self
+                    .names
+                    .keys()
+                    .filter(|k| k.0 == key.0 && k.1 == key.1)
+                    .count();
+                let path = match n {
+                    0 => self.dir.join(format!("{}-{}.csv", key.0, key.1)),
+                    n => self.dir.join(format!("{}-{}-{}.csv", key.0, key.1, n + 1)),
+                };
+                let file = BufWriter::new(File::create(&path)?);
+                self.names.insert(key.clone(), path.clone());
+                self.paths.push(path);
+                Writer::new(self.parser, file)
+            }
+        };
+        self.open.insert(key.clone(), (writer, self.writes));
+        Ok(())
+    }
+}
+
+// write a row quoting the fields that need it (RFC 4180)
+fn write_row<W: Write, S: AsRef<str>>(out: &mut W, row: &[S]) -> Result<()> {
+    for (i, field) in row.iter().enumerate() {
+        let field = field.as_ref();
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        if field.contains(&[',', '"', '\n', '\r'][..]) {
+            write!(out, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            out.write_all(field.as_bytes())?;
+        }
+    }
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn ntp_to_rfc3339(ntp: u64, digits: usize) -> String {
+    let nanos = timestamp::ntp_to_unix(ntp, 1_000_000_000);
+    timestamp::to_rfc3339(
+        nanos.div_euclid(1_000_000_000),
+        nanos.rem_euclid(1_000_000_000) as u32,
+        digits,
+    )
+}
@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use parser;
+use state;
+use std::fmt;
+
+/// Node is a part of a message along with where it was found, the same way
+/// a dissector shows packets: message header, sets, templates or records
+/// and their fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub label: String,
+    /// offset of the first byte from the start of the message
+    pub offset: usize,
+    pub length: usize,
+    /// the specifier a field was decoded with, or the one being defined
+    pub field: Option<parser::FieldSpecifier>,
+    pub value: Option<String>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn new(label: String, offset: usize, length: usize) -> Self {
+        Self {
+            label,
+            offset,
+            length,
+            field: None,
+            value: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn leaf<T: ToString>(label: &str, offset: usize, length: usize, value: T) -> Self {
+        let mut node = Self::new(label.to_string(), offset, length);
+        node.value = Some(value.to_string());
+        node
+    }
+
+    fn fmt_indent(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(
+            f,
+            "{:04x} {:5} {:indent$}{}",
+            self.offset,
+            self.length,
+            "",
+            self.label,
+            indent = depth * 2
+        )?;
+        if let Some(ref value) = self.value {
+            write!(f, ": {}", value)?;
+        }
+        if let Some(ref fs) = self.field {
+            write!(f, " (id {}, length {}", fs.ident, fs.field_length)?;
+            if let Some(pen) = fs.enterprise_number {
+                write!(f, ", pen {}", pen)?;
+            }
+            write!(f, ")")?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.fmt_indent(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Node {
+    /// one line per node with its offset in hex and length
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
+
+/// decode a message into a tree of nodes, templates are learned into `state`
+/// as `Parser::parse_message` would so the following messages decode too.
+pub fn dissect(parser: &parser::Parser, state: &mut state::State, input: &[u8]) -> Result<Node> {
+    let (body, msg) = parser::Message::parse(input)
+        .map_err(|e| anyhow!("failed while parsing ipfix header : {:?}", e))?;
+    let (rest, mut sets) = parser::Set::parse_many(body)
+        .map_err(|e| anyhow!("failed while extracting sets {:?}", e))?;
+
+    let mut root = Node::new("IPFIX Message".to_string(), 0, input.len());
+    root.children = vec![
+        Node::leaf("version", 0, 2, msg.version),
+        Node::leaf("length", 2, 2, msg.length),
+        Node::leaf("exportTime", 4, 4, msg.export_time),
+        Node::leaf("sequenceNumber", 8, 4, msg.sequence_number),
+        Node::leaf("observationDomainId", 12, 4, msg.observation_domain_id),
+    ];
+
+    for set in &mut sets {
+        // templates are learned before a set is dissected, records are
+        // decoded with the templates known at that point of the message
+        if set.stype != parser::SetType::DataSet {
+            match set.process_set_body(&parser.pen_formatter, state, msg.observation_domain_id) {
+                Err(err) if err.is::<state::LimitError>() => return Err(err),
+                _ => {}
+            }
+        }
+        root.children.push(dissect_set(
+            parser,
+            state,
+            msg.observation_domain_id,
+            set,
+            offset_of(input, set.buf),
+        ));
+    }
+    if !rest.is_empty() {
+        let offset = offset_of(input, rest);
+        root.children
+            .push(Node::leaf("trailing bytes", offset, rest.len(), hex(rest)));
+    }
+    Ok(root)
+}
+
+fn dissect_set(
+    parser: &parser::Parser,
+    state: &state::State,
+    domain: u32,
+    set: &parser::Set,
+    offset: usize,
+) -> Node {
+    let set_id = set.hdr.set_id;
+    let label = match set.stype {
+        parser::SetType::Template => format!("Template Set {}", set_id),
+        parser::SetType::OptionTemplate => format!("Options Template Set {}", set_id),
+        parser::SetType::DataSet => format!("Data Set {}", set_id),
+    };
+    let mut node = Node::new(label, offset - 4, set.buf.len() + 4);
+    node.children = vec![
+        Node::leaf("setId", offset - 4, 2, set_id),
+        Node::leaf("length", offset - 2, 2, set.hdr.length),
+    ];
+
+    let rest = match set.stype {
+        parser::SetType::Template => dissect_templates(&mut node, set.buf, offset, 4),
+        parser::SetType::OptionTemplate => dissect_templates(&mut node, set.buf, offset, 6),
+        parser::SetType::DataSet => match state.get_domain_field_specifiers(domain, &set_id) {
+            Some(fields) => dissect_records(&mut node, parser, fields, set.buf, offset),
+            None => {
+                node.children.push(Node::leaf(
+                    "unknown template",
+                    offset,
+                    set.buf.len(),
+                    hex(set.buf),
+                ));
+                &[]
+            }
+        },
+    };
+    if !rest.is_empty() {
+        let at = offset + set.buf.len() - rest.len();
+        node.children
+            .push(Node::leaf("padding", at, rest.len(), hex(rest)));
+    }
+    node
+}
+
+// dissect the template records of a set, `header_len` is 4 for templates and
+// 6 for options templates, returns what couldn't be parsed
+fn dissect_templates<'a>(
+    node: &mut Node,
+    buf: &'a [u8],
+    offset: usize,
+    header_len: usize,
+) -> &'a [u8] {
+    let mut rest = buf;
+    loop {
+        let parsed = if header_len == 4 {
+            parser::Template::parse(rest)
+                .map(|(more, t)| (more, t.header.template_id, None, t.field_specifiers))
+        } else {
+            parser::OptionsTemplate::parse(rest).map(|(more, t)| {
+                (
+                    more,
+                    t.header.id,
+                    Some(t.header.scope_field_count),
+                    t.field_specifiers,
+                )
+            })
+        };
+        let (more, id, scope_field_count, fields) = match parsed {
+            // padding is zeros, so is a template with id 0 and no fields
+            Ok(parsed) if parsed.1 != 0 => parsed,
+            _ => return rest,
+        };
+        let at = offset + buf.len() - rest.len();
+        let mut template = Node::new(format!("Template {}", id), at, rest.len() - more.len());
+        template.children = vec![
+            Node::leaf("templateId", at, 2, id),
+            Node::leaf("fieldCount", at + 2, 2, fields.len()),
+        ];
+        if let Some(count) = scope_field_count {
+            template
+                .children
+                .push(Node::leaf("scopeFieldCount", at + 4, 2, count));
+        }
+        let mut at = at + header_len;
+        for fs in fields {
+            let length = if fs.enterprise_number.is_some() { 8 } else { 4 };
+            let mut field = Node::new("Field Specifier".to_string(), at, length);
+            field.field = Some(fs);
+            template.children.push(field);
+            at += length;
+        }
+        node.children.push(template);
+        rest = more;
+    }
+}
+
+// dissect the data records of a set, returns what couldn't be parsed
+fn dissect_records<'a>(
+    node: &mut Node,
+    parser: &parser::Parser,
+    fields: &[parser::FieldSpecifier],
+    buf: &'a [u8],
+    offset: usize,
+) -> &'a [u8] {
+    let mut rest = buf;
+    let mut n = 0;
+    while !rest.is_empty() {
+        let at = offset + buf.len() - rest.len();
+        let mut record = Node::new(format!("Record {}", n), at, 0);
+        let mut more = rest;
+        for fs in fields {
+            let (next, value) = match parser::DataSet::take_field(more, fs.field_length) {
+                Ok(taken) => taken,
+                Err(_) => return rest,
+            };
+            let field_at = offset + buf.len() - more.len();
+            let pen = fs.enterprise_number.unwrap_or(0);
+            let (label, value) = match parser.decode_field(pen, fs.ident, value) {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => (
+                    format!("field {}", fs.ident),
+                    parser::DataRecordValue::Bytes(value).to_string(),
+                ),
+            };
+            let mut field = Node::new(label, field_at, more.len() - next.len());
+            field.field = Some(fs.clone());
+            field.value = Some(value);
+            record.children.push(field);
+            more = next;
+        }
+        // nothing is consumed with an empty template
+        if more.len() == rest.len() {
+            break;
+        }
+        record.length = rest.len() - more.len();
+        node.children.push(record);
+        rest = more;
+        n += 1;
+    }
+    rest
+}
+
+// offset of `part` within `input` which it was sliced from
+fn offset_of(input: &[u8], part: &[u8]) -> usize {
+    part.as_ptr() as usize - input.as_ptr() as usize
+}
+
+fn hex(buf: &[u8]) -> String {
+    parser::DataRecordValue::Bytes(buf).to_string()
+}
@@ -0,0 +1,147 @@
+use parser::{DataRecord, DataRecordKey, DataRecordValue, Message};
+use rustc_hash::FxHashMap as HashMap;
+use std::net::IpAddr;
+
+/// interface and VRF names learned from the options data of a single exporter
+#[derive(Debug, Default)]
+pub struct ExporterNames {
+    interfaces: HashMap<u64, String>,
+    vrfs: HashMap<u64, String>,
+}
+
+/// Enricher caches interface and VRF tables exported as options data
+/// per exporter and adds the resolved names to subsequent data records.
+#[derive(Debug, Default, new)]
+pub struct Enricher {
+    #[new(default)]
+    exporters: HashMap<IpAddr, ExporterNames>,
+}
+
+impl ExporterNames {
+    /// name of the interface with the given ifIndex
+    pub fn interface_name(&self, if_index: u64) -> Option<&str> {
+        self.interfaces.get(&if_index).map(|e| e.as_str())
+    }
+
+    /// name of the VRF with the given id
+    pub fn vrf_name(&self, vrf_id: u64) -> Option<&str> {
+        self.vrfs.get(&vrf_id).map(|e| e.as_str())
+    }
+
+    pub fn interfaces_len(&self) -> usize {
+        self.interfaces.len()
+    }
+
+    pub fn vrfs_len(&self) -> usize {
+        self.vrfs.len()
+    }
+
+    // learn the mappings carried by an options record, a record
+    // may describe an interface, a VRF or neither of them.
+    fn learn_record(&mut self, record: &DataRecord) {
+        if let Some(if_index) = get_u64(record, "ingressInterface") {
+            // interfaceName is preferred, some exporters only send the description
+            if let Some(name) = get_name(record, "interfaceName")
+                .or_else(|| get_name(record, "interfaceDescription"))
+            {
+                self.interfaces.insert(if_index, name);
+            }
+        }
+
+        if let Some(vrf_id) = get_u64(record, "ingressVRFID") {
+            if let Some(name) = get_name(record, "VRFname") {
+                self.vrfs.insert(vrf_id, name);
+            }
+        }
+    }
+}
+
+impl Enricher {
+    /// learn interface and VRF names from the options records in `msg`
+    pub fn learn(&mut self, exporter: IpAddr, msg: &Message) {
+        let records = msg.get_options_records();
+        if records.is_empty() {
+            return;
+        }
+        let names = self.exporters.entry(exporter).or_default();
+        for record in records {
+            names.learn_record(record);
+        }
+    }
+
+    /// add `ingressInterfaceName`, `egressInterfaceName` and `vrfName` to the
+    /// record when the exporter previously sent the matching options data.
+    pub fn enrich<'a>(&self, exporter: IpAddr, record: &mut DataRecord<'a>) {
+        let names = match self.exporters.get(&exporter) {
+            Some(names) => names,
+            None => return,
+        };
+
+        let mut enriched: Vec<(&'static str, String)> = Vec::new();
+        if let Some(name) =
+            get_u64(record, "ingressInterface").and_then(|i| names.interface_name(i))
+        {
+            enriched.push(("ingressInterfaceName", name.to_string()));
+        }
+        if let Some(name) = get_u64(record, "egressInterface").and_then(|i| names.interface_name(i))
+        {
+            enriched.push(("egressInterfaceName", name.to_string()));
+        }
+        if let Some(name) = get_u64(record, "ingressVRFID")
+            .or_else(|| get_u64(record, "egressVRFID"))
+            .and_then(|i| names.vrf_name(i))
+        {
+            enriched.push(("vrfName", name.to_string()));
+        }
+
+        for (key, name) in enriched {
+            record
+                .values
+                .insert(DataRecordKey::Str(key), DataRecordValue::String(name));
+        }
+    }
+
+    /// learn from the options records in `msg` then enrich its data records,
+    /// records decoded with options templates are left untouched.
+    pub fn process(&mut self, exporter: IpAddr, msg: &mut Message) {
+        self.learn(exporter, msg);
+        for set in &mut msg.sets {
+            for ds in set.data.iter_mut().filter(|ds| !ds.is_options) {
+                for record in &mut ds.records {
+                    self.enrich(exporter, record);
+                }
+            }
+        }
+    }
+
+    /// get the names learned for an exporter
+    pub fn get(&self, exporter: &IpAddr) -> Option<&ExporterNames> {
+        self.exporters.get(exporter)
+    }
+
+    /// forget everything learned from an exporter
+    pub fn remove(&mut self, exporter: &IpAddr) -> Option<ExporterNames> {
+        self.exporters.remove(exporter)
+    }
+
+    pub fn len(&self) -> usize {
+        self.exporters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn get_u64(record: &DataRecord, key: &str) -> Option<u64> {
+    record.get(key).and_then(|v| v.as_u64())
+}
+
+// names are frequently padded with NULs up to the field length
+fn get_name(record: &DataRecord, key: &str) -> Option<String> {
+    record
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim_end_matches('\0').to_string())
+        .filter(|s| !s.is_empty())
+}
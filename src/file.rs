@@ -0,0 +1,230 @@
+use anyhow::{anyhow, Result};
+use parser;
+use state;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use stream::{self, Frame};
+
+pub use stream::MESSAGE_HEADER_LEN;
+
+/// template id used by `Writer` for the Export Session Details options template,
+/// picked from the top of the range to stay clear of exporter templates.
+pub const EXPORT_SESSION_TEMPLATE_ID: u16 = 65000;
+
+/// Reader iterates the messages of an IPFIX file (RFC 5655), the file is
+/// treated as a single session so templates are kept across messages.
+/// A truncated message at the end of the file ends the iteration.
+pub struct Reader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    state: state::State,
+    truncated: bool,
+}
+
+/// details about the export session the file was collected from,
+/// written as an Export Session Details options record (RFC 5655 8.1.3).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSessionDetails {
+    pub exporter: SocketAddr,
+    pub collector: SocketAddr,
+    /// IP protocol number of the transport (17 UDP, 6 TCP, 132 SCTP)
+    pub transport_protocol: u8,
+    pub observation_domain_id: u32,
+    pub min_export_seconds: u32,
+    pub max_export_seconds: u32,
+}
+
+/// Writer appends IPFIX messages to an IPFIX file (RFC 5655)
+pub struct Writer<W> {
+    inner: W,
+    sequence_number: u32,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_state(inner, state::State::new())
+    }
+
+    /// create a reader continuing from previously known templates
+    pub fn with_state(inner: R, state: state::State) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            state,
+            truncated: false,
+        }
+    }
+
+    /// read the next raw message, returns `None` at the end of the file
+    pub fn read_raw(&mut self) -> Result<Option<&[u8]>> {
+        if self.truncated {
+            return Ok(None);
+        }
+
+        match stream::read_frame(&mut self.inner, &mut self.buf)? {
+            Frame::Message => Ok(Some(&self.buf)),
+            Frame::Eof => Ok(None),
+            Frame::Truncated => {
+                self.truncated = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// read and parse the next message, returns `None` at the end of the file
+    pub fn read_message<'a>(
+        &'a mut self,
+        parser: &'a parser::Parser,
+    ) -> Result<Option<parser::Message<'a>>> {
+        if self.read_raw()?.is_none() {
+            return Ok(None);
+        }
+        let Reader {
+            ref mut state,
+            ref buf,
+            ..
+        } = *self;
+        parser.parse_message(state, buf).map(Some)
+    }
+
+    /// whether the file ended in the middle of a message
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn get_state(&self) -> &state::State {
+        &self.state
+    }
+
+    pub fn into_state(self) -> state::State {
+        self.state
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            sequence_number: 0,
+        }
+    }
+
+    /// append a raw message to the file
+    pub fn write_message(&mut self, msg: &[u8]) -> Result<()> {
+        if msg.len() < MESSAGE_HEADER_LEN {
+            return Err(anyhow!("message too short : {} bytes", msg.len()));
+        }
+        let length = u16::from_be_bytes([msg[2], msg[3]]) as usize;
+        if length != msg.len() {
+            return Err(anyhow!(
+                "message length {} doesn't match buffer length {}",
+                length,
+                msg.len()
+            ));
+        }
+        self.inner.write_all(msg)?;
+        Ok(())
+    }
+
+    /// append a message carrying the Export Session Details options template and record
+    pub fn write_export_session_details(&mut self, details: &ExportSessionDetails) -> Result<()> {
+        let exporter_ie = match details.exporter.ip() {
+            IpAddr::V4(_) => (130, 4),
+            IpAddr::V6(_) => (131, 16),
+        };
+        let collector_ie = match details.collector.ip() {
+            IpAddr::V4(_) => (211, 4),
+            IpAddr::V6(_) => (212, 16),
+        };
+        // sessionScope is the only scope field
+        let fields: [(u16, u16); 9] = [
+            (267, 1),
+            exporter_ie,
+            (217, 2),
+            collector_ie,
+            (216, 2),
+            (215, 1),
+            (149, 4),
+            (264, 4),
+            (260, 4),
+        ];
+
+        let mut template = Vec::new();
+        template.extend_from_slice(&EXPORT_SESSION_TEMPLATE_ID.to_be_bytes());
+        template.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+        template.extend_from_slice(&1u16.to_be_bytes());
+        for (ident, length) in fields.iter() {
+            template.extend_from_slice(&ident.to_be_bytes());
+            template.extend_from_slice(&length.to_be_bytes());
+        }
+
+        let mut record = vec![1u8];
+        push_addr(&mut record, details.exporter.ip());
+        record.extend_from_slice(&details.exporter.port().to_be_bytes());
+        push_addr(&mut record, details.collector.ip());
+        record.extend_from_slice(&details.collector.port().to_be_bytes());
+        record.push(details.transport_protocol);
+        record.extend_from_slice(&details.observation_domain_id.to_be_bytes());
+        record.extend_from_slice(&details.min_export_seconds.to_be_bytes());
+        record.extend_from_slice(&details.max_export_seconds.to_be_bytes());
+
+        let msg = build_message(
+            export_time(),
+            self.sequence_number,
+            details.observation_domain_id,
+            &[(3, &template), (EXPORT_SESSION_TEMPLATE_ID, &record)],
+        )?;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        self.write_message(&msg)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+// assemble a message out of (set_id, set body) pairs
+fn build_message(
+    export_time: u32,
+    sequence_number: u32,
+    observation_domain_id: u32,
+    sets: &[(u16, &[u8])],
+) -> Result<Vec<u8>> {
+    let length = MESSAGE_HEADER_LEN + sets.iter().map(|(_, b)| 4 + b.len()).sum::<usize>();
+    if length > usize::from(u16::MAX) {
+        return Err(anyhow!("message too large : {} bytes", length));
+    }
+
+    let mut msg = Vec::with_capacity(length);
+    msg.extend_from_slice(&10u16.to_be_bytes());
+    msg.extend_from_slice(&(length as u16).to_be_bytes());
+    msg.extend_from_slice(&export_time.to_be_bytes());
+    msg.extend_from_slice(&sequence_number.to_be_bytes());
+    msg.extend_from_slice(&observation_domain_id.to_be_bytes());
+    for (set_id, body) in sets {
+        msg.extend_from_slice(&set_id.to_be_bytes());
+        msg.extend_from_slice(&(4 + body.len() as u16).to_be_bytes());
+        msg.extend_from_slice(body);
+    }
+    Ok(msg)
+}
+
+fn push_addr(buf: &mut Vec<u8>, addr: IpAddr) {
+    match addr {
+        IpAddr::V4(ip) => buf.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => buf.extend_from_slice(&ip.octets()),
+    }
+}
+
+fn export_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
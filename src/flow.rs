@@ -0,0 +1,164 @@
+//! A canonical view of a flow record, independent of the information
+//! elements the exporter chose. Every field of `Flow` is taken from the first
+//! of its candidate elements the record has with a usable value:
+//!
+//! | field | candidates, in order of precedence |
+//! |-------|------------------------------------|
+//! | `src_addr` | sourceIPv4Address, sourceIPv6Address, unspecified (`0.0.0.0`, `::`) addresses skipped |
+//! | `dst_addr` | destinationIPv4Address, destinationIPv6Address, unspecified addresses skipped |
+//! | `src_port` | sourceTransportPort, tcpSourcePort, udpSourcePort |
+//! | `dst_port` | destinationTransportPort, tcpDestinationPort, udpDestinationPort |
+//! | `protocol` | protocolIdentifier, nextHeaderIPv6 |
+//! | `bytes` | octetDeltaCount, postOctetDeltaCount, octetTotalCount, postOctetTotalCount |
+//! | `packets` | packetDeltaCount, postPacketDeltaCount, packetTotalCount, postPacketTotalCount |
+//! | `start`, `end` | as `timestamp::flow_start_millis` / `flow_end_millis`, then the other boundary and flowDurationMilliSeconds or flowDurationMicroSeconds |
+//! | `ingress_interface` | ingressInterface, ingressPhysicalInterface |
+//! | `egress_interface` | egressInterface, egressPhysicalInterface |
+//! | `direction` | flowDirection |
+//! | `tcp_flags` | tcpControlBits |
+//!
+//! Delta counters are preferred over total counters, and counts at the
+//! observation point over their `post` counterparts. A value of the wrong
+//! type or out of range for the field counts as missing.
+
+use ie::{self, InformationElement};
+use parser::{DataRecord, Message};
+use record::FromValue;
+use serde::Serialize;
+use std::net::IpAddr;
+use timestamp;
+
+/// direction of a flow at the observation point (flowDirection)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Direction {
+    Ingress,
+    Egress,
+}
+
+/// Flow is a normalized flow record, fields the record doesn't carry in any
+/// of their elements are `None`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Flow {
+    pub src_addr: Option<IpAddr>,
+    pub dst_addr: Option<IpAddr>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub protocol: Option<u8>,
+    pub bytes: Option<u64>,
+    pub packets: Option<u64>,
+    /// milliseconds since the UNIX epoch
+    pub start: Option<u64>,
+    /// milliseconds since the UNIX epoch
+    pub end: Option<u64>,
+    pub ingress_interface: Option<u32>,
+    pub egress_interface: Option<u32>,
+    pub direction: Option<Direction>,
+    /// TCP header flags, 16 bits wide since RFC 7125
+    pub tcp_flags: Option<u16>,
+}
+
+impl Flow {
+    /// normalize a data record, `export_time` is the export time of its
+    /// message and `init_time` the exporter's `systemInitTimeMilliSeconds`
+    /// if known, used for sysUpTime timestamps.
+    pub fn from_record(record: &DataRecord, export_time: u32, init_time: Option<u64>) -> Self {
+        let mut start = timestamp::flow_start_millis(record, export_time, init_time);
+        let mut end = timestamp::flow_end_millis(record, export_time, init_time);
+        let duration = first::<u64>(record, &[ie::FLOW_DURATION_MILLISECONDS]).or_else(|| {
+            first::<u64>(record, &[ie::FLOW_DURATION_MICROSECONDS]).map(|us| us / 1000)
+        });
+        if let Some(duration) = duration {
+            match (start, end) {
+                (Some(s), None) => end = s.checked_add(duration),
+                (None, Some(e)) => start = e.checked_sub(duration),
+                _ => {}
+            }
+        }
+
+        Flow {
+            src_addr: address(record, &[ie::SOURCE_IPV4_ADDRESS, ie::SOURCE_IPV6_ADDRESS]),
+            dst_addr: address(
+                record,
+                &[ie::DESTINATION_IPV4_ADDRESS, ie::DESTINATION_IPV6_ADDRESS],
+            ),
+            src_port: first(
+                record,
+                &[
+                    ie::SOURCE_TRANSPORT_PORT,
+                    ie::TCP_SOURCE_PORT,
+                    ie::UDP_SOURCE_PORT,
+                ],
+            ),
+            dst_port: first(
+                record,
+                &[
+                    ie::DESTINATION_TRANSPORT_PORT,
+                    ie::TCP_DESTINATION_PORT,
+                    ie::UDP_DESTINATION_PORT,
+                ],
+            ),
+            protocol: first(record, &[ie::PROTOCOL_IDENTIFIER, ie::NEXT_HEADER_IPV6]),
+            bytes: first(
+                record,
+                &[
+                    ie::OCTET_DELTA_COUNT,
+                    ie::POST_OCTET_DELTA_COUNT,
+                    ie::OCTET_TOTAL_COUNT,
+                    ie::POST_OCTET_TOTAL_COUNT,
+                ],
+            ),
+            packets: first(
+                record,
+                &[
+                    ie::PACKET_DELTA_COUNT,
+                    ie::POST_PACKET_DELTA_COUNT,
+                    ie::PACKET_TOTAL_COUNT,
+                    ie::POST_PACKET_TOTAL_COUNT,
+                ],
+            ),
+            start,
+            end,
+            ingress_interface: first(
+                record,
+                &[ie::INGRESS_INTERFACE, ie::INGRESS_PHYSICAL_INTERFACE],
+            ),
+            egress_interface: first(
+                record,
+                &[ie::EGRESS_INTERFACE, ie::EGRESS_PHYSICAL_INTERFACE],
+            ),
+            direction: first::<u8>(record, &[ie::FLOW_DIRECTION]).and_then(|d| match d {
+                0 => Some(Direction::Ingress),
+                1 => Some(Direction::Egress),
+                _ => None,
+            }),
+            tcp_flags: first(record, &[ie::TCP_CONTROL_BITS]),
+        }
+    }
+
+    /// normalize the data records of a message, options records excluded
+    pub fn from_message(msg: &Message, init_time: Option<u64>) -> Vec<Self> {
+        msg.sets
+            .iter()
+            .flat_map(|set| set.data.iter().filter(|ds| !ds.is_options))
+            .flat_map(|ds| ds.records.iter())
+            .map(|record| Self::from_record(record, msg.export_time, init_time))
+            .collect()
+    }
+}
+
+// the value of the first element the record has and converts
+fn first<T: FromValue>(record: &DataRecord, candidates: &[InformationElement]) -> Option<T> {
+    candidates
+        .iter()
+        .filter_map(|ie| record.get_typed(*ie).ok())
+        .next()
+}
+
+// the first address the record has that isn't unspecified, exporters
+// sending both families zero the one a flow doesn't use
+fn address(record: &DataRecord, candidates: &[InformationElement]) -> Option<IpAddr> {
+    candidates
+        .iter()
+        .filter_map(|ie| record.get_typed::<IpAddr>(*ie).ok())
+        .find(|addr| !addr.is_unspecified())
+}
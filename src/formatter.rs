@@ -1,5 +1,6 @@
 extern crate nom;
 
+use ie;
 use nom::number::complete::{be_u128, be_u16, be_u32, be_u64};
 use parser;
 use rustc_hash::FxHashMap as HashMap;
@@ -25,6 +26,13 @@ pub fn be_int(s: &[u8]) -> parser::DataRecordValue {
     }
 }
 
+/// big endian unsigned integer of up to 8 bytes, as sent with reduced size
+/// encoding
+#[inline]
+pub fn be_uint(s: &[u8]) -> u64 {
+    s.iter().fold(0, |n, b| n << 8 | u64::from(*b))
+}
+
 /// conversion of bytes array to a DataRecordValue ipv4
 #[inline]
 pub fn ipv4_addr(s: &[u8]) -> parser::DataRecordValue {
@@ -84,6 +92,98 @@ fn mpls_stack(s: &[u8]) -> parser::DataRecordValue {
     }
 }
 
+/// IANA abstract data types (RFC 7011 6.1, RFC 6313)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Unsigned8,
+    Unsigned16,
+    Unsigned32,
+    Unsigned64,
+    Signed8,
+    Signed16,
+    Signed32,
+    Signed64,
+    Float32,
+    Float64,
+    Boolean,
+    MacAddress,
+    OctetArray,
+    String,
+    DateTimeSeconds,
+    DateTimeMilliseconds,
+    DateTimeMicroseconds,
+    DateTimeNanoseconds,
+    Ipv4Address,
+    Ipv6Address,
+    BasicList,
+    SubTemplateList,
+    SubTemplateMultiList,
+}
+
+impl DataType {
+    /// encoded length of the type, `None` for variable length types
+    pub fn length(self) -> Option<u16> {
+        match self {
+            DataType::Unsigned8 | DataType::Signed8 | DataType::Boolean => Some(1),
+            DataType::Unsigned16 | DataType::Signed16 => Some(2),
+            DataType::Unsigned32
+            | DataType::Signed32
+            | DataType::Float32
+            | DataType::DateTimeSeconds
+            | DataType::Ipv4Address => Some(4),
+            DataType::MacAddress => Some(6),
+            DataType::Unsigned64
+            | DataType::Signed64
+            | DataType::Float64
+            | DataType::DateTimeMilliseconds
+            | DataType::DateTimeMicroseconds
+            | DataType::DateTimeNanoseconds => Some(8),
+            DataType::Ipv6Address => Some(16),
+            DataType::OctetArray
+            | DataType::String
+            | DataType::BasicList
+            | DataType::SubTemplateList
+            | DataType::SubTemplateMultiList => None,
+        }
+    }
+
+    /// whether the type can be sent in fewer bytes (RFC 7011 6.2)
+    pub fn allows_reduced_size(self) -> bool {
+        matches!(
+            self,
+            DataType::Unsigned16
+                | DataType::Unsigned32
+                | DataType::Unsigned64
+                | DataType::Signed16
+                | DataType::Signed32
+                | DataType::Signed64
+                | DataType::Float64
+        )
+    }
+
+    /// whether a field of `field_length` can hold the type, reduced size
+    /// encoding included
+    pub fn fits(self, field_length: u16) -> bool {
+        if field_length == 65535 {
+            return self.length().is_none();
+        }
+        match self.length() {
+            // only to a float32
+            Some(length) if self == DataType::Float64 => {
+                field_length == length || field_length == 4
+            }
+            Some(length) if self.allows_reduced_size() => field_length <= length,
+            Some(length) => field_length == length,
+            None => true,
+        }
+    }
+}
+
+/// abstract data type of a field of enterprise number 0
+pub fn default_type(field_id: u16) -> Option<DataType> {
+    ie::by_id(field_id).and_then(|ie| ie.data_type)
+}
+
 /// mapping of field_id -> parser
 pub type FieldFormatter = HashMap<u16, (&'static str, fn(&[u8]) -> parser::DataRecordValue)>;
 
@@ -172,9 +272,9 @@ pub fn get_default_parsers() -> FieldFormatter {
         79 => ("mplsLabelStackEntry10", mpls_stack),
         80 => ("destinationMacAddress", be_int),
         81 => ("postSourceMacAddress", be_int),
-        82 => ("interfaceName", be_int),
-        83 => ("interfaceDescription", be_int),
-        84 => ("samplerName", be_int),
+        82 => ("interfaceName", be_string),
+        83 => ("interfaceDescription", be_string),
+        84 => ("samplerName", be_string),
         85 => ("octetTotalCount", be_int),
         86 => ("packetTotalCount", be_int),
         88 => ("fragmentOffsetIPv4", be_int),
@@ -197,7 +297,7 @@ pub fn get_default_parsers() -> FieldFormatter {
         144 => ("exportingProcessId", be_int),
         145 => ("templateId", be_int),
         146 => ("wlanChannelId", be_int),
-        147 => ("wlanSsid", be_int),
+        147 => ("wlanSsid", be_string),
         148 => ("flowId", be_int),
         149 => ("sourceId", be_int),
         150 => ("flowStartSeconds", be_int),
@@ -261,7 +361,24 @@ pub fn get_default_parsers() -> FieldFormatter {
         208 => ("ipv4Options", be_int),
         209 => ("tcpOptions", be_int),
         210 => ("paddingOctets", be_int),
+        211 => ("collectorIPv4Address", ipv4_addr),
+        212 => ("collectorIPv6Address", ipv6_addr),
         213 => ("headerLengthIPv4", be_int),
-        214 => ("mplsPayloadLength", be_int)
+        214 => ("mplsPayloadLength", be_int),
+        215 => ("exportTransportProtocol", be_int),
+        216 => ("collectorTransportPort", be_int),
+        217 => ("exporterTransportPort", be_int),
+        234 => ("ingressVRFID", be_int),
+        235 => ("egressVRFID", be_int),
+        236 => ("VRFname", be_string),
+        258 => ("collectionTimeMilliseconds", be_int),
+        260 => ("maxExportSeconds", be_int),
+        261 => ("maxFlowEndSeconds", be_int),
+        262 => ("messageMD5Checksum", be_int),
+        263 => ("messageScope", be_int),
+        264 => ("minExportSeconds", be_int),
+        265 => ("minFlowStartSeconds", be_int),
+        266 => ("opaqueOctets", be_int),
+        267 => ("sessionScope", be_int)
     }
 }
@@ -0,0 +1,101 @@
+//! Constants of the IANA information elements, generated at build time from
+//! the copy of the IANA registry in `registry/ipfix-information-elements.csv`
+//! and the source of `formatter::default_type`. Constants are named after
+//! the registry, their `name` is the key records hold them under: the name
+//! of `formatter::get_default_parsers` for the elements it decodes, which
+//! predates the registry and differs for some of them (`sourceId` for
+//! observationDomainId, `flowStartMilliSeconds`, ..), the registry name for
+//! the others, which records key by id.
+//!
+//! ```ignore
+//! let src = record.get_ipv4(ie::SOURCE_IPV4_ADDRESS)?;
+//! let octets = record.get_u64(ie::OCTET_DELTA_COUNT)?;
+//! ```
+
+use formatter::DataType;
+use std::fmt;
+
+/// InformationElement identifies a field by its id and the name records
+/// are keyed by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InformationElement {
+    pub id: u16,
+    pub name: &'static str,
+    /// abstract data type, `None` for types the crate doesn't know
+    pub data_type: Option<DataType>,
+}
+
+impl fmt::Display for InformationElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.id)
+    }
+}
+
+// the data types of the generated table, apart from the elements named
+// after them (basicList, ..)
+#[allow(dead_code)]
+mod data_type {
+    use formatter::DataType;
+
+    pub const UNSIGNED8: Option<DataType> = Some(DataType::Unsigned8);
+    pub const UNSIGNED16: Option<DataType> = Some(DataType::Unsigned16);
+    pub const UNSIGNED32: Option<DataType> = Some(DataType::Unsigned32);
+    pub const UNSIGNED64: Option<DataType> = Some(DataType::Unsigned64);
+    pub const SIGNED8: Option<DataType> = Some(DataType::Signed8);
+    pub const SIGNED16: Option<DataType> = Some(DataType::Signed16);
+    pub const SIGNED32: Option<DataType> = Some(DataType::Signed32);
+    pub const SIGNED64: Option<DataType> = Some(DataType::Signed64);
+    pub const FLOAT32: Option<DataType> = Some(DataType::Float32);
+    pub const FLOAT64: Option<DataType> = Some(DataType::Float64);
+    pub const BOOLEAN: Option<DataType> = Some(DataType::Boolean);
+    pub const MAC_ADDRESS: Option<DataType> = Some(DataType::MacAddress);
+    pub const OCTET_ARRAY: Option<DataType> = Some(DataType::OctetArray);
+    pub const STRING: Option<DataType> = Some(DataType::String);
+    pub const DATE_TIME_SECONDS: Option<DataType> = Some(DataType::DateTimeSeconds);
+    pub const DATE_TIME_MILLISECONDS: Option<DataType> = Some(DataType::DateTimeMilliseconds);
+    pub const DATE_TIME_MICROSECONDS: Option<DataType> = Some(DataType::DateTimeMicroseconds);
+    pub const DATE_TIME_NANOSECONDS: Option<DataType> = Some(DataType::DateTimeNanoseconds);
+    pub const IPV4_ADDRESS: Option<DataType> = Some(DataType::Ipv4Address);
+    pub const IPV6_ADDRESS: Option<DataType> = Some(DataType::Ipv6Address);
+    pub const BASIC_LIST: Option<DataType> = Some(DataType::BasicList);
+    pub const SUB_TEMPLATE_LIST: Option<DataType> = Some(DataType::SubTemplateList);
+    pub const SUB_TEMPLATE_MULTI_LIST: Option<DataType> = Some(DataType::SubTemplateMultiList);
+    pub const UNTYPED: Option<DataType> = None;
+}
+
+/// information_elements defines a constant per information element along
+/// with lookups by id and name
+macro_rules! information_elements(
+    { $($konst:ident = ($id:literal, $name:literal, $data_type:path)),+ $(,)* } => {
+        $(
+            #[doc = $name]
+            pub const $konst: InformationElement = InformationElement {
+                id: $id,
+                name: $name,
+                data_type: $data_type,
+            };
+        )+
+
+        /// every information element, ordered by id
+        pub const ALL: &[InformationElement] = &[$($konst),+];
+
+        /// the information element of an id
+        pub fn by_id(id: u16) -> Option<InformationElement> {
+            match id {
+                $($id => Some($konst),)+
+                _ => None,
+            }
+        }
+
+        /// the information element of a name
+        pub fn by_name(name: &str) -> Option<InformationElement> {
+            match name {
+                $($name => Some($konst),)+
+                _ => None,
+            }
+        }
+    };
+);
+
+// generated by build.rs from registry/ipfix-information-elements.csv
+include!(concat!(env!("OUT_DIR"), "/information_elements.rs"));
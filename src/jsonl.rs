@@ -0,0 +1,314 @@
+use anyhow::Result;
+use parser::{DataRecord, DataRecordKey, DataRecordValue, Message};
+use rustc_hash::FxHashMap as HashMap;
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::Write;
+use std::net::SocketAddr;
+
+/// how octet arrays are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteEncoding {
+    /// lowercase hex digits without a prefix
+    Hex,
+    /// standard base64 with padding
+    Base64,
+}
+
+/// how field names are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Naming {
+    /// IANA names as they are, e.g. `sourceIPv4Address`
+    Iana,
+    /// snake case, e.g. `source_ipv4_address`
+    SnakeCase,
+}
+
+/// writer options, by default hex bytes, IANA names and no metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    pub bytes: ByteEncoding,
+    pub naming: Naming,
+    /// wrap every record along with where it came from
+    pub metadata: bool,
+}
+
+/// where a record came from, written when `Options::metadata` is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub exporter: Option<SocketAddr>,
+    pub observation_domain_id: u32,
+    pub template_id: u16,
+    pub export_time: u32,
+}
+
+/// Writer streams data records to `out` as JSON Lines, one object per
+/// record with its keys sorted so the same record is always written the same.
+pub struct Writer<W: Write> {
+    out: W,
+    options: Options,
+    // snake case names, converted once per field
+    names: HashMap<String, String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            bytes: ByteEncoding::Hex,
+            naming: Naming::Iana,
+            metadata: false,
+        }
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W, options: Options) -> Self {
+        Self {
+            out,
+            options,
+            names: HashMap::default(),
+        }
+    }
+
+    /// write a single record followed by a newline
+    pub fn write_record(&mut self, meta: &Metadata, record: &DataRecord) -> Result<()> {
+        if self.options.naming == Naming::SnakeCase {
+            for key in record.values.keys() {
+                if let DataRecordKey::Str(name) = *key {
+                    if !self.names.contains_key(name) {
+                        self.names.insert(name.to_string(), snake_case(name));
+                    }
+                }
+            }
+        }
+        let fields = Fields {
+            record,
+            options: &self.options,
+            names: &self.names,
+        };
+        if self.options.metadata {
+            let line = Line {
+                meta,
+                fields,
+                snake: self.options.naming == Naming::SnakeCase,
+            };
+            serde_json::to_writer(&mut self.out, &line)?;
+        } else {
+            serde_json::to_writer(&mut self.out, &fields)?;
+        }
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// write every data record of a message
+    pub fn write_message(&mut self, exporter: Option<SocketAddr>, msg: &Message) -> Result<()> {
+        for set in &msg.sets {
+            for ds in &set.data {
+                let meta = Metadata {
+                    exporter,
+                    observation_domain_id: msg.observation_domain_id,
+                    template_id: ds.header.set_id,
+                    export_time: msg.export_time,
+                };
+                for record in &ds.records {
+                    self.write_record(&meta, record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+// a record along with its metadata
+struct Line<'r, 'a: 'r> {
+    meta: &'r Metadata,
+    fields: Fields<'r, 'a>,
+    snake: bool,
+}
+
+// the fields of a record, sorted by key
+struct Fields<'r, 'a: 'r> {
+    record: &'r DataRecord<'a>,
+    options: &'r Options,
+    names: &'r HashMap<String, String>,
+}
+
+struct Value<'r, 'a: 'r> {
+    value: &'r DataRecordValue<'a>,
+    bytes: ByteEncoding,
+}
+
+struct Hex<'a>(&'a [u8]);
+
+struct Base64<'a>(&'a [u8]);
+
+impl<'r, 'a> Serialize for Line<'r, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let keys: [&str; 5] = if self.snake {
+            [
+                "exporter",
+                "observation_domain_id",
+                "export_time",
+                "template_id",
+                "record",
+            ]
+        } else {
+            [
+                "exporter",
+                "observationDomainId",
+                "exportTime",
+                "templateId",
+                "record",
+            ]
+        };
+        let mut map = serializer.serialize_map(Some(5))?;
+        match self.meta.exporter {
+            Some(ref exporter) => map.serialize_entry(keys[0], &format_args!("{}", exporter))?,
+            None => map.serialize_entry(keys[0], &())?,
+        }
+        map.serialize_entry(keys[1], &self.meta.observation_domain_id)?;
+        map.serialize_entry(keys[2], &self.meta.export_time)?;
+        map.serialize_entry(keys[3], &self.meta.template_id)?;
+        map.serialize_entry(keys[4], &self.fields)?;
+        map.end()
+    }
+}
+
+impl<'r, 'a> Serialize for Fields<'r, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut fields: Vec<_> = self.record.values.iter().collect();
+        fields.sort_by(|a, b| compare_keys(a.0, b.0));
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for (key, value) in fields {
+            let value = Value {
+                value,
+                bytes: self.options.bytes,
+            };
+            match *key {
+                DataRecordKey::Str(name) => match self.names.get(name) {
+                    Some(snake) => map.serialize_entry(snake, &value)?,
+                    None => map.serialize_entry(name, &value)?,
+                },
+                DataRecordKey::Unrecognized(id) => map.serialize_entry(&id, &value)?,
+                DataRecordKey::Err(ref e) => map.serialize_entry(e, &value)?,
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'r, 'a> Serialize for Value<'r, 'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match *self.value {
+            DataRecordValue::IPv4(ip) => serializer.collect_str(&ip),
+            DataRecordValue::IPv6(ip) => serializer.collect_str(&ip),
+            DataRecordValue::U8(v) => serializer.serialize_u8(v),
+            DataRecordValue::U16(v) => serializer.serialize_u16(v),
+            DataRecordValue::U32(v) => serializer.serialize_u32(v),
+            DataRecordValue::U64(v) => serializer.serialize_u64(v),
+            DataRecordValue::String(ref s) => serializer.serialize_str(s),
+            DataRecordValue::Bytes(b) => match self.bytes {
+                ByteEncoding::Hex => serializer.collect_str(&Hex(b)),
+                ByteEncoding::Base64 => serializer.collect_str(&Base64(b)),
+            },
+            DataRecordValue::MPLS(label, exp, bottom) => {
+                let mut seq = serializer.serialize_seq(Some(3))?;
+                seq.serialize_element(&label)?;
+                seq.serialize_element(&exp)?;
+                seq.serialize_element(&bottom)?;
+                seq.end()
+            }
+            DataRecordValue::Err(ref e, _) => serializer.serialize_str(e),
+            DataRecordValue::Empty => serializer.serialize_unit(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Hex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for Base64<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        for chunk in self.0.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    let c = ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize];
+                    write!(f, "{}", c as char)?;
+                } else {
+                    write!(f, "=")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// names first, then unrecognized fields by id, then errors
+fn compare_keys(a: &DataRecordKey, b: &DataRecordKey) -> Ordering {
+    match (a, b) {
+        (DataRecordKey::Str(a), DataRecordKey::Str(b)) => a.cmp(b),
+        (DataRecordKey::Unrecognized(a), DataRecordKey::Unrecognized(b)) => a.cmp(b),
+        (DataRecordKey::Err(a), DataRecordKey::Err(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn rank(key: &DataRecordKey) -> u8 {
+    match *key {
+        DataRecordKey::Str(_) => 0,
+        DataRecordKey::Unrecognized(_) => 1,
+        DataRecordKey::Err(_) => 2,
+    }
+}
+
+/// convert an IANA name to snake case keeping acronyms together,
+/// e.g. `postNATSourceIPv4Address` to `post_nat_source_ipv4_address`
+pub fn snake_case(name: &str) -> String {
+    // IPv4/IPv6 would otherwise split after the I
+    let name = name.replace("IPv", "Ipv");
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = match chars.get(i + 1) {
+                Some(next) => next.is_ascii_lowercase(),
+                None => false,
+            };
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
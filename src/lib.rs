@@ -1,15 +1,83 @@
 #[macro_use]
 extern crate nom;
 extern crate anyhow;
+#[cfg(feature = "arrow")]
+extern crate arrow;
+#[cfg(feature = "codec")]
+extern crate bytes;
 #[macro_use]
 extern crate derive_new;
 extern crate nom_derive;
+#[cfg(feature = "parquet")]
+extern crate parquet;
+#[cfg(feature = "tls")]
+extern crate ring;
+#[cfg(feature = "derive")]
+extern crate rsipfix_derive;
 extern crate rustc_hash;
+#[cfg(feature = "tls")]
+extern crate rustls;
+#[cfg(feature = "tls")]
+extern crate rustls_pemfile;
 extern crate serde;
+extern crate serde_json;
+#[cfg(feature = "codec")]
+extern crate tokio_util;
 
+/// implements a Parquet archive sink partitioned by exporter and time
+#[cfg(feature = "parquet")]
+pub mod archive;
+/// implements a UDP collector
+pub mod collector;
+/// implements decoding of data sets into Apache Arrow record batches
+#[cfg(feature = "arrow")]
+pub mod columnar;
+/// implements exporter conformance reports
+pub mod conformance;
+/// implements CSV output with template-derived columns
+pub mod csv;
+/// implements byte-offset annotated decoding for debugging
+pub mod dissect;
+/// implements enrichment of data records from options data
+pub mod enrich;
+/// implements IPFIX File Format (RFC 5655) reader and writer
+pub mod file;
+/// implements a normalized flow view of data records
+pub mod flow;
 /// implements formatters for various types
 pub mod formatter;
+/// implements constants of the IANA information elements
+pub mod ie;
+/// implements streaming JSON Lines output
+pub mod jsonl;
+/// implements a Prometheus metrics endpoint
+#[cfg(feature = "metrics")]
+pub mod metrics;
+/// implements owned, deserializable copies of parsed messages
+pub mod owned;
 /// implements IPFIX parser
 pub mod parser;
+/// implements extraction of IPFIX messages from pcap/pcapng captures
+pub mod pcap;
+/// implements exporter admission and template pinning
+pub mod policy;
+/// implements conversion of data records into typed values and structs
+pub mod record;
+/// implements JSON Schema, Avro and Arrow schema generation from templates
+pub mod schema;
 /// implements IPFIX state
 pub mod state;
+/// implements runtime statistics of sessions
+pub mod stats;
+/// implements framing of IPFIX message streams
+pub mod stream;
+/// implements normalization of flow timestamps
+pub mod timestamp;
+/// implements IPFIX over TLS
+#[cfg(feature = "tls")]
+pub mod tls;
+
+pub use record::IpfixRecord;
+/// derives `record::IpfixRecord` from the `#[ipfix(..)]` attributes of the fields
+#[cfg(feature = "derive")]
+pub use rsipfix_derive::IpfixRecord;
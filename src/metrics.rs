@@ -0,0 +1,305 @@
+use anyhow::{anyhow, Result};
+use collector;
+use stats;
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// renders the current metrics in the Prometheus text format
+pub type Render = Arc<dyn Fn() -> String + Send + Sync>;
+
+// name, help and value of a counter kept per observation domain
+type DomainCounter = (&'static str, &'static str, fn(&stats::DomainStats) -> u64);
+
+// longest request accepted, scrapers send a few hundred bytes at most
+const MAX_REQUEST_LEN: usize = 8192;
+
+// requests answered at once, connections over it are closed right away
+const MAX_CONNECTIONS: usize = 16;
+
+// time a client has to send its request and read the response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Server answers `GET /metrics` over HTTP, accepting connections on a
+/// background thread and answering each on its own so a slow client
+/// doesn't hold up the others
+pub struct Server {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// listen on `listen` and serve whatever `render` returns
+    pub fn bind(listen: SocketAddr, render: Render) -> Result<Self> {
+        let listener =
+            TcpListener::bind(listen).map_err(|e| anyhow!("failed to bind {} : {}", listen, e))?;
+        // poll so the thread notices shutdown
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let stop = shutdown.clone();
+        let thread = thread::Builder::new()
+            .name("ipfix-metrics".to_string())
+            .spawn(move || {
+                let connections = Arc::new(AtomicUsize::new(0));
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                                connections.fetch_sub(1, Ordering::Relaxed);
+                                continue;
+                            }
+                            let render = render.clone();
+                            let open = connections.clone();
+                            let spawned = thread::Builder::new()
+                                .name("ipfix-metrics-conn".to_string())
+                                .spawn(move || {
+                                    let _ = respond(stream, &render);
+                                    open.fetch_sub(1, Ordering::Relaxed);
+                                });
+                            if spawned.is_err() {
+                                connections.fetch_sub(1, Ordering::Relaxed);
+                            }
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(100));
+                        }
+                        Err(_) => {}
+                    }
+                }
+            })?;
+        Ok(Self {
+            local_addr,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// stop serving and wait for the thread to finish
+    pub fn shutdown(mut self) {
+        self.stop()
+    }
+
+    fn stop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop()
+    }
+}
+
+/// render the collector counters and the statistics of every exporter
+pub fn render(
+    counters: &collector::CountersSnapshot,
+    exporters: &BTreeMap<SocketAddr, stats::Stats>,
+) -> String {
+    let mut out = String::new();
+    let totals = [
+        (
+            "ipfix_collector_datagrams_received_total",
+            "Datagrams received on the socket.",
+            counters.received,
+        ),
+        (
+            "ipfix_collector_datagrams_dropped_total",
+            "Datagrams dropped because a worker queue was full.",
+            counters.dropped,
+        ),
+        (
+            "ipfix_collector_datagrams_rejected_total",
+            "Datagrams rejected by the policy.",
+            counters.rejected,
+        ),
+        (
+            "ipfix_collector_parse_errors_total",
+            "Datagrams that couldn't be parsed as a message.",
+            counters.parse_errors,
+        ),
+        (
+            "ipfix_collector_limit_errors_total",
+            "Datagrams dropped because an exporter went over its limits.",
+            counters.limit_errors,
+        ),
+        (
+            "ipfix_collector_messages_total",
+            "Messages handed to the handler.",
+            counters.messages,
+        ),
+        (
+            "ipfix_collector_refused_templates_total",
+            "Template definitions refused because their id is pinned.",
+            counters.refused_templates,
+        ),
+    ];
+    for (name, help, value) in totals.iter() {
+        header(&mut out, name, help, "counter");
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+
+    let domains = exporters
+        .iter()
+        .flat_map(|(e, s)| s.domains.iter().map(move |(id, d)| (e, id, d)));
+    let per_domain: [DomainCounter; 8] = [
+        (
+            "ipfix_exporter_messages_total",
+            "Messages parsed per exporter and observation domain.",
+            |d| d.messages,
+        ),
+        (
+            "ipfix_exporter_bytes_total",
+            "Bytes of the messages parsed.",
+            |d| d.bytes,
+        ),
+        (
+            "ipfix_exporter_data_sets_total",
+            "Data sets received.",
+            |d| d.data_sets,
+        ),
+        (
+            "ipfix_exporter_template_sets_total",
+            "Template and options template sets received.",
+            |d| d.template_sets + d.options_template_sets,
+        ),
+        (
+            "ipfix_exporter_missing_template_sets_total",
+            "Data sets dropped because their template wasn't known.",
+            |d| d.missing_templates,
+        ),
+        (
+            "ipfix_exporter_sequence_gaps_total",
+            "Messages whose sequence number didn't follow the records seen before.",
+            |d| d.sequence_gaps,
+        ),
+        (
+            "ipfix_exporter_lost_records_total",
+            "Data records skipped by the sequence numbers.",
+            |d| d.lost_records,
+        ),
+        (
+            "ipfix_exporter_template_definitions_total",
+            "Templates defined or redefined.",
+            |d| d.template_definitions + d.template_redefinitions,
+        ),
+    ];
+    for (name, help, value) in per_domain.iter() {
+        header(&mut out, name, help, "counter");
+        for (exporter, domain, d) in domains.clone() {
+            let _ = writeln!(
+                out,
+                "{}{{exporter=\"{}\",domain=\"{}\"}} {}",
+                name,
+                exporter,
+                domain,
+                value(d)
+            );
+        }
+    }
+
+    header(
+        &mut out,
+        "ipfix_exporter_records_total",
+        "Data records decoded per template.",
+        "counter",
+    );
+    for (exporter, domain, d) in domains {
+        for (template, records) in &d.records {
+            let _ = writeln!(
+                out,
+                "ipfix_exporter_records_total{{exporter=\"{}\",domain=\"{}\",template=\"{}\"}} {}",
+                exporter, domain, template, records
+            );
+        }
+    }
+
+    header(
+        &mut out,
+        "ipfix_exporter_parse_errors_total",
+        "Parse errors per exporter by kind.",
+        "counter",
+    );
+    for (exporter, s) in exporters {
+        let e = &s.parse_errors;
+        let kinds = [
+            ("headers", e.headers),
+            ("sets", e.sets),
+            ("limits", e.limits),
+            ("violations", e.violations),
+        ];
+        for (kind, value) in kinds.iter() {
+            let _ = writeln!(
+                out,
+                "ipfix_exporter_parse_errors_total{{exporter=\"{}\",kind=\"{}\"}} {}",
+                exporter, kind, value
+            );
+        }
+    }
+
+    header(
+        &mut out,
+        "ipfix_exporter_templates",
+        "Templates and options templates held per exporter.",
+        "gauge",
+    );
+    for (exporter, s) in exporters {
+        let _ = writeln!(
+            out,
+            "ipfix_exporter_templates{{exporter=\"{}\"}} {}",
+            exporter, s.templates
+        );
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// answer a single request and close the connection
+fn respond(mut stream: TcpStream, render: &Render) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 || request.len() + n > MAX_REQUEST_LEN {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let line = request.split(|b| *b == b'\r').next().unwrap_or(&[]);
+    let mut parts = line.split(|b| *b == b' ');
+    let (method, path) = (parts.next(), parts.next());
+    let (status, body) = match (method, path) {
+        (Some(b"GET"), Some(b"/metrics")) => ("200 OK", render()),
+        (Some(b"GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,227 @@
+//! Owned copies of parsed messages. `parser::Message` borrows the datagram
+//! it was parsed from and serializes its values untagged, which reads well
+//! but can't be deserialized back: the JSON doesn't tell an IPv4 address
+//! from a string or a `U8` from a `U64`. The types here own their data and
+//! serialize every value tagged with its type, so a message deserializes to
+//! what was serialized.
+//!
+//! ```ignore
+//! let msg = owned::Message::from(&parser.parse_message(&mut state, &buf)?);
+//! let json = serde_json::to_string(&msg)?;
+//! let back: owned::Message = serde_json::from_str(&json)?;
+//! ```
+
+use parser::{self, OptionsTemplate, SetHeader, SetType, Template};
+use rustc_hash::FxHashMap as HashMap;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub version: u16,
+    pub length: u16,
+    pub export_time: u32,
+    pub sequence_number: u32,
+    pub observation_domain_id: u32,
+    pub sets: Vec<Set>,
+}
+
+/// a set along with the templates or data records it carried
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Set {
+    pub hdr: SetHeader,
+    pub stype: SetType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<Template>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options_templates: Vec<OptionsTemplate>,
+    pub data: Vec<DataSet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataSet {
+    pub header: SetHeader,
+    pub records: Vec<DataRecord>,
+    pub is_options: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataRecord {
+    #[serde(flatten)]
+    pub values: HashMap<Key, Value>,
+}
+
+/// the key of a field. Fields of an unknown enterprise, keyed by an error
+/// in `parser::DataRecordKey`, are keyed by the error message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Name(String),
+    Unrecognized(u16),
+}
+
+/// a value tagged with its type, e.g. `{"U32":1500}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    IPv4(Ipv4Addr),
+    IPv6(Ipv6Addr),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    String(String),
+    Bytes(Vec<u8>),
+    MPLS(u32, u8, u8),
+    Err(String, Vec<u8>),
+    Empty,
+}
+
+impl<'a> From<&parser::Message<'a>> for Message {
+    fn from(msg: &parser::Message<'a>) -> Self {
+        Message {
+            version: msg.version,
+            length: msg.length,
+            export_time: msg.export_time,
+            sequence_number: msg.sequence_number,
+            observation_domain_id: msg.observation_domain_id,
+            sets: msg.sets.iter().map(Set::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&parser::Set<'a>> for Set {
+    fn from(set: &parser::Set<'a>) -> Self {
+        Set {
+            hdr: set.hdr.clone(),
+            stype: set.stype,
+            templates: set.templates.clone(),
+            options_templates: set.options_templates.clone(),
+            data: set.data.iter().map(DataSet::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&parser::DataSet<'a>> for DataSet {
+    fn from(ds: &parser::DataSet<'a>) -> Self {
+        DataSet {
+            header: ds.header.clone(),
+            records: ds.records.iter().map(DataRecord::from).collect(),
+            is_options: ds.is_options,
+        }
+    }
+}
+
+impl<'a> From<&parser::DataRecord<'a>> for DataRecord {
+    fn from(record: &parser::DataRecord<'a>) -> Self {
+        DataRecord {
+            values: record
+                .values
+                .iter()
+                .map(|(k, v)| (Key::from(k), Value::from(v)))
+                .collect(),
+        }
+    }
+}
+
+impl DataRecord {
+    /// borrow the record as a `parser::DataRecord`, for the typed getters
+    /// and the writers
+    pub fn as_record(&self) -> parser::DataRecord<'_> {
+        parser::DataRecord {
+            values: self
+                .values
+                .iter()
+                .map(|(k, v)| (k.as_key(), v.as_value()))
+                .collect(),
+        }
+    }
+}
+
+impl<'a, 'r> From<&'r parser::DataRecordKey<'a>> for Key {
+    fn from(key: &'r parser::DataRecordKey<'a>) -> Self {
+        match *key {
+            parser::DataRecordKey::Str(name) => Key::Name(name.to_string()),
+            parser::DataRecordKey::Unrecognized(id) => Key::Unrecognized(id),
+            parser::DataRecordKey::Err(ref e) => Key::Name(e.clone()),
+        }
+    }
+}
+
+impl Key {
+    pub fn as_key(&self) -> parser::DataRecordKey<'_> {
+        match *self {
+            Key::Name(ref name) => parser::DataRecordKey::Str(name),
+            Key::Unrecognized(id) => parser::DataRecordKey::Unrecognized(id),
+        }
+    }
+}
+
+impl<'a, 'r> From<&'r parser::DataRecordValue<'a>> for Value {
+    fn from(value: &'r parser::DataRecordValue<'a>) -> Self {
+        match *value {
+            parser::DataRecordValue::IPv4(ip) => Value::IPv4(ip),
+            parser::DataRecordValue::IPv6(ip) => Value::IPv6(ip),
+            parser::DataRecordValue::U8(v) => Value::U8(v),
+            parser::DataRecordValue::U16(v) => Value::U16(v),
+            parser::DataRecordValue::U32(v) => Value::U32(v),
+            parser::DataRecordValue::U64(v) => Value::U64(v),
+            parser::DataRecordValue::String(ref s) => Value::String(s.clone()),
+            parser::DataRecordValue::Bytes(b) => Value::Bytes(b.to_vec()),
+            parser::DataRecordValue::MPLS(label, exp, bottom) => Value::MPLS(label, exp, bottom),
+            parser::DataRecordValue::Err(ref e, b) => Value::Err(e.clone(), b.to_vec()),
+            parser::DataRecordValue::Empty => Value::Empty,
+        }
+    }
+}
+
+impl Value {
+    pub fn as_value(&self) -> parser::DataRecordValue<'_> {
+        match *self {
+            Value::IPv4(ip) => parser::DataRecordValue::IPv4(ip),
+            Value::IPv6(ip) => parser::DataRecordValue::IPv6(ip),
+            Value::U8(v) => parser::DataRecordValue::U8(v),
+            Value::U16(v) => parser::DataRecordValue::U16(v),
+            Value::U32(v) => parser::DataRecordValue::U32(v),
+            Value::U64(v) => parser::DataRecordValue::U64(v),
+            Value::String(ref s) => parser::DataRecordValue::String(s.clone()),
+            Value::Bytes(ref b) => parser::DataRecordValue::Bytes(b),
+            Value::MPLS(label, exp, bottom) => parser::DataRecordValue::MPLS(label, exp, bottom),
+            Value::Err(ref e, ref b) => parser::DataRecordValue::Err(e.clone(), b),
+            Value::Empty => parser::DataRecordValue::Empty,
+        }
+    }
+}
+
+// keys are map keys, names as they are and unrecognized ids as digits
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Key::Name(ref name) => serializer.serialize_str(name),
+            Key::Unrecognized(id) => serializer.collect_str(&id),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(KeyVisitor)
+    }
+}
+
+struct KeyVisitor;
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = Key;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a field name or id")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Key, E> {
+        Ok(match s.parse() {
+            Ok(id) => Key::Unrecognized(id),
+            Err(_) => Key::Name(s.to_string()),
+        })
+    }
+}
@@ -1,22 +1,82 @@
-use nom::{error::make_error, number::complete::be_u8};
+use nom::{
+    error::make_error,
+    number::complete::{be_u16, be_u8},
+};
 
 use anyhow::{anyhow, Result};
 use formatter;
+use ie;
 use nom_derive::{Nom, Parse};
+use record;
 use rustc_hash::FxHashMap as HashMap;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use state;
 use std::{
-    net::{Ipv4Addr, Ipv6Addr},
+    error::Error,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::{Arc, RwLock},
 };
 
 pub struct Parser {
-    pen_formatter: formatter::EnterpriseFormatter,
+    pub(crate) pen_formatter: formatter::EnterpriseFormatter,
+    mode: Mode,
+}
+
+/// Mode decides what the parser does with messages violating RFC 7011
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// decode whatever can be decoded and skip the rest, for production ingest
+    Lenient,
+    /// reject a message on its first violation, for exporter certification.
+    /// Data sets whose template isn't known yet are still skipped.
+    Strict,
+}
+
+/// Violation of RFC 7011 reported in strict mode
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// the length in the message header doesn't match the message
+    MessageLength { header: u16, actual: usize },
+    /// bytes after the last set that don't make up a set
+    TrailingBytes { length: usize },
+    /// set ids 0, 1 and 4 to 255 are reserved
+    ReservedSetId { set_id: u16 },
+    /// template ids below 256 are reserved for set ids
+    TemplateId { set_id: u16, template_id: u16 },
+    /// options templates need between 1 and field count scope fields
+    ScopeFieldCount {
+        template_id: u16,
+        scope_field_count: u16,
+        field_count: u16,
+    },
+    /// a field that isn't variable length can't be zero bytes long
+    ZeroLengthField { template_id: u16, ident: u16 },
+    /// padding has to be zeros and shorter than a record of the set
+    Padding { set_id: u16, length: usize },
+    /// a record ends past the end of its set
+    TruncatedRecord { set_id: u16, length: usize },
+    /// a field belongs to an enterprise without registered formatters
+    UnknownPen { set_id: u16, pen: u32, ident: u16 },
+}
+
+/// ValueError is returned by the typed getters of `DataRecord`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueError {
+    /// the record has no value for the information element
+    Missing { name: &'static str },
+    /// the value isn't of a type convertible to the one asked for
+    Mismatch {
+        name: &'static str,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// the value is too large for the integer type asked for
+    Overflow { name: &'static str, value: u64 },
 }
 
 #[allow(dead_code)]
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Serialize)]
 pub struct Message<'a> {
     #[nom(Verify = "*version == 10")]
     pub version: u16,
@@ -28,22 +88,29 @@ pub struct Message<'a> {
     pub sets: Vec<Set<'a>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SetType {
     DataSet,
     Template,
     OptionTemplate,
 }
 
-#[derive(Debug)]
+/// a set as found in a message, serialized without its raw bytes. Template
+/// sets hold the templates they defined, data sets their records.
+#[derive(Debug, Serialize)]
 pub struct Set<'a> {
     pub hdr: SetHeader,
     pub stype: SetType,
+    #[serde(skip)]
     pub buf: &'a [u8],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<Template>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options_templates: Vec<OptionsTemplate>,
     pub data: Vec<DataSet<'a>>,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SetHeader {
     pub set_id: u16, // 2: Template Set, 3: Options Template Set, >255: Data Set
     pub length: u16,
@@ -56,13 +123,13 @@ pub struct TemplateSet {
     pub records: Vec<Template>,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TemplateHeader {
     pub template_id: u16,
     pub field_count: u16,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Template {
     pub header: TemplateHeader,
     #[nom(Count = "header.field_count")]
@@ -76,7 +143,7 @@ pub struct OptionsTemplateSet {
     pub records: Vec<OptionsTemplate>,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OptionsTemplateHeader {
     pub id: u16,
     pub field_count: u16,
@@ -84,15 +151,17 @@ pub struct OptionsTemplateHeader {
     pub scope_field_count: u16,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OptionsTemplate {
     pub header: OptionsTemplateHeader,
     #[nom(Count = "header.field_count")]
     pub field_specifiers: Vec<FieldSpecifier>,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SerializedFieldSpecifier")]
 pub struct FieldSpecifier {
+    #[serde(skip)]
     temp_ident: u16,
     #[nom(
         Ignore,
@@ -111,11 +180,53 @@ pub struct FieldSpecifier {
     is_pen: bool,
 }
 
-#[derive(Debug)]
+// what a field specifier is serialized as, the rest is derived from it
+#[derive(Deserialize)]
+struct SerializedFieldSpecifier {
+    ident: u16,
+    field_length: u16,
+    enterprise_number: Option<u32>,
+}
+
+impl FieldSpecifier {
+    /// whether the field is variable length, encoded with a length prefix
+    pub fn is_variable(&self) -> bool {
+        self.is_variable
+    }
+
+    /// whether the field is enterprise specific
+    pub fn is_pen(&self) -> bool {
+        self.is_pen
+    }
+}
+
+impl From<SerializedFieldSpecifier> for FieldSpecifier {
+    fn from(fs: SerializedFieldSpecifier) -> Self {
+        let temp_ident = match fs.enterprise_number {
+            Some(_) => fs.ident | 0x8000,
+            None => fs.ident,
+        };
+        FieldSpecifier {
+            temp_ident,
+            ident: fs.ident,
+            field_length: fs.field_length,
+            enterprise_number: fs.enterprise_number,
+            is_variable: fs.field_length == 65535,
+            is_pen: fs.enterprise_number.is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct DataSet<'a> {
     #[allow(dead_code)]
     pub header: SetHeader,
     pub records: Vec<DataRecord<'a>>,
+    // whether the records were decoded using an options template
+    pub is_options: bool,
+    // bytes left after the last record, padding or a record cut short
+    #[serde(skip)]
+    pub trailing: &'a [u8],
 }
 
 #[derive(PartialEq, Debug, Serialize)]
@@ -166,62 +277,295 @@ impl From<u16> for SetType {
     }
 }
 
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::MessageLength { header, actual } => write!(
+                f,
+                "message length {} doesn't match the {} bytes received",
+                header, actual
+            ),
+            Violation::TrailingBytes { length } => {
+                write!(f, "{} bytes after the last set", length)
+            }
+            Violation::ReservedSetId { set_id } => write!(f, "set id {} is reserved", set_id),
+            Violation::TemplateId {
+                set_id,
+                template_id,
+            } => write!(
+                f,
+                "set {} defines template {}, template ids start at 256",
+                set_id, template_id
+            ),
+            Violation::ScopeFieldCount {
+                template_id,
+                scope_field_count,
+                field_count,
+            } => write!(
+                f,
+                "options template {} has {} scope fields out of {} fields",
+                template_id, scope_field_count, field_count
+            ),
+            Violation::ZeroLengthField { template_id, ident } => write!(
+                f,
+                "template {} has a zero length field {}",
+                template_id, ident
+            ),
+            Violation::Padding { set_id, length } => {
+                write!(f, "set {} has {} bytes of invalid padding", set_id, length)
+            }
+            Violation::TruncatedRecord { set_id, length } => write!(
+                f,
+                "set {} ends with a truncated record of {} bytes",
+                set_id, length
+            ),
+            Violation::UnknownPen { set_id, pen, ident } => write!(
+                f,
+                "set {} uses field {} of unknown enterprise {}",
+                set_id, ident, pen
+            ),
+        }
+    }
+}
+
+impl Error for Violation {}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueError::Missing { name } => write!(f, "record has no {}", name),
+            ValueError::Mismatch {
+                name,
+                expected,
+                found,
+            } => write!(f, "{} is {}, expected {}", name, found, expected),
+            ValueError::Overflow { name, value } => {
+                write!(f, "{} value {} is out of range", name, value)
+            }
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+impl<'a> DataRecordValue<'a> {
+    /// widen an unsigned integer value to u64, reduced-size encoded
+    /// fields of up to 8 bytes are accepted as well.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            DataRecordValue::U8(v) => Some(u64::from(v)),
+            DataRecordValue::U16(v) => Some(u64::from(v)),
+            DataRecordValue::U32(v) => Some(u64::from(v)),
+            DataRecordValue::U64(v) => Some(v),
+            DataRecordValue::Bytes(b) if !b.is_empty() && b.len() <= 8 => {
+                Some(b.iter().fold(0u64, |acc, &x| (acc << 8) | u64::from(x)))
+            }
+            _ => None,
+        }
+    }
+
+    /// get the value as a string slice if it was parsed as a string
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            DataRecordValue::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// name of the variant, as reported by `ValueError::Mismatch`
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            DataRecordValue::IPv4(_) => "ipv4",
+            DataRecordValue::IPv6(_) => "ipv6",
+            DataRecordValue::U8(_) => "u8",
+            DataRecordValue::U16(_) => "u16",
+            DataRecordValue::U32(_) => "u32",
+            DataRecordValue::U64(_) => "u64",
+            DataRecordValue::String(_) => "string",
+            DataRecordValue::Bytes(_) => "bytes",
+            DataRecordValue::MPLS(..) => "mpls",
+            DataRecordValue::Err(..) => "error",
+            DataRecordValue::Empty => "empty",
+        }
+    }
+}
+
+impl<'a> fmt::Display for DataRecordKey<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataRecordKey::Str(name) => write!(f, "{}", name),
+            DataRecordKey::Unrecognized(id) => write!(f, "{}", id),
+            DataRecordKey::Err(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<'a> fmt::Display for DataRecordValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataRecordValue::IPv4(ip) => write!(f, "{}", ip),
+            DataRecordValue::IPv6(ip) => write!(f, "{}", ip),
+            DataRecordValue::U8(v) => write!(f, "{}", v),
+            DataRecordValue::U16(v) => write!(f, "{}", v),
+            DataRecordValue::U32(v) => write!(f, "{}", v),
+            DataRecordValue::U64(v) => write!(f, "{}", v),
+            DataRecordValue::String(ref s) => write!(f, "{}", s),
+            DataRecordValue::Bytes(b) => {
+                write!(f, "0x")?;
+                for byte in b {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            DataRecordValue::MPLS(label, exp, bottom) => write!(f, "{}/{}/{}", label, exp, bottom),
+            DataRecordValue::Err(ref e, _) => write!(f, "error: {}", e),
+            DataRecordValue::Empty => Ok(()),
+        }
+    }
+}
+
 impl<'a> DataRecord<'a> {
+    /// get a value by its field name
+    pub fn get<'s>(&'s self, name: &'s str) -> Option<&'s DataRecordValue<'a>> {
+        let values: &HashMap<DataRecordKey, DataRecordValue<'a>> = &self.values;
+        values.get(&DataRecordKey::Str(name))
+    }
+
+    /// get the value of an information element, keyed by its name or, for
+    /// elements without a formatter, by its id
+    pub fn get_ie(&self, ie: ie::InformationElement) -> Option<&DataRecordValue<'a>> {
+        self.get(ie.name)
+            .or_else(|| self.values.get(&DataRecordKey::Unrecognized(ie.id)))
+    }
+
+    /// get an unsigned integer value, widened from any smaller encoding
+    pub fn get_u64(&self, ie: ie::InformationElement) -> Result<u64, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get an unsigned integer value that fits 32 bits
+    pub fn get_u32(&self, ie: ie::InformationElement) -> Result<u32, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get an unsigned integer value that fits 16 bits
+    pub fn get_u16(&self, ie: ie::InformationElement) -> Result<u16, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get an unsigned integer value that fits 8 bits
+    pub fn get_u8(&self, ie: ie::InformationElement) -> Result<u8, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get an IPv4 address, also accepted as a 32 bit integer
+    pub fn get_ipv4(&self, ie: ie::InformationElement) -> Result<Ipv4Addr, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get an IPv6 address, also accepted as 16 bytes
+    pub fn get_ipv6(&self, ie: ie::InformationElement) -> Result<Ipv6Addr, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get an address of either family
+    pub fn get_ip(&self, ie: ie::InformationElement) -> Result<IpAddr, ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get a MAC address, also accepted as a 48 bit integer
+    pub fn get_mac(&self, ie: ie::InformationElement) -> Result<[u8; 6], ValueError> {
+        self.get_typed(ie)
+    }
+
+    /// get a string value
+    pub fn get_str(&self, ie: ie::InformationElement) -> Result<&str, ValueError> {
+        let value = self.value(ie)?;
+        value
+            .as_str()
+            .ok_or_else(|| record::mismatch(ie.name, "string", value))
+    }
+
+    /// get the bytes of an octet array value
+    pub fn get_bytes(&self, ie: ie::InformationElement) -> Result<&'a [u8], ValueError> {
+        match *self.value(ie)? {
+            DataRecordValue::Bytes(b) => Ok(b),
+            ref value => Err(record::mismatch(ie.name, "bytes", value)),
+        }
+    }
+
+    /// get a value converted by `record::FromValue`
+    pub fn get_typed<T: record::FromValue>(
+        &self,
+        ie: ie::InformationElement,
+    ) -> Result<T, ValueError> {
+        self.value(ie).and_then(|v| T::from_value(ie.name, v))
+    }
+
+    fn value(&self, ie: ie::InformationElement) -> Result<&DataRecordValue<'a>, ValueError> {
+        self.get_ie(ie).ok_or(ValueError::Missing { name: ie.name })
+    }
+
     /// json serialize the DataRecord
     pub fn to_json(&self) -> serde_json::Result<String> {
         serde_json::to_string(&self)
     }
 }
 
+// the key a field of an enterprise (0 for IANA) is stored under in records
+fn record_key(
+    enterprise_parsers: &formatter::EnterpriseFormatter,
+    pen: u32,
+    field_id: u16,
+) -> DataRecordKey<'static> {
+    match enterprise_parsers.get(&pen) {
+        Some(value_parsers) => match value_parsers.get(&field_id) {
+            Some((field_name, _)) => DataRecordKey::Str(field_name),
+            None => DataRecordKey::Unrecognized(field_id),
+        },
+        None => DataRecordKey::Err(format!(
+            "unsupported pen {} when trying to parse field {}",
+            pen, field_id
+        )),
+    }
+}
+
 impl<'a> DataSet<'a> {
-    // Given DataRecord values (field_id, (field_buffer, enterprise_number)) apply enterprise formatter on it
-    // returning a datarecord key value map
+    // Given DataRecord values (field_id, field_buffer, enterprise_number) apply enterprise formatter on it
+    // returning a datarecord key value map, fields sharing a key keep the last value
     fn enrich_fields(
-        values: &HashMap<u16, (&'a [u8], u32)>,
+        values: &[(u16, &'a [u8], u32)],
         enterprise_parsers: &formatter::EnterpriseFormatter,
     ) -> HashMap<DataRecordKey<'a>, DataRecordValue<'a>> {
-        let hs = values
+        values
             .iter()
-            .map(
-                |(field_id, (val_bytes, pen))| -> (DataRecordKey, DataRecordValue) {
-                    match enterprise_parsers.get(pen) {
-                        Some(value_parsers) => {
-                            match value_parsers.get(field_id) {
-                                Some((field_name, field_parser)) => {
-                                    let parsed_val = field_parser(val_bytes);
-                                    (DataRecordKey::Str(field_name), parsed_val)
-                                }
-                                None => {
-                                    // recognized pen but unrecognized field parser
-                                    (
-                                        DataRecordKey::Unrecognized(*field_id),
-                                        DataRecordValue::Bytes(*val_bytes),
-                                    )
-                                }
-                            }
-                        }
-                        None => {
-                            // unrecognized pen
-                            (
-                                DataRecordKey::Err(format!(
-                                    "unsupported pen {} when trying to parse field {}",
-                                    pen, field_id
-                                )),
-                                DataRecordValue::Empty,
-                            )
-                        }
+            .map(|(field_id, val_bytes, pen)| {
+                match enterprise_parsers.get(pen).and_then(|m| m.get(field_id)) {
+                    Some((field_name, field_parser)) => {
+                        (DataRecordKey::Str(field_name), field_parser(val_bytes))
                     }
-                },
-            )
-            .collect();
-
-        hs
+                    None => match record_key(enterprise_parsers, *pen, *field_id) {
+                        // recognized pen but unrecognized field parser
+                        key @ DataRecordKey::Unrecognized(_) => {
+                            (key, DataRecordValue::Bytes(val_bytes))
+                        }
+                        // unrecognized pen
+                        key => (key, DataRecordValue::Empty),
+                    },
+                }
+            })
+            .collect()
     }
 
     // take a field from input given it's size, and handle variable lengths sec7
-    fn take_field(input: &'a [u8], field_size: u16) -> nom::IResult<&'a [u8], &'a [u8]> {
+    pub(crate) fn take_field(input: &'a [u8], field_size: u16) -> nom::IResult<&'a [u8], &'a [u8]> {
         if field_size == 65535 {
             let (rest, actual_size) = call!(input, be_u8)?;
+            if actual_size == 255 {
+                // lengths of 255 bytes and over use a 3 bytes prefix
+                let (rest, actual_size) = call!(rest, be_u16)?;
+                return take!(rest, actual_size);
+            }
             take!(rest, actual_size)
         } else {
             take!(input, field_size)
@@ -229,17 +573,17 @@ impl<'a> DataSet<'a> {
     }
 
     // based on `takes` which is a vector of tuples (field_id, field_size, enterprise_number) do `take_field`
-    // returns a hashmap of <field_id, (field_buffer, enterprise_number)>
+    // returns a vector of (field_id, field_buffer, enterprise_number) in template order
     fn take_fields(
         input: &'a [u8],
         takes: Vec<(u16, u16, u32)>,
-    ) -> nom::IResult<&[u8], HashMap<u16, (&'a [u8], u32)>> {
-        let mut values = HashMap::<u16, (&[u8], u32)>::default();
+    ) -> nom::IResult<&'a [u8], Vec<(u16, &'a [u8], u32)>> {
+        let mut values = Vec::with_capacity(takes.len());
         let mut rest = input;
         for (field_ident, field_size, enterprise_number) in takes {
             let (more, field_buf) = Self::take_field(&rest, field_size)?;
             rest = more;
-            values.insert(field_ident, (field_buf, enterprise_number));
+            values.push((field_ident, field_buf, enterprise_number));
         }
         Ok((rest, values))
     }
@@ -248,17 +592,24 @@ impl<'a> DataSet<'a> {
         input: &'a [u8],
         length: u16,
         set_id: u16,
+        domain: u32,
         value_parsers: &formatter::EnterpriseFormatter,
         state: &state::State,
     ) -> nom::IResult<&'a [u8], DataSet<'a>> {
         let mut temp_buf = input;
 
-        if let Some(template) = state.get_template(&set_id) {
+        if let Some(template) = state.get_domain_template(domain, &set_id) {
             // So a dataset consisit of multiple "records"
             // each records is a bunch of fields, so we need
             // to apply the template on dataset multiple times if required.
             let mut records = Vec::new();
             while !temp_buf.is_empty() {
+                if records.len() >= state.get_limits().max_records_per_set {
+                    return Err(nom::Err::Failure(make_error(
+                        temp_buf,
+                        nom::error::ErrorKind::TooLarge,
+                    )));
+                }
                 // generate a vector of tuples that represent field information to extract
                 let takes = template
                     .field_specifiers
@@ -276,7 +627,6 @@ impl<'a> DataSet<'a> {
                         temp_buf = rest;
                         // push the record with enriched fields
                         records.push(DataRecord {
-                            // TODO : parsing fields doesn't respect PEN
                             values: Self::enrich_fields(&values, value_parsers),
                         });
                     }
@@ -291,14 +641,22 @@ impl<'a> DataSet<'a> {
                 DataSet {
                     header: SetHeader { set_id, length },
                     records,
+                    is_options: false,
+                    trailing: temp_buf,
                 },
             ))
-        } else if let Some(template) = state.get_options_template(&set_id) {
+        } else if let Some(template) = state.get_domain_options_template(domain, &set_id) {
             // So a dataset consisit of multiple "records"
             // each records is a bunch of fields, so we need
             // to apply the template on dataset multiple times if required.
             let mut records = Vec::new();
             while !temp_buf.is_empty() {
+                if records.len() >= state.get_limits().max_records_per_set {
+                    return Err(nom::Err::Failure(make_error(
+                        temp_buf,
+                        nom::error::ErrorKind::TooLarge,
+                    )));
+                }
                 // generate a vector of tuples that represent field information to extract
                 let takes = template
                     .field_specifiers
@@ -316,7 +674,6 @@ impl<'a> DataSet<'a> {
                         temp_buf = rest;
                         // push the record with enriched fields
                         records.push(DataRecord {
-                            // TODO : parsing fields doesn't respect PEN
                             values: Self::enrich_fields(&values, value_parsers),
                         });
                     }
@@ -331,6 +688,8 @@ impl<'a> DataSet<'a> {
                 DataSet {
                     header: SetHeader { set_id, length },
                     records,
+                    is_options: true,
+                    trailing: temp_buf,
                 },
             ))
         } else {
@@ -354,6 +713,18 @@ impl<'a> Message<'a> {
             .flatten()
             .collect::<Vec<&DataRecord<'_>>>()
     }
+
+    /// get the records that were decoded using an options template
+    /// if none exists an empty vector is returned.
+    pub fn get_options_records(&self) -> Vec<&DataRecord<'_>> {
+        self.sets
+            .iter()
+            .filter(|set| set.stype == SetType::DataSet)
+            .flat_map(|e| &e.data)
+            .filter(|e| e.is_options)
+            .flat_map(|e| &e.records)
+            .collect::<Vec<&DataRecord<'_>>>()
+    }
 }
 
 impl<'a> Set<'a> {
@@ -388,6 +759,8 @@ impl<'a> Set<'a> {
                 stype: hdr.set_id.into(),
                 hdr,
                 buf: left_bytes,
+                templates: Vec::new(),
+                options_templates: Vec::new(),
                 data: Vec::new(),
             },
         ))
@@ -399,10 +772,11 @@ impl<'a> Set<'a> {
 
     // parse and process the set buffer based on its type
     // and update the respective data structure with the parsed data.
-    fn process_set_body(
+    pub(crate) fn process_set_body(
         &mut self,
         fmts: &formatter::EnterpriseFormatter,
         state: &mut state::State,
+        domain: u32,
     ) -> Result<()> {
         match self.stype {
             SetType::DataSet => {
@@ -410,26 +784,31 @@ impl<'a> Set<'a> {
                     self.buf,
                     self.length().unwrap_or(0) as u16,
                     self.hdr.set_id,
+                    domain,
                     fmts,
                     &state,
                 )
-                .map_err(|e| anyhow!("failed parsing dataset : {}", e))?;
+                .map_err(|e| dataset_error(e, self.hdr.set_id, state.get_limits()))?;
                 self.data.push(ds);
                 Ok(())
             }
             SetType::OptionTemplate => {
+                check_field_counts(self.buf, 6, state.get_limits())?;
                 let (_, tv) = OptionsTemplate::parse_many(self.buf)
                     .map_err(|e| anyhow!("failed parsing options templates : {}", e))?;
                 for ts in tv {
-                    state.add_options_template(ts.header.id, ts);
+                    state.add_options_template(domain, ts.header.id, ts.clone())?;
+                    self.options_templates.push(ts);
                 }
                 Ok(())
             }
             SetType::Template => {
+                check_field_counts(self.buf, 4, state.get_limits())?;
                 let (_, tv) = Template::parse_many(self.buf)
                     .map_err(|e| anyhow!("failed parsing templates : {}", e))?;
                 for ts in tv {
-                    state.add_template(ts.header.template_id, ts);
+                    state.add_template(domain, ts.header.template_id, ts.clone())?;
+                    self.templates.push(ts);
                 }
                 Ok(())
             }
@@ -442,6 +821,7 @@ impl<'a> Set<'a> {
         &mut self,
         fmts: &formatter::EnterpriseFormatter,
         state: Arc<RwLock<state::State>>,
+        domain: u32,
     ) -> Result<()> {
         match self.stype {
             SetType::DataSet => {
@@ -453,33 +833,38 @@ impl<'a> Set<'a> {
                     self.buf,
                     self.length().unwrap_or(0) as u16,
                     self.hdr.set_id,
+                    domain,
                     fmts,
                     &s,
                 )
-                .map_err(|e| anyhow!("failed parsing dataset : {}", e))?;
+                .map_err(|e| dataset_error(e, self.hdr.set_id, s.get_limits()))?;
 
                 self.data.push(ds);
                 Ok(())
             }
             SetType::OptionTemplate => {
+                check_field_counts(self.buf, 6, &read_limits(&state)?)?;
                 let (_, tv) = OptionsTemplate::parse_many(self.buf)
                     .map_err(|e| anyhow!("failed parsing options templates : {}", e))?;
                 let mut s = state
                     .write()
                     .map_err(|e| anyhow!("failed to obtain read lock on state : {}", e))?;
                 for ts in tv {
-                    s.add_options_template(ts.header.id, ts);
+                    s.add_options_template(domain, ts.header.id, ts.clone())?;
+                    self.options_templates.push(ts);
                 }
                 Ok(())
             }
             SetType::Template => {
+                check_field_counts(self.buf, 4, &read_limits(&state)?)?;
                 let (_, tv) = Template::parse_many(self.buf)
                     .map_err(|e| anyhow!("failed parsing templates : {}", e))?;
                 let mut s = state
                     .write()
                     .map_err(|e| anyhow!("failed to obtain write lock on state : {}", e))?;
                 for ts in tv {
-                    s.add_template(ts.header.template_id, ts);
+                    s.add_template(domain, ts.header.template_id, ts.clone())?;
+                    self.templates.push(ts);
                 }
                 Ok(())
             }
@@ -495,6 +880,62 @@ impl<'a> Set<'a> {
     }
 }
 
+// check the field counts of the template records in a set before they're
+// parsed, so a template can't make the parser allocate beyond the limits.
+fn check_field_counts(buf: &[u8], header_len: usize, limits: &state::Limits) -> Result<()> {
+    let mut rest = buf;
+    while rest.len() >= header_len {
+        let id = u16::from_be_bytes([rest[0], rest[1]]);
+        let count = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        if count > limits.max_fields_per_template {
+            return Err(state::LimitError(format!(
+                "template {} has {} fields, at most {} are allowed",
+                id, count, limits.max_fields_per_template
+            ))
+            .into());
+        }
+        rest = &rest[header_len..];
+        for _ in 0..count {
+            // enterprise specific fields carry a 4 bytes enterprise number
+            let length = match rest.first() {
+                Some(b) if b & 0x80 != 0 => 8,
+                Some(_) => 4,
+                None => return Ok(()),
+            };
+            if rest.len() < length {
+                return Ok(());
+            }
+            rest = &rest[length..];
+        }
+    }
+    Ok(())
+}
+
+// data set parsing errors, keeping limit violations apart from malformed data
+fn dataset_error(
+    e: nom::Err<nom::error::Error<&[u8]>>,
+    set_id: u16,
+    limits: &state::Limits,
+) -> anyhow::Error {
+    match e {
+        nom::Err::Failure(ref err) if err.code == nom::error::ErrorKind::TooLarge => {
+            state::LimitError(format!(
+                "data set {} has more than {} records",
+                set_id, limits.max_records_per_set
+            ))
+            .into()
+        }
+        e => anyhow!("failed parsing dataset : {}", e),
+    }
+}
+
+fn read_limits(state: &RwLock<state::State>) -> Result<state::Limits> {
+    let s = state
+        .read()
+        .map_err(|e| anyhow!("failed to obtain read lock on state : {}", e))?;
+    Ok(*s.get_limits())
+}
+
 impl Default for Parser {
     fn default() -> Self {
         Self::new()
@@ -508,9 +949,25 @@ impl Parser {
         enterprise_formatters.insert(0, formatter::get_default_parsers());
         Self {
             pen_formatter: enterprise_formatters,
+            mode: Mode::Lenient,
         }
     }
 
+    /// create a new parser in the given mode
+    pub fn with_mode(mode: Mode) -> Self {
+        let mut parser = Self::new();
+        parser.mode = mode;
+        parser
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
     /// add custom fields for formatting to support custom fields
     pub fn add_custom_field(
         &mut self,
@@ -526,8 +983,60 @@ impl Parser {
         m.insert(field_id, (name, parser));
     }
 
+    /// the key the values of a field are stored under in data records
+    pub fn record_key(&self, enterprise_number: u32, field_id: u16) -> DataRecordKey<'static> {
+        record_key(&self.pen_formatter, enterprise_number, field_id)
+    }
+
+    /// get the name of a field as known to the formatters
+    pub fn get_field_name(&self, enterprise_number: u32, field_id: u16) -> Option<&'static str> {
+        self.pen_formatter
+            .get(&enterprise_number)
+            .and_then(|m| m.get(&field_id))
+            .map(|(name, _)| *name)
+    }
+
+    /// column names of the fields of a template in order, named as known to
+    /// the formatters, `field_<id>` or `field_<pen>_<id>` otherwise. Names
+    /// used more than once get a `_2`, `_3`... suffix.
+    pub fn field_names(&self, fields: &[FieldSpecifier]) -> Vec<String> {
+        let mut seen = HashMap::<String, usize>::default();
+        fields
+            .iter()
+            .map(|fs| {
+                let pen = fs.enterprise_number.unwrap_or(0);
+                let name = match self.get_field_name(pen, fs.ident) {
+                    Some(name) => name.to_string(),
+                    None if pen == 0 => format!("field_{}", fs.ident),
+                    None => format!("field_{}_{}", pen, fs.ident),
+                };
+                let count = seen.entry(name.clone()).or_insert(0);
+                *count += 1;
+                match *count {
+                    1 => name,
+                    n => format!("{}_{}", name, n),
+                }
+            })
+            .collect()
+    }
+
+    /// decode the value of a field with its formatter, `None` for unknown fields
+    pub fn decode_field<'a>(
+        &self,
+        enterprise_number: u32,
+        field_id: u16,
+        buf: &'a [u8],
+    ) -> Option<(&'static str, DataRecordValue<'a>)> {
+        self.pen_formatter
+            .get(&enterprise_number)
+            .and_then(|m| m.get(&field_id))
+            .map(|(name, parser)| (*name, parser(buf)))
+    }
+
     /// similar to `parse_message` except it takes a thread-safe state
-    /// can be used for concurrent processing.
+    /// can be used for concurrent processing. Only template sets take the
+    /// write lock, so the statistics of the shared state are left alone:
+    /// each thread counts its messages with `stats::Stats::count`.
     pub fn parse_message_async<'a>(
         &'a self,
         state: Arc<RwLock<state::State>>,
@@ -539,14 +1048,25 @@ impl Parser {
         let (body, mut parsed) = Message::parse(input)
             .map_err(|e| anyhow!("failed while parsing ipfix header : {:?}", e))?;
 
-        let (_, sets) =
+        let (rest, sets) =
             Set::parse_many(&body).map_err(|e| anyhow!("failed while extracting sets {:?}", e))?;
         parsed.sets = sets;
+        if self.mode == Mode::Strict {
+            check_message(&parsed, input, rest)?;
+        }
 
         // parse sets with async state updates
+        let domain = parsed.observation_domain_id;
         for set in &mut parsed.sets {
-            match set.process_set_body_async(&self.pen_formatter, state.clone()) {
+            if self.mode == Mode::Strict {
+                let s = state
+                    .read()
+                    .map_err(|e| anyhow!("failed to obtain read lock on state : {}", e))?;
+                self.check_set(set, &s, domain)?;
+            }
+            match set.process_set_body_async(&self.pen_formatter, state.clone(), domain) {
                 Ok(()) => {}
+                Err(err) if err.is::<state::LimitError>() => return Err(err),
                 Err(_err) => {
                     // TODO : handle
                 }
@@ -562,19 +1082,37 @@ impl Parser {
         state: &mut state::State,
         input: &'a [u8],
     ) -> Result<Message> {
+        let result = self.decode_message(state, input);
+        state.add_message(input.len(), &result);
+        result
+    }
+
+    fn decode_message<'a>(
+        &'a self,
+        state: &mut state::State,
+        input: &'a [u8],
+    ) -> Result<Message<'a>> {
         // this should be 1:1 with UDP datagrams
         // we aren't currently using any of the data from the ipfix message header but we still
         // need to chop it off
         let (body, mut parsed) = Message::parse(input)
             .map_err(|e| anyhow!("failed while parsing ipfix header : {:?}", e))?;
 
-        let (_, sets) =
+        let (rest, sets) =
             Set::parse_many(&body).map_err(|e| anyhow!("failed while extracting sets {:?}", e))?;
         parsed.sets = sets;
+        if self.mode == Mode::Strict {
+            check_message(&parsed, input, rest)?;
+        }
 
+        let domain = parsed.observation_domain_id;
         for set in &mut parsed.sets {
-            match set.process_set_body(&self.pen_formatter, state) {
+            if self.mode == Mode::Strict {
+                self.check_set(set, state, domain)?;
+            }
+            match set.process_set_body(&self.pen_formatter, state, domain) {
                 Ok(()) => {}
+                Err(err) if err.is::<state::LimitError>() => return Err(err),
                 Err(_err) => {
                     // TODO : handle
                 }
@@ -583,4 +1121,181 @@ impl Parser {
 
         Ok(parsed)
     }
+
+    /// look for the violations the strict parser rejects in `msg`, decoded
+    /// leniently from `input`, returning the first one. Data sets are
+    /// checked with the templates of `state` once `msg` was decoded with it,
+    /// those decoded without a template are skipped as the strict parser
+    /// does. This spares decoding a message twice to both check and decode it.
+    pub fn check_violations(
+        &self,
+        state: &state::State,
+        msg: &Message,
+        input: &[u8],
+    ) -> Result<()> {
+        let (body, _) = Message::parse(input)
+            .map_err(|e| anyhow!("failed while parsing ipfix header : {:?}", e))?;
+        let (rest, _) =
+            Set::parse_many(&body).map_err(|e| anyhow!("failed while extracting sets {:?}", e))?;
+        check_message(msg, input, rest)?;
+
+        for set in &msg.sets {
+            if set.stype == SetType::DataSet && set.hdr.set_id > 255 && set.data.is_empty() {
+                continue;
+            }
+            self.check_set(set, state, msg.observation_domain_id)?;
+        }
+        Ok(())
+    }
+
+    // check a set for violations before it's processed, so a violating
+    // template set never makes it into the state.
+    fn check_set(&self, set: &Set, state: &state::State, domain: u32) -> Result<()> {
+        let set_id = set.hdr.set_id;
+        if set_id < 2 || (4..256).contains(&set_id) {
+            return Err(Violation::ReservedSetId { set_id }.into());
+        }
+
+        match set.stype {
+            SetType::Template => {
+                check_field_counts(set.buf, 4, state.get_limits())?;
+                let (rest, tv) = Template::parse_many(set.buf)
+                    .map_err(|e| anyhow!("failed parsing templates : {}", e))?;
+                for t in &tv {
+                    check_template(set_id, t.header.template_id, &t.field_specifiers)?;
+                }
+                check_padding(set_id, rest, 4)
+            }
+            SetType::OptionTemplate => {
+                check_field_counts(set.buf, 6, state.get_limits())?;
+                let (rest, tv) = OptionsTemplate::parse_many(set.buf)
+                    .map_err(|e| anyhow!("failed parsing options templates : {}", e))?;
+                for t in &tv {
+                    check_template(set_id, t.header.id, &t.field_specifiers)?;
+                    let h = &t.header;
+                    if h.scope_field_count == 0 || h.scope_field_count > h.field_count {
+                        return Err(Violation::ScopeFieldCount {
+                            template_id: h.id,
+                            scope_field_count: h.scope_field_count,
+                            field_count: h.field_count,
+                        }
+                        .into());
+                    }
+                }
+                check_padding(set_id, rest, 6)
+            }
+            SetType::DataSet => {
+                let fields = match (
+                    state.get_domain_template(domain, &set_id),
+                    state.get_domain_options_template(domain, &set_id),
+                ) {
+                    (Some(t), _) => &t.field_specifiers,
+                    (None, Some(t)) => &t.field_specifiers,
+                    // skipped until the template shows up
+                    (None, None) => return Ok(()),
+                };
+                self.check_records(set_id, set.buf, fields)
+            }
+        }
+    }
+
+    // walk the records of a data set, making sure they fit the set exactly
+    fn check_records(&self, set_id: u16, buf: &[u8], fields: &[FieldSpecifier]) -> Result<()> {
+        for fs in fields {
+            let pen = fs.enterprise_number.unwrap_or(0);
+            if !self.pen_formatter.contains_key(&pen) {
+                return Err(Violation::UnknownPen {
+                    set_id,
+                    pen,
+                    ident: fs.ident,
+                }
+                .into());
+            }
+        }
+        let min_len = fields
+            .iter()
+            .map(|fs| {
+                if fs.field_length == 65535 {
+                    1
+                } else {
+                    fs.field_length as usize
+                }
+            })
+            .sum::<usize>();
+        if min_len == 0 {
+            return Ok(());
+        }
+
+        let mut rest = buf;
+        while rest.len() >= min_len {
+            let mut record = rest;
+            for fs in fields {
+                match DataSet::take_field(record, fs.field_length) {
+                    Ok((more, _)) => record = more,
+                    Err(_) => {
+                        return Err(Violation::TruncatedRecord {
+                            set_id,
+                            length: rest.len(),
+                        }
+                        .into())
+                    }
+                }
+            }
+            rest = record;
+        }
+        // zeros shorter than a record are padding, anything else was cut short
+        if rest.iter().any(|b| *b != 0) {
+            return Err(Violation::TruncatedRecord {
+                set_id,
+                length: rest.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+// check the message header against what was received
+fn check_message(msg: &Message, input: &[u8], rest: &[u8]) -> Result<()> {
+    if msg.length as usize != input.len() {
+        return Err(Violation::MessageLength {
+            header: msg.length,
+            actual: input.len(),
+        }
+        .into());
+    }
+    if !rest.is_empty() {
+        return Err(Violation::TrailingBytes { length: rest.len() }.into());
+    }
+    Ok(())
+}
+
+fn check_template(set_id: u16, template_id: u16, fields: &[FieldSpecifier]) -> Result<()> {
+    if template_id < 256 {
+        return Err(Violation::TemplateId {
+            set_id,
+            template_id,
+        }
+        .into());
+    }
+    match fields.iter().find(|fs| fs.field_length == 0) {
+        Some(fs) => Err(Violation::ZeroLengthField {
+            template_id,
+            ident: fs.ident,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+// padding is made of zeros and shorter than the smallest record
+fn check_padding(set_id: u16, padding: &[u8], record_len: usize) -> Result<()> {
+    if padding.len() >= record_len || padding.iter().any(|b| *b != 0) {
+        return Err(Violation::Padding {
+            set_id,
+            length: padding.len(),
+        }
+        .into());
+    }
+    Ok(())
 }
@@ -0,0 +1,595 @@
+use anyhow::{anyhow, Result};
+use parser;
+use rustc_hash::FxHashMap as HashMap;
+use state;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Read;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use stream::{self, read_full};
+
+/// IANA assigned IPFIX port
+pub const IPFIX_PORT: u16 = 4739;
+
+// packets larger than this are considered a corrupt capture
+const MAX_PACKET_LEN: usize = 256 * 1024;
+// bytes of out of order TCP segments buffered per stream before giving up on them
+const MAX_PENDING_BYTES: usize = 1 << 20;
+// TCP streams reassembled at once, the least recently active one is dropped
+// to make room for a new one
+const MAX_STREAMS: usize = 4096;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// transport protocol a message was captured on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+/// an IPFIX message extracted from a capture
+#[derive(Debug, Clone)]
+pub struct Datagram {
+    /// capture time since the UNIX epoch
+    pub timestamp: Duration,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub transport: Transport,
+    /// the UDP payload or a message reassembled from the TCP stream
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        le: bool,
+        nanos: bool,
+        linktype: u32,
+    },
+    PcapNg {
+        le: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+#[derive(Debug)]
+struct Interface {
+    linktype: u32,
+    // timestamp units per second, from if_tsresol
+    resolution: u64,
+}
+
+#[derive(Debug, Default)]
+struct TcpStream {
+    next_seq: Option<u32>,
+    pending: BTreeMap<u32, Vec<u8>>,
+    // bytes held by `pending`
+    pending_len: usize,
+    buf: Vec<u8>,
+    // capture time of the last segment
+    last_seen: Duration,
+}
+
+/// Reader walks a pcap or pcapng capture and extracts the IPFIX messages sent over
+/// UDP and TCP to the configured destination ports. TCP streams are reassembled
+/// and split into messages, IP fragments are skipped.
+pub struct Reader<R> {
+    inner: R,
+    format: Format,
+    ports: Vec<u16>,
+    buf: Vec<u8>,
+    streams: HashMap<(SocketAddr, SocketAddr), TcpStream>,
+    ready: VecDeque<Datagram>,
+}
+
+impl<R: Read> Reader<R> {
+    /// create a reader extracting messages sent to the IPFIX port
+    pub fn new(inner: R) -> Result<Self> {
+        Self::with_ports(inner, &[IPFIX_PORT])
+    }
+
+    /// create a reader extracting messages sent to any of `ports`
+    pub fn with_ports(mut inner: R, ports: &[u16]) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        if read_full(&mut inner, &mut magic)? < magic.len() {
+            return Err(anyhow!("capture too short"));
+        }
+
+        let format = match u32::from_le_bytes(magic) {
+            PCAPNG_SECTION_HEADER => read_section_header(&mut inner)?,
+            _ => read_pcap_header(&mut inner, magic)?,
+        };
+
+        Ok(Self {
+            inner,
+            format,
+            ports: ports.to_vec(),
+            buf: Vec::new(),
+            streams: HashMap::default(),
+            ready: VecDeque::new(),
+        })
+    }
+
+    /// get the next message from the capture, returns `None` at the end of the capture
+    pub fn next_datagram(&mut self) -> Result<Option<Datagram>> {
+        loop {
+            if let Some(datagram) = self.ready.pop_front() {
+                return Ok(Some(datagram));
+            }
+            let (timestamp, linktype, start, end) = match self.read_packet()? {
+                Some(packet) => packet,
+                None => return Ok(None),
+            };
+            let buf = mem::take(&mut self.buf);
+            self.process_packet(timestamp, linktype, &buf[start..end]);
+            self.buf = buf;
+        }
+    }
+
+    // read the next packet into `buf`, returning its timestamp, link type and
+    // position in `buf`. A truncated packet at the end of the capture ends it.
+    fn read_packet(&mut self) -> Result<Option<(Duration, u32, usize, usize)>> {
+        loop {
+            match self.format {
+                Format::Pcap {
+                    le,
+                    nanos,
+                    linktype,
+                } => {
+                    let mut header = [0u8; 16];
+                    if read_full(&mut self.inner, &mut header)? < header.len() {
+                        return Ok(None);
+                    }
+                    let seconds = read_u32(&header, 0, le);
+                    let fraction = read_u32(&header, 4, le);
+                    let captured = read_u32(&header, 8, le) as usize;
+                    if captured > MAX_PACKET_LEN {
+                        return Err(anyhow!("invalid pcap record length {}", captured));
+                    }
+                    self.buf.resize(captured, 0);
+                    if read_full(&mut self.inner, &mut self.buf)? < captured {
+                        return Ok(None);
+                    }
+                    let nanos = if nanos {
+                        fraction
+                    } else {
+                        fraction.saturating_mul(1000)
+                    };
+                    let timestamp = Duration::new(u64::from(seconds), nanos);
+                    return Ok(Some((timestamp, linktype, 0, captured)));
+                }
+                Format::PcapNg { le, .. } => {
+                    let mut header = [0u8; 8];
+                    if read_full(&mut self.inner, &mut header)? < header.len() {
+                        return Ok(None);
+                    }
+                    if read_u32(&header, 0, le) == PCAPNG_SECTION_HEADER {
+                        // a new section may switch byte order and resets interfaces
+                        let mut magic = [0u8; 4];
+                        magic.copy_from_slice(&header[4..]);
+                        let mut chain = (&magic[..]).chain(&mut self.inner);
+                        self.format = read_section_header(&mut chain)?;
+                        continue;
+                    }
+                    if let Some(packet) = self.read_block(le, &header)? {
+                        return Ok(Some(packet));
+                    }
+                }
+            }
+        }
+    }
+
+    // read a pcapng block body, interface descriptions are recorded and
+    // packets returned.
+    fn read_block(
+        &mut self,
+        le: bool,
+        header: &[u8],
+    ) -> Result<Option<(Duration, u32, usize, usize)>> {
+        let block_type = read_u32(header, 0, le);
+        let length = read_u32(header, 4, le) as usize;
+        if length < 12 || length & 3 != 0 || length > MAX_PACKET_LEN {
+            return Err(anyhow!("invalid pcapng block length {}", length));
+        }
+        self.buf.resize(length - 8, 0);
+        if read_full(&mut self.inner, &mut self.buf)? < length - 8 {
+            // truncated block, nothing more to read
+            self.buf.clear();
+            return Ok(None);
+        }
+        // trailing block length
+        let body_len = length - 12;
+
+        let interfaces = match self.format {
+            Format::PcapNg {
+                ref mut interfaces, ..
+            } => interfaces,
+            Format::Pcap { .. } => return Ok(None),
+        };
+
+        match block_type {
+            // interface description block
+            1 if body_len >= 8 => {
+                interfaces.push(Interface {
+                    linktype: u32::from(read_u16(&self.buf, 0, le)),
+                    resolution: read_resolution(&self.buf[8..body_len], le),
+                });
+                Ok(None)
+            }
+            // enhanced packet block
+            6 if body_len >= 20 => {
+                let interface = match interfaces.get(read_u32(&self.buf, 0, le) as usize) {
+                    Some(interface) => interface,
+                    None => return Ok(None),
+                };
+                let ts = (u64::from(read_u32(&self.buf, 4, le)) << 32)
+                    | u64::from(read_u32(&self.buf, 8, le));
+                let captured = read_u32(&self.buf, 12, le) as usize;
+                if 20 + captured > body_len {
+                    return Ok(None);
+                }
+                let timestamp = Duration::new(
+                    ts / interface.resolution,
+                    ((u128::from(ts % interface.resolution) * 1_000_000_000)
+                        / u128::from(interface.resolution)) as u32,
+                );
+                Ok(Some((timestamp, interface.linktype, 20, 20 + captured)))
+            }
+            // simple packet block, no timestamp and always the first interface
+            3 if body_len >= 4 => {
+                let linktype = match interfaces.first() {
+                    Some(interface) => interface.linktype,
+                    None => return Ok(None),
+                };
+                let captured = (read_u32(&self.buf, 0, le) as usize).min(body_len - 4);
+                Ok(Some((Duration::default(), linktype, 4, 4 + captured)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn process_packet(&mut self, timestamp: Duration, linktype: u32, packet: &[u8]) {
+        let ip = match link_payload(linktype, packet) {
+            Some(ip) => ip,
+            None => return,
+        };
+        let (src, dst, protocol, payload) = match ip_payload(ip) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+
+        match protocol {
+            17 if payload.len() >= 8 => {
+                let dst_port = read_u16(payload, 2, false);
+                if !self.ports.contains(&dst_port) {
+                    return;
+                }
+                let length = (read_u16(payload, 4, false) as usize).min(payload.len());
+                if length < 8 {
+                    return;
+                }
+                self.ready.push_back(Datagram {
+                    timestamp,
+                    source: SocketAddr::new(src, read_u16(payload, 0, false)),
+                    destination: SocketAddr::new(dst, dst_port),
+                    transport: Transport::Udp,
+                    payload: payload[8..length].to_vec(),
+                });
+            }
+            6 if payload.len() >= 20 => {
+                let dst_port = read_u16(payload, 2, false);
+                if !self.ports.contains(&dst_port) {
+                    return;
+                }
+                let offset = usize::from(payload[12] >> 4) * 4;
+                if offset < 20 || offset > payload.len() {
+                    return;
+                }
+                let source = SocketAddr::new(src, read_u16(payload, 0, false));
+                let destination = SocketAddr::new(dst, dst_port);
+                let seq = read_u32(payload, 4, false);
+                let flags = payload[13];
+                let (fin, syn, rst) = (flags & 0x01 != 0, flags & 0x02 != 0, flags & 0x04 != 0);
+
+                let key = (source, destination);
+                if !self.streams.contains_key(&key) && self.streams.len() >= MAX_STREAMS {
+                    let idlest = self
+                        .streams
+                        .iter()
+                        .min_by_key(|(_, stream)| stream.last_seen)
+                        .map(|(key, _)| *key);
+                    if let Some(idlest) = idlest {
+                        self.streams.remove(&idlest);
+                    }
+                }
+                let messages = {
+                    let stream = self.streams.entry(key).or_default();
+                    stream.last_seen = timestamp;
+                    stream.push(seq, syn, &payload[offset..]);
+                    stream.take_messages()
+                };
+                for payload in messages {
+                    self.ready.push_back(Datagram {
+                        timestamp,
+                        source,
+                        destination,
+                        transport: Transport::Tcp,
+                        payload,
+                    });
+                }
+                if fin || rst {
+                    self.streams.remove(&(source, destination));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Datagram>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_datagram() {
+            Ok(Some(datagram)) => Some(Ok(datagram)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl TcpStream {
+    // add a segment to the stream, out of order segments are kept until the gap is filled
+    fn push(&mut self, seq: u32, syn: bool, data: &[u8]) {
+        let seq = if syn { seq.wrapping_add(1) } else { seq };
+        let next = *self.next_seq.get_or_insert(seq);
+
+        if (seq.wrapping_sub(next) as i32) > 0 {
+            if !data.is_empty() && self.pending_len + data.len() <= MAX_PENDING_BYTES {
+                self.pending_len += data.len();
+                if let Some(old) = self.pending.insert(seq, data.to_vec()) {
+                    self.pending_len -= old.len();
+                }
+            }
+            return;
+        }
+        self.append_from(seq, data);
+
+        // drain the pending segments that became contiguous
+        loop {
+            let next = self.next_seq.unwrap_or(seq);
+            let key = match self
+                .pending
+                .keys()
+                .find(|k| (k.wrapping_sub(next) as i32) <= 0)
+            {
+                Some(key) => *key,
+                None => break,
+            };
+            if let Some(data) = self.pending.remove(&key) {
+                self.pending_len -= data.len();
+                self.append_from(key, &data);
+            }
+        }
+    }
+
+    // append the part of a segment starting at `seq` that wasn't seen yet
+    fn append_from(&mut self, seq: u32, data: &[u8]) {
+        let next = self.next_seq.unwrap_or(seq);
+        let seen = next.wrapping_sub(seq) as usize;
+        if seen < data.len() {
+            self.buf.extend_from_slice(&data[seen..]);
+            self.next_seq = Some(next.wrapping_add((data.len() - seen) as u32));
+        }
+    }
+
+    // split the complete messages off the stream buffer, bytes that can't be the
+    // start of a message are skipped to resync on streams captured midway.
+    fn take_messages(&mut self) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        loop {
+            let length = match stream::message_len(&self.buf) {
+                Ok(Some(length)) => length,
+                Ok(None) => break,
+                Err(_) => {
+                    let skip = self.buf[1..]
+                        .windows(2)
+                        .position(|w| w == [0, 10])
+                        .map(|p| p + 1)
+                        .unwrap_or(self.buf.len() - 1);
+                    self.buf.drain(..skip);
+                    continue;
+                }
+            };
+            if self.buf.len() < length {
+                break;
+            }
+            messages.push(self.buf.drain(..length).collect());
+        }
+        messages
+    }
+}
+
+/// parse a message extracted from a capture using the templates of its source
+pub fn parse_datagram<'a>(
+    parser: &'a parser::Parser,
+    sessions: &mut state::Sessions<SocketAddr>,
+    datagram: &'a Datagram,
+) -> Result<parser::Message<'a>> {
+    let state = sessions.get_or_insert(datagram.source)?;
+    parser.parse_message(state, &datagram.payload)
+}
+
+fn read_pcap_header<R: Read>(inner: &mut R, magic: [u8; 4]) -> Result<Format> {
+    let (le, nanos) = match u32::from_le_bytes(magic) {
+        0xa1b2_c3d4 => (true, false),
+        0xa1b2_3c4d => (true, true),
+        0xd4c3_b2a1 => (false, false),
+        0x4d3c_b2a1 => (false, true),
+        other => return Err(anyhow!("unknown capture magic {:#x}", other)),
+    };
+    let mut header = [0u8; 20];
+    if read_full(inner, &mut header)? < header.len() {
+        return Err(anyhow!("truncated pcap header"));
+    }
+    Ok(Format::Pcap {
+        le,
+        nanos,
+        linktype: read_u32(&header, 16, le),
+    })
+}
+
+// read the section header block following its block type
+fn read_section_header<R: Read>(inner: &mut R) -> Result<Format> {
+    let mut header = [0u8; 8];
+    if read_full(inner, &mut header)? < header.len() {
+        return Err(anyhow!("truncated pcapng section header"));
+    }
+    let le = match read_u32(&header, 4, true) {
+        PCAPNG_BYTE_ORDER_MAGIC => true,
+        _ if read_u32(&header, 4, false) == PCAPNG_BYTE_ORDER_MAGIC => false,
+        _ => return Err(anyhow!("invalid pcapng byte order magic")),
+    };
+    let length = read_u32(&header, 0, le) as usize;
+    if !(12..=MAX_PACKET_LEN).contains(&length) {
+        return Err(anyhow!("invalid pcapng block length {}", length));
+    }
+    // skip the rest of the block, options are of no interest
+    let mut rest = vec![0u8; length - 12];
+    if read_full(inner, &mut rest)? < rest.len() {
+        return Err(anyhow!("truncated pcapng section header"));
+    }
+    Ok(Format::PcapNg {
+        le,
+        interfaces: Vec::new(),
+    })
+}
+
+// strip the link layer header off a packet
+fn link_payload(linktype: u32, packet: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        // ethernet, with any number of VLAN tags
+        1 => {
+            let mut offset = 12;
+            loop {
+                if packet.len() < offset + 2 {
+                    return None;
+                }
+                match read_u16(packet, offset, false) {
+                    0x8100 | 0x88a8 | 0x9100 => offset += 4,
+                    0x0800 | 0x86dd => return Some(&packet[offset + 2..]),
+                    _ => return None,
+                }
+            }
+        }
+        // BSD loopback, the address family is in host byte order
+        0 | 108 if packet.len() > 4 => Some(&packet[4..]),
+        // raw IP
+        12 | 14 | 101 | 228 | 229 => Some(packet),
+        // linux cooked capture v1 and v2
+        113 if packet.len() > 16 => Some(&packet[16..]),
+        276 if packet.len() > 20 => Some(&packet[20..]),
+        _ => None,
+    }
+}
+
+// parse an IPv4/IPv6 header returning the addresses, transport protocol and payload
+fn ip_payload(ip: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8])> {
+    match ip.first().map(|b| b >> 4) {
+        Some(4) if ip.len() >= 20 => {
+            let header_len = usize::from(ip[0] & 0x0f) * 4;
+            let total_len = (read_u16(ip, 2, false) as usize).min(ip.len());
+            // fragments are not reassembled
+            if read_u16(ip, 6, false) & 0x3fff != 0 || header_len < 20 || header_len > total_len {
+                return None;
+            }
+            let src = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+            let dst = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+            Some((src.into(), dst.into(), ip[9], &ip[header_len..total_len]))
+        }
+        Some(6) if ip.len() >= 40 => {
+            let payload_len = read_u16(ip, 4, false) as usize;
+            let end = (40 + payload_len).min(ip.len());
+            let mut src = [0u8; 16];
+            let mut dst = [0u8; 16];
+            src.copy_from_slice(&ip[8..24]);
+            dst.copy_from_slice(&ip[24..40]);
+
+            let mut next = ip[6];
+            let mut offset = 40;
+            loop {
+                let header_len = match next {
+                    // hop-by-hop, routing and destination options
+                    0 | 43 | 60 if offset + 2 <= end => (usize::from(ip[offset + 1]) + 1) * 8,
+                    // authentication header
+                    51 if offset + 2 <= end => (usize::from(ip[offset + 1]) + 2) * 4,
+                    // fragments are not reassembled
+                    44 => return None,
+                    _ => break,
+                };
+                if offset + header_len > end {
+                    return None;
+                }
+                next = ip[offset];
+                offset += header_len;
+            }
+            Some((
+                Ipv6Addr::from(src).into(),
+                Ipv6Addr::from(dst).into(),
+                next,
+                &ip[offset..end],
+            ))
+        }
+        _ => None,
+    }
+}
+
+// get the timestamp resolution from the interface description block options
+fn read_resolution(mut options: &[u8], le: bool) -> u64 {
+    while options.len() >= 4 {
+        let code = read_u16(options, 0, le);
+        let length = read_u16(options, 2, le) as usize;
+        let padded = (length + 3) & !3;
+        if code == 0 || options.len() < 4 + padded {
+            break;
+        }
+        if code == 9 && length == 1 {
+            let tsresol = options[4];
+            let exponent = u32::from(tsresol & 0x7f);
+            return if tsresol & 0x80 == 0 {
+                10u64.checked_pow(exponent).unwrap_or(1_000_000)
+            } else {
+                2u64.checked_pow(exponent).unwrap_or(1_000_000)
+            };
+        }
+        options = &options[4 + padded..];
+    }
+    // microseconds by default
+    1_000_000
+}
+
+fn read_u16(buf: &[u8], offset: usize, le: bool) -> u16 {
+    let bytes = [buf[offset], buf[offset + 1]];
+    if le {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize, le: bool) -> u32 {
+    let bytes = [
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ];
+    if le {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Result};
+use parser;
+use state;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use stream::MESSAGE_HEADER_LEN;
+
+/// AddrRange is a block of addresses in CIDR notation, e.g. `10.0.0.0/8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddrRange {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+/// Policy decides which messages a collector accepts, by exporter address
+/// and observation domain, and which templates exporters can't redefine.
+/// With no exporters or domains configured everything is accepted.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    exporters: Vec<AddrRange>,
+    domains: Vec<u32>,
+    templates: Vec<parser::Template>,
+    options_templates: Vec<parser::OptionsTemplate>,
+}
+
+impl AddrRange {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max {
+            return Err(anyhow!("invalid prefix length {} for {}", prefix_len, addr));
+        }
+        Ok(Self { addr, prefix_len })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        // dual stack sockets report IPv4 exporters as mapped addresses
+        let addr = match addr {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            _ => addr,
+        };
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AddrRange {
+    type Err = anyhow::Error;
+
+    /// parse `addr/prefix_len`, a bare address is a range of its own
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, '/');
+        let addr: IpAddr = parts
+            .next()
+            .unwrap_or("")
+            .parse()
+            .map_err(|_| anyhow!("invalid address range {}", s))?;
+        let prefix_len = match parts.next() {
+            Some(len) => len
+                .parse()
+                .map_err(|_| anyhow!("invalid address range {}", s))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for AddrRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// accept messages from exporters within `range`
+    pub fn allow_exporter(&mut self, range: AddrRange) {
+        self.exporters.push(range);
+    }
+
+    /// accept messages for the observation domain `id`
+    pub fn allow_domain(&mut self, id: u32) {
+        self.domains.push(id);
+    }
+
+    /// pin a template in every session created by `new_state`
+    pub fn pin_template(&mut self, template: parser::Template) {
+        self.templates.push(template);
+    }
+
+    /// pin an options template in every session created by `new_state`
+    pub fn pin_options_template(&mut self, options_template: parser::OptionsTemplate) {
+        self.options_templates.push(options_template);
+    }
+
+    /// check a raw message before it's parsed, so a rejected message
+    /// never gets to touch the templates of a session.
+    pub fn admit(&self, exporter: IpAddr, msg: &[u8]) -> Result<()> {
+        if !self.exporters.is_empty() && !self.exporters.iter().any(|r| r.contains(exporter)) {
+            return Err(anyhow!("exporter {} isn't allowed", exporter));
+        }
+        if !self.domains.is_empty() {
+            if msg.len() < MESSAGE_HEADER_LEN {
+                return Err(anyhow!("message too short : {} bytes", msg.len()));
+            }
+            let domain = u32::from_be_bytes([msg[12], msg[13], msg[14], msg[15]]);
+            if !self.domains.contains(&domain) {
+                return Err(anyhow!("observation domain {} isn't allowed", domain));
+            }
+        }
+        Ok(())
+    }
+
+    /// create the state of a new session holding the pinned templates
+    pub fn new_state(&self) -> state::State {
+        let mut state = state::State::new();
+        for t in &self.templates {
+            state.pin_template(t.header.template_id, t.clone());
+        }
+        for t in &self.options_templates {
+            state.pin_options_template(t.header.id, t.clone());
+        }
+        state
+    }
+}
+
+// whether the first `prefix_len` bits of `a` and `b` match
+fn prefix_eq(a: &[u8], b: &[u8], prefix_len: u8) -> bool {
+    let bytes = (prefix_len / 8) as usize;
+    let bits = prefix_len % 8;
+    if a[..bytes] != b[..bytes] {
+        return false;
+    }
+    if bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - bits);
+    a[bytes] & mask == b[bytes] & mask
+}
@@ -0,0 +1,205 @@
+//! Conversion of data records into typed values and user structs.
+//!
+//! `FromValue` converts a single `DataRecordValue`, `IpfixRecord` a whole
+//! record. `IpfixRecord` is usually derived (behind the `derive` feature)
+//! with an `#[ipfix(..)]` attribute on every field naming its information
+//! element:
+//!
+//! ```ignore
+//! #[derive(IpfixRecord)]
+//! struct Flow {
+//!     #[ipfix(ie = 8)]
+//!     src: Ipv4Addr,
+//!     #[ipfix(ie = 1)]
+//!     octets: u64,
+//!     // None when the record doesn't have the field
+//!     #[ipfix(ie = 82)]
+//!     interface: Option<String>,
+//!     // an enterprise field, zero when missing
+//!     #[ipfix(pen = 35632, ie = 205, default)]
+//!     application_id: u32,
+//!     #[ipfix(skip)]
+//!     seen: usize,
+//! }
+//!
+//! let flow = Flow::from_record(&parser, record)?;
+//! ```
+
+use parser::{self, DataRecord, DataRecordValue, ValueError};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// FromValue converts a data record value, `name` is the field reported by
+/// the errors
+pub trait FromValue: Sized {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError>;
+}
+
+/// IpfixRecord builds a type from a data record
+pub trait IpfixRecord: Sized {
+    fn from_record(parser: &parser::Parser, record: &DataRecord) -> Result<Self, ValueError>;
+}
+
+/// the value of a field of an enterprise (0 for IANA) in a record, keyed by
+/// its formatter name. Records key fields without a formatter by their id
+/// alone, whatever their enterprise, so those aren't found: register them
+/// with `Parser::add_custom_field`.
+pub fn lookup<'r, 'a>(
+    parser: &parser::Parser,
+    record: &'r DataRecord<'a>,
+    pen: u32,
+    ie: u16,
+) -> Option<&'r DataRecordValue<'a>> {
+    parser
+        .get_field_name(pen, ie)
+        .and_then(|name| record.get(name))
+}
+
+/// convert the value of a field, `None` when the record doesn't have it.
+/// Errors name the field by its formatter name, `name` otherwise.
+pub fn field<T: FromValue>(
+    parser: &parser::Parser,
+    record: &DataRecord,
+    pen: u32,
+    ie: u16,
+    name: &'static str,
+) -> Result<Option<T>, ValueError> {
+    let name = parser.get_field_name(pen, ie).unwrap_or(name);
+    match lookup(parser, record, pen, ie) {
+        Some(value) => T::from_value(name, value).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// convert the value of a field the record must have
+pub fn required<T: FromValue>(
+    parser: &parser::Parser,
+    record: &DataRecord,
+    pen: u32,
+    ie: u16,
+    name: &'static str,
+) -> Result<T, ValueError> {
+    field(parser, record, pen, ie, name)?.ok_or(ValueError::Missing {
+        name: parser.get_field_name(pen, ie).unwrap_or(name),
+    })
+}
+
+pub(crate) fn mismatch(
+    name: &'static str,
+    expected: &'static str,
+    found: &DataRecordValue,
+) -> ValueError {
+    ValueError::Mismatch {
+        name,
+        expected,
+        found: found.kind(),
+    }
+}
+
+// an unsigned integer no larger than `max`
+fn narrow(name: &'static str, value: &DataRecordValue, max: u64) -> Result<u64, ValueError> {
+    let value = u64::from_value(name, value)?;
+    if value > max {
+        return Err(ValueError::Overflow { name, value });
+    }
+    Ok(value)
+}
+
+/// widened from any smaller encoding
+impl FromValue for u64 {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        value
+            .as_u64()
+            .ok_or_else(|| mismatch(name, "unsigned", value))
+    }
+}
+
+impl FromValue for u32 {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        narrow(name, value, u64::from(u32::MAX)).map(|v| v as u32)
+    }
+}
+
+impl FromValue for u16 {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        narrow(name, value, u64::from(u16::MAX)).map(|v| v as u16)
+    }
+}
+
+impl FromValue for u8 {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        narrow(name, value, u64::from(u8::MAX)).map(|v| v as u8)
+    }
+}
+
+/// also accepted as a 32 bit integer
+impl FromValue for Ipv4Addr {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        match *value {
+            DataRecordValue::IPv4(ip) => Ok(ip),
+            DataRecordValue::U32(v) => Ok(Ipv4Addr::from(v)),
+            _ => Err(mismatch(name, "ipv4", value)),
+        }
+    }
+}
+
+/// also accepted as 16 bytes
+impl FromValue for Ipv6Addr {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        match *value {
+            DataRecordValue::IPv6(ip) => Ok(ip),
+            DataRecordValue::Bytes(b) if b.len() == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(b);
+                Ok(Ipv6Addr::from(octets))
+            }
+            _ => Err(mismatch(name, "ipv6", value)),
+        }
+    }
+}
+
+impl FromValue for IpAddr {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        match *value {
+            DataRecordValue::IPv4(ip) => Ok(IpAddr::V4(ip)),
+            DataRecordValue::IPv6(ip) => Ok(IpAddr::V6(ip)),
+            _ => Err(mismatch(name, "ip address", value)),
+        }
+    }
+}
+
+/// a MAC address, also accepted as a 48 bit integer
+impl FromValue for [u8; 6] {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        let n = match *value {
+            DataRecordValue::Bytes(b) if b.len() == 6 => value.as_u64(),
+            DataRecordValue::U64(v) if v >> 48 == 0 => Some(v),
+            _ => None,
+        };
+        match n {
+            Some(n) => {
+                let mut mac = [0u8; 6];
+                mac.copy_from_slice(&n.to_be_bytes()[2..]);
+                Ok(mac)
+            }
+            None => Err(mismatch(name, "mac address", value)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        value
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| mismatch(name, "string", value))
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(name: &'static str, value: &DataRecordValue) -> Result<Self, ValueError> {
+        match *value {
+            DataRecordValue::Bytes(b) => Ok(b.to_vec()),
+            _ => Err(mismatch(name, "bytes", value)),
+        }
+    }
+}
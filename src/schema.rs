@@ -0,0 +1,408 @@
+#[cfg(feature = "arrow")]
+use arrow;
+#[cfg(feature = "arrow")]
+use columnar;
+use formatter::{self, DataType};
+use jsonl;
+use parser::{self, DataRecordKey, DataRecordValue, FieldSpecifier};
+use rustc_hash::FxHashMap as HashMap;
+use serde_json::{Map, Value};
+use state;
+
+/// JSON Schema dialect of the generated schemas
+pub const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// namespace of the generated Avro records
+pub const AVRO_NAMESPACE: &str = "rsipfix";
+
+// the Avro type of unsigned 64 bit integers, a two's complement big endian
+// decimal one byte wider than the value so it stays positive
+const AVRO_UINT64: &str = "uint64";
+
+/// the schemas of a template
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateSchemas {
+    /// the observation domain a state scoped to domains learned the
+    /// template in, `None` for a template used in every domain
+    pub observation_domain_id: Option<u32>,
+    pub template_id: u16,
+    /// whether the template is an options template
+    pub is_options: bool,
+    pub json_schema: Value,
+    pub avro: Value,
+    /// the schema `columnar::Decoder` decodes the data sets with
+    #[cfg(feature = "arrow")]
+    pub arrow: arrow::datatypes::Schema,
+}
+
+/// generate the schemas of every template and options template in `state`,
+/// ordered by observation domain, templates used in every domain first, and
+/// template id
+pub fn generate(parser: &parser::Parser, state: &state::State) -> Vec<TemplateSchemas> {
+    let mut templates = Vec::new();
+    fields_by_id(
+        None,
+        state.get_templates(),
+        state.get_options_templates(),
+        &mut templates,
+    );
+    fields_by_id(
+        None,
+        state.get_pinned_templates(),
+        state.get_pinned_options_templates(),
+        &mut templates,
+    );
+    for domain in state.scoped_domains() {
+        if let (Some(t), Some(o)) = (
+            state.get_domain_templates(domain),
+            state.get_domain_options_templates(domain),
+        ) {
+            fields_by_id(Some(domain), t, o, &mut templates);
+        }
+    }
+    let mut schemas: Vec<TemplateSchemas> = templates
+        .into_iter()
+        .map(
+            |(observation_domain_id, template_id, is_options, fields)| TemplateSchemas {
+                observation_domain_id,
+                template_id,
+                is_options,
+                json_schema: json_schema(parser, template_id, fields),
+                avro: avro_schema(parser, template_id, fields),
+                #[cfg(feature = "arrow")]
+                arrow: columnar::schema(parser, fields),
+            },
+        )
+        .collect();
+    schemas.sort_by_key(|s| (s.observation_domain_id, s.template_id, s.is_options));
+    schemas
+}
+
+// the domain, id, kind and field specifiers of templates
+type Fields<'s> = (Option<u32>, u16, bool, &'s [FieldSpecifier]);
+
+fn fields_by_id<'s>(
+    domain: Option<u32>,
+    templates: &'s HashMap<u16, parser::Template>,
+    options_templates: &'s HashMap<u16, parser::OptionsTemplate>,
+    out: &mut Vec<Fields<'s>>,
+) {
+    out.extend(
+        templates
+            .iter()
+            .map(|(id, t)| (domain, *id, false, &t.field_specifiers[..])),
+    );
+    out.extend(
+        options_templates
+            .iter()
+            .map(|(id, t)| (domain, *id, true, &t.field_specifiers[..])),
+    );
+}
+
+/// JSON Schema of the records of a template as `jsonl::Writer` writes them
+/// with default options, see `json_schema_with`
+pub fn json_schema(parser: &parser::Parser, template_id: u16, fields: &[FieldSpecifier]) -> Value {
+    json_schema_with(parser, template_id, fields, &jsonl::Options::default())
+}
+
+/// JSON Schema of the records of a template as `jsonl::Writer` writes them
+/// with `options`: an object with a property per record key, all of them
+/// required.
+///
+/// Values are typed by what the field's formatter decodes at the field
+/// length, the way records hold them: integers as bounded integers,
+/// addresses as `ipv4`/`ipv6` strings and octet arrays, including fields
+/// without a formatter, as hex or base64 strings. Variable length fields
+/// can take any of the types their formatter decodes to.
+pub fn json_schema_with(
+    parser: &parser::Parser,
+    template_id: u16,
+    fields: &[FieldSpecifier],
+    options: &jsonl::Options,
+) -> Value {
+    // records hold a single value per key, the last one
+    let key = |fs: &FieldSpecifier| parser.record_key(fs.enterprise_number.unwrap_or(0), fs.ident);
+    let mut by_key: Vec<&FieldSpecifier> = Vec::with_capacity(fields.len());
+    for fs in fields {
+        by_key.retain(|f| key(f) != key(fs));
+        by_key.push(fs);
+    }
+
+    let mut properties = Map::new();
+    for fs in by_key {
+        let name = match key(fs) {
+            DataRecordKey::Str(name) => match options.naming {
+                jsonl::Naming::Iana => name.to_string(),
+                jsonl::Naming::SnakeCase => jsonl::snake_case(name),
+            },
+            DataRecordKey::Unrecognized(id) => id.to_string(),
+            DataRecordKey::Err(e) => e,
+        };
+        let mut property = match value_schemas(parser, fs, options.bytes).as_slice() {
+            [one] => one.clone(),
+            any => {
+                let mut property = Map::new();
+                property.insert(
+                    "anyOf".to_string(),
+                    any.iter().cloned().map(Value::Object).collect(),
+                );
+                property
+            }
+        };
+        property.insert("description".to_string(), description(fs).into());
+        properties.insert(name, Value::Object(property));
+    }
+
+    let mut record = object(properties);
+    if options.metadata {
+        let snake = options.naming == jsonl::Naming::SnakeCase;
+        let key = |iana: &str| {
+            if snake {
+                jsonl::snake_case(iana)
+            } else {
+                iana.to_string()
+            }
+        };
+        let mut properties = Map::new();
+        let mut exporter = Map::new();
+        let types = vec![Value::from("string"), Value::from("null")];
+        exporter.insert("type".to_string(), Value::Array(types));
+        properties.insert("exporter".to_string(), Value::Object(exporter));
+        properties.insert(
+            key("observationDomainId"),
+            Value::Object(integer(u64::from(u32::MAX))),
+        );
+        properties.insert(
+            key("exportTime"),
+            Value::Object(integer(u64::from(u32::MAX))),
+        );
+        properties.insert(
+            key("templateId"),
+            Value::Object(integer(u64::from(u16::MAX))),
+        );
+        properties.insert("record".to_string(), record);
+        record = object(properties);
+    }
+
+    let mut schema = match record {
+        Value::Object(schema) => schema,
+        _ => Map::new(),
+    };
+    schema.insert("$schema".to_string(), JSON_SCHEMA_DIALECT.into());
+    schema.insert(
+        "title".to_string(),
+        format!("template {}", template_id).into(),
+    );
+    Value::Object(schema)
+}
+
+/// Avro schema of the records of a template, a record named
+/// `template_<id>` with a field per template field in template order.
+///
+/// Unsigned integers of up to 16 bits are `int`, 32 bit ones `long` and 64
+/// bit ones, which don't fit a signed `long`, the `uint64` fixed of 9 bytes
+/// with the `decimal` logical type. Signed integers are `int` or `long`,
+/// floats `float` or `double`, booleans `boolean`, addresses and strings
+/// `string`, timestamps `long` with the `timestamp-millis`,
+/// `timestamp-micros` or `timestamp-nanos` logical type and everything else
+/// `bytes`.
+///
+/// This is only a schema: the crate doesn't write Avro, so whatever encodes
+/// records with it has to convert their values, e.g. 64 bit unsigned
+/// integers to 9 big endian bytes and timestamps in seconds to
+/// milliseconds.
+pub fn avro_schema(parser: &parser::Parser, template_id: u16, fields: &[FieldSpecifier]) -> Value {
+    // named types are defined once, where first used
+    let mut uint64_defined = false;
+    let columns = parser
+        .field_names(fields)
+        .into_iter()
+        .zip(fields)
+        .map(|(name, fs)| {
+            let t: Value = match data_type(fs) {
+                Some(DataType::Unsigned8)
+                | Some(DataType::Unsigned16)
+                | Some(DataType::Signed8)
+                | Some(DataType::Signed16)
+                | Some(DataType::Signed32) => "int".into(),
+                Some(DataType::Signed64) => "long".into(),
+                Some(DataType::Float32) => "float".into(),
+                Some(DataType::Float64) => "double".into(),
+                Some(DataType::Boolean) => "boolean".into(),
+                Some(DataType::Unsigned32) => "long".into(),
+                Some(DataType::Unsigned64) if uint64_defined => AVRO_UINT64.into(),
+                Some(DataType::Unsigned64) => {
+                    uint64_defined = true;
+                    let mut t = Map::new();
+                    t.insert("type".to_string(), "fixed".into());
+                    t.insert("name".to_string(), AVRO_UINT64.into());
+                    t.insert("size".to_string(), 9.into());
+                    t.insert("logicalType".to_string(), "decimal".into());
+                    t.insert("precision".to_string(), 20.into());
+                    t.insert("scale".to_string(), 0.into());
+                    Value::Object(t)
+                }
+                Some(DataType::Ipv4Address)
+                | Some(DataType::Ipv6Address)
+                | Some(DataType::MacAddress)
+                | Some(DataType::String) => "string".into(),
+                Some(DataType::DateTimeSeconds) | Some(DataType::DateTimeMilliseconds) => {
+                    timestamp("timestamp-millis")
+                }
+                Some(DataType::DateTimeMicroseconds) => timestamp("timestamp-micros"),
+                Some(DataType::DateTimeNanoseconds) => timestamp("timestamp-nanos"),
+                Some(DataType::OctetArray)
+                | Some(DataType::BasicList)
+                | Some(DataType::SubTemplateList)
+                | Some(DataType::SubTemplateMultiList)
+                | None => "bytes".into(),
+            };
+            let mut field = Map::new();
+            field.insert("name".to_string(), name.into());
+            field.insert("type".to_string(), t);
+            field.insert("doc".to_string(), description(fs).into());
+            Value::Object(field)
+        })
+        .collect();
+
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), "record".into());
+    schema.insert(
+        "name".to_string(),
+        format!("template_{}", template_id).into(),
+    );
+    schema.insert("namespace".to_string(), AVRO_NAMESPACE.into());
+    schema.insert("fields".to_string(), Value::Array(columns));
+    Value::Object(schema)
+}
+
+// the IANA data type a field is encoded with, `None` for enterprise
+// fields, unknown fields and lengths that don't fit the type
+fn data_type(fs: &FieldSpecifier) -> Option<DataType> {
+    match fs.enterprise_number {
+        None => formatter::default_type(fs.ident).filter(|t| t.fits(fs.field_length)),
+        Some(_) => None,
+    }
+}
+
+fn description(fs: &FieldSpecifier) -> String {
+    match fs.enterprise_number {
+        Some(pen) => format!("ie {}, pen {}", fs.ident, pen),
+        None => format!("ie {}", fs.ident),
+    }
+}
+
+// the schemas of the values a field decodes to, variable length fields
+// are decoded at a few lengths
+fn value_schemas(
+    parser: &parser::Parser,
+    fs: &FieldSpecifier,
+    bytes: jsonl::ByteEncoding,
+) -> Vec<Map<String, Value>> {
+    const VARIABLE_LENGTHS: [usize; 7] = [0, 1, 2, 3, 4, 8, 16];
+    let lengths: Vec<usize> = if fs.is_variable() {
+        VARIABLE_LENGTHS.to_vec()
+    } else {
+        vec![usize::from(fs.field_length)]
+    };
+    let pen = fs.enterprise_number.unwrap_or(0);
+    let mut schemas: Vec<Map<String, Value>> = Vec::new();
+    for length in lengths {
+        let buf = vec![0u8; length];
+        let schema = match parser.record_key(pen, fs.ident) {
+            DataRecordKey::Str(_) => match parser.decode_field(pen, fs.ident, &buf) {
+                Some((_, value)) => value_schema(&value, bytes),
+                None => null(),
+            },
+            DataRecordKey::Unrecognized(_) => value_schema(&DataRecordValue::Bytes(&buf), bytes),
+            DataRecordKey::Err(_) => null(),
+        };
+        // integers of any width share a schema bounded by the widest
+        let same = schemas.iter_mut().find(|s| {
+            s.get("type") == schema.get("type") && s.get("format") == schema.get("format")
+        });
+        match same {
+            Some(s) => {
+                if let (Some(a), Some(b)) = (s.get("maximum"), schema.get("maximum")) {
+                    if b.as_u64() > a.as_u64() {
+                        s.insert("maximum".to_string(), b.clone());
+                    }
+                }
+            }
+            None => schemas.push(schema),
+        }
+    }
+    schemas
+}
+
+// the schema of a value as jsonl writes it
+fn value_schema(value: &DataRecordValue, bytes: jsonl::ByteEncoding) -> Map<String, Value> {
+    let string = |key: &str, value: &str| {
+        let mut property = Map::new();
+        property.insert("type".to_string(), "string".into());
+        property.insert(key.to_string(), value.into());
+        property
+    };
+    match *value {
+        DataRecordValue::U8(_) => integer(u64::from(u8::MAX)),
+        DataRecordValue::U16(_) => integer(u64::from(u16::MAX)),
+        DataRecordValue::U32(_) => integer(u64::from(u32::MAX)),
+        DataRecordValue::U64(_) => integer(u64::MAX),
+        DataRecordValue::IPv4(_) => string("format", "ipv4"),
+        DataRecordValue::IPv6(_) => string("format", "ipv6"),
+        DataRecordValue::Bytes(_) => match bytes {
+            jsonl::ByteEncoding::Hex => string("contentEncoding", "base16"),
+            jsonl::ByteEncoding::Base64 => string("contentEncoding", "base64"),
+        },
+        DataRecordValue::String(_) | DataRecordValue::Err(..) => {
+            let mut property = Map::new();
+            property.insert("type".to_string(), "string".into());
+            property
+        }
+        DataRecordValue::MPLS(..) => {
+            let mut property = Map::new();
+            property.insert("type".to_string(), "array".into());
+            property.insert(
+                "items".to_string(),
+                Value::Object(integer(u64::from(u32::MAX))),
+            );
+            property.insert("minItems".to_string(), 3.into());
+            property.insert("maxItems".to_string(), 3.into());
+            property
+        }
+        DataRecordValue::Empty => null(),
+    }
+}
+
+// an unsigned integer up to `maximum`
+fn integer(maximum: u64) -> Map<String, Value> {
+    let mut property = Map::new();
+    property.insert("type".to_string(), "integer".into());
+    property.insert("minimum".to_string(), 0.into());
+    property.insert("maximum".to_string(), maximum.into());
+    property
+}
+
+fn null() -> Map<String, Value> {
+    let mut property = Map::new();
+    property.insert("type".to_string(), "null".into());
+    property
+}
+
+// an object of required properties and nothing else
+fn object(properties: Map<String, Value>) -> Value {
+    let required: Vec<Value> = properties.keys().cloned().map(Value::from).collect();
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), "object".into());
+    schema.insert("required".to_string(), Value::Array(required));
+    schema.insert("properties".to_string(), Value::Object(properties));
+    schema.insert("additionalProperties".to_string(), false.into());
+    Value::Object(schema)
+}
+
+fn timestamp(logical_type: &str) -> Value {
+    let mut t = Map::new();
+    t.insert("type".to_string(), "long".into());
+    t.insert("logicalType".to_string(), logical_type.into());
+    Value::Object(t)
+}
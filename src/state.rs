@@ -1,45 +1,519 @@
+use anyhow::{anyhow, Result};
 use parser;
 use rustc_hash::FxHashMap as HashMap;
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use stats;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::iter;
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// version of the snapshot format written by `write_snapshot`
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// what became of a template definition
+enum Outcome {
+    Added,
+    Replaced,
+    // the same definition as the pinned template
+    Pinned,
+    // pinned to a different definition
+    Refused,
+}
 
 #[derive(Debug, new)]
 pub struct State {
+    // templates learned from exporters, unless scoped to their domain
     #[new(default)]
-    templates: HashMap<u16, parser::Template>,
+    unscoped: Scope,
+    // templates learned by observation domain, when scoped
+    #[new(default)]
+    scoped: HashMap<u32, Scope>,
+    #[new(default)]
+    domain_scoped: bool,
+    // templates of every observation domain that can't be redefined
+    #[new(default)]
+    pinned_templates: HashMap<u16, parser::Template>,
+    #[new(default)]
+    pinned_options_templates: HashMap<u16, parser::OptionsTemplate>,
+    #[new(default)]
+    refused: u64,
+    #[new(default)]
+    limits: Limits,
+    // number of templates defined per observation domain
+    #[new(default)]
+    domains: HashMap<u32, usize>,
+    // approximate size of the templates in bytes
+    #[new(default)]
+    memory: usize,
+    #[new(default)]
+    stats: stats::Stats,
+    // shared with the other sessions of a `Sessions`
     #[new(default)]
+    budget: Option<Arc<Budget>>,
+}
+
+// the templates learned in one scope: every observation domain, or a single
+// one when templates are scoped to their domain
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    templates: HashMap<u16, parser::Template>,
     options_templates: HashMap<u16, parser::OptionsTemplate>,
+    // the observation domain each template was defined in and when, in
+    // seconds since the UNIX epoch
+    defined: HashMap<u16, (u32, u64)>,
+}
+
+/// Limits bound the resources a single session can take up, so a hostile
+/// or broken exporter gets an error instead of exhausting the collector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// field specifiers in a template
+    pub max_fields_per_template: usize,
+    /// templates and options templates per observation domain
+    pub max_templates_per_domain: usize,
+    /// observation domains defining templates in a session
+    pub max_domains: usize,
+    /// data records decoded from a single set
+    pub max_records_per_set: usize,
+    /// approximate memory taken by the templates of a session, in bytes
+    pub max_memory: usize,
+}
+
+/// Budget bounds the number of sessions and the memory taken by their
+/// templates over every `Sessions` sharing it, e.g. the workers of a
+/// collector, on top of the `Limits` of each session.
+#[derive(Debug)]
+pub struct Budget {
+    max_sessions: usize,
+    max_memory: usize,
+    sessions: AtomicUsize,
+    memory: AtomicUsize,
+}
+
+/// LimitError is returned when a session goes over one of its `Limits`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitError(pub String);
+
+/// a template or an options template saved in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Definition {
+    Template(parser::Template),
+    OptionsTemplate(parser::OptionsTemplate),
+}
+
+/// a saved template along with where and when it was defined
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateEntry {
+    pub observation_domain_id: u32,
+    pub template_id: u16,
+    /// seconds since the UNIX epoch
+    pub updated: u64,
+    pub definition: Definition,
+}
+
+/// StateSnapshot holds the templates learned by a `State`. Pinned templates
+/// aren't part of it, they come from the configuration of the collector.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub templates: Vec<TemplateEntry>,
+}
+
+// the format of the file written by `write_snapshot`
+#[derive(Serialize, Deserialize)]
+struct SessionsSnapshot<K> {
+    version: u32,
+    /// seconds since the UNIX epoch
+    saved: u64,
+    sessions: Vec<(K, StateSnapshot)>,
+}
+
+/// Sessions keeps a separate `State` per session key, e.g. the
+/// exporter's address, so exporters can't overwrite each others templates.
+#[derive(Debug, new)]
+pub struct Sessions<K: Hash + Eq> {
+    #[new(default)]
+    states: HashMap<K, Session>,
+    #[new(value = "Arc::new(Budget::default())")]
+    budget: Arc<Budget>,
+}
+
+// the state of a session and when a message last came in
+#[derive(Debug)]
+struct Session {
+    state: State,
+    used: Instant,
+}
+
+/// SessionKey identifies a transport session by the exporter's address and,
+/// for authenticated transports such as TLS, the verified peer identity so
+/// an authenticated exporter can't share templates with anyone else.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, new)]
+pub struct SessionKey {
+    pub exporter: SocketAddr,
+    pub peer_identity: Option<String>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_fields_per_template: 1024,
+            max_templates_per_domain: 4096,
+            max_domains: 256,
+            max_records_per_set: 16384,
+            max_memory: 64 << 20,
+        }
+    }
+}
+
+impl Budget {
+    /// a budget of `max_sessions` sessions whose templates take up at most
+    /// `max_memory` bytes
+    pub fn new(max_sessions: usize, max_memory: usize) -> Self {
+        Self {
+            max_sessions,
+            max_memory,
+            sessions: AtomicUsize::new(0),
+            memory: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.max_memory
+    }
+
+    /// number of sessions open
+    pub fn sessions(&self) -> usize {
+        self.sessions.load(Ordering::Relaxed)
+    }
+
+    /// approximate memory taken by the templates of every session in bytes
+    pub fn memory(&self) -> usize {
+        self.memory.load(Ordering::Relaxed)
+    }
+
+    // take a session, false when they're all taken
+    fn open_session(&self) -> bool {
+        let max = self.max_sessions;
+        self.sessions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    fn close_sessions(&self, n: usize) {
+        self.sessions.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    // resize templates of `old` bytes to `new` bytes, false when that
+    // grows the memory over the budget
+    fn resize(&self, old: usize, new: usize) -> bool {
+        let max = self.max_memory;
+        self.memory
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                let used = used - old.min(used) + new;
+                if new <= old || used <= max {
+                    Some(used)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    // resize whatever the budget, for templates dropped or pinned
+    fn force_resize(&self, old: usize, new: usize) {
+        let _ = self
+            .memory
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used - old.min(used) + new)
+            });
+    }
+}
+
+/// no limit on either sessions or memory
+impl Default for Budget {
+    fn default() -> Self {
+        Self::new(usize::MAX, usize::MAX)
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "limit exceeded : {}", self.0)
+    }
 }
 
+impl Error for LimitError {}
+
 impl State {
-    pub fn add_template(&mut self, id: u16, template: parser::Template) {
-        self.templates.insert(id, template);
+    /// create an empty state bounded by `limits`
+    pub fn with_limits(limits: Limits) -> Self {
+        let mut state = Self::new();
+        state.limits = limits;
+        state
+    }
+
+    /// create an empty state keeping the templates of each observation
+    /// domain apart, so an id can be defined differently in two domains.
+    /// Templates are otherwise shared by the domains of the session.
+    pub fn domain_scoped() -> Self {
+        let mut state = Self::new();
+        state.domain_scoped = true;
+        state
     }
 
-    pub fn add_options_template(&mut self, id: u16, options_template: parser::OptionsTemplate) {
-        self.options_templates.insert(id, options_template);
+    /// whether templates are kept apart by observation domain
+    pub fn is_domain_scoped(&self) -> bool {
+        self.domain_scoped
     }
 
+    pub fn get_limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// change the limits, they apply to templates added from now on
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// add or replace a template defined in observation domain `domain`,
+    /// returns false when the id is pinned to a different definition and
+    /// the template is refused, and an error when a limit is exceeded.
+    pub fn add_template(
+        &mut self,
+        domain: u32,
+        id: u16,
+        template: parser::Template,
+    ) -> Result<bool> {
+        let outcome = self.define(domain, id, Definition::Template(template), now())?;
+        Ok(self.count(domain, outcome))
+    }
+
+    /// add or replace an options template defined in observation domain
+    /// `domain`, returns false when the id is pinned to a different definition
+    /// and the template is refused, and an error when a limit is exceeded.
+    pub fn add_options_template(
+        &mut self,
+        domain: u32,
+        id: u16,
+        options_template: parser::OptionsTemplate,
+    ) -> Result<bool> {
+        let definition = Definition::OptionsTemplate(options_template);
+        let outcome = self.define(domain, id, definition, now())?;
+        Ok(self.count(domain, outcome))
+    }
+
+    /// add a template that exporters can't redefine in any observation
+    /// domain, pinned templates don't count towards the limits of a domain.
+    pub fn pin_template(&mut self, id: u16, template: parser::Template) {
+        self.pinned_options_templates.remove(&id);
+        self.pinned_templates.insert(id, template);
+        self.unlearn(id);
+    }
+
+    /// add an options template that exporters can't redefine in any
+    /// observation domain, pinned templates don't count towards the limits
+    /// of a domain.
+    pub fn pin_options_template(&mut self, id: u16, options_template: parser::OptionsTemplate) {
+        self.pinned_templates.remove(&id);
+        self.pinned_options_templates.insert(id, options_template);
+        self.unlearn(id);
+    }
+
+    pub fn is_pinned(&self, id: u16) -> bool {
+        self.pinned_templates.contains_key(&id) || self.pinned_options_templates.contains_key(&id)
+    }
+
+    /// number of template definitions refused because their id is pinned
+    pub fn refused_templates(&self) -> u64 {
+        self.refused
+    }
+
+    /// drop all templates except pinned ones, e.g. when the transport session closes
+    pub fn clear(&mut self) {
+        self.unscoped = Scope::default();
+        self.scoped.clear();
+        self.recount();
+    }
+
+    /// approximate memory taken by the templates in bytes
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// number of observation domains that defined templates
+    pub fn domains_len(&self) -> usize {
+        self.domains.len()
+    }
+
+    /// copy the templates learned from exporters along with their domains
+    pub fn snapshot(&self) -> StateSnapshot {
+        let mut templates: Vec<TemplateEntry> =
+            self.scopes().flat_map(|scope| scope.entries()).collect();
+        templates.sort_by_key(|t| (t.observation_domain_id, t.template_id));
+        StateSnapshot { templates }
+    }
+
+    /// add the templates of a snapshot, pinned templates and the limits
+    /// apply as if the exporter had sent them but the statistics are left
+    /// alone. Every template is restored on its own, returns the number of
+    /// templates refused or over a limit.
+    pub fn restore(&mut self, snapshot: StateSnapshot) -> usize {
+        let mut failed = 0;
+        for t in snapshot.templates {
+            let (domain, id) = (t.observation_domain_id, t.template_id);
+            match self.define(domain, id, t.definition, t.updated) {
+                Ok(Outcome::Refused) | Err(_) => failed += 1,
+                Ok(_) => {}
+            }
+        }
+        failed
+    }
+
+    /// counters of what was parsed with this state
+    pub fn get_stats(&self) -> &stats::Stats {
+        &self.stats
+    }
+
+    // count a message of `length` bytes parsed with this state
+    pub(crate) fn add_message(&mut self, length: usize, result: &Result<parser::Message>) {
+        let mut stats = mem::take(&mut self.stats);
+        stats.count(self, length, result);
+        self.stats = stats;
+    }
+
+    /// the template `id`, pinned or learned unless scoped to its
+    /// observation domain, see `get_domain_template`
     pub fn get_template(&self, id: &u16) -> Option<&parser::Template> {
-        self.templates.get(id)
+        self.pinned_templates
+            .get(id)
+            .or_else(|| self.unscoped.templates.get(id))
+    }
+
+    /// the template `id` used in observation domain `domain`, pinned or learned
+    pub fn get_domain_template(&self, domain: u32, id: &u16) -> Option<&parser::Template> {
+        self.pinned_templates
+            .get(id)
+            .or_else(|| self.scope(domain)?.templates.get(id))
     }
 
+    /// templates learned from exporters, unless scoped to their
+    /// observation domain
     pub fn get_templates(&self) -> &HashMap<u16, parser::Template> {
-        &self.templates
+        &self.unscoped.templates
     }
 
+    /// options templates learned from exporters, unless scoped to their
+    /// observation domain
     pub fn get_options_templates(&self) -> &HashMap<u16, parser::OptionsTemplate> {
-        &self.options_templates
+        &self.unscoped.options_templates
+    }
+
+    /// templates learned in observation domain `domain` by a state scoped
+    /// to domains
+    pub fn get_domain_templates(&self, domain: u32) -> Option<&HashMap<u16, parser::Template>> {
+        self.scoped.get(&domain).map(|scope| &scope.templates)
     }
 
+    /// options templates learned in observation domain `domain` by a state
+    /// scoped to domains
+    pub fn get_domain_options_templates(
+        &self,
+        domain: u32,
+    ) -> Option<&HashMap<u16, parser::OptionsTemplate>> {
+        self.scoped
+            .get(&domain)
+            .map(|scope| &scope.options_templates)
+    }
+
+    /// observation domains a state scoped to domains learned templates in
+    pub fn scoped_domains<'s>(&'s self) -> impl Iterator<Item = u32> + 's {
+        self.scoped.keys().cloned()
+    }
+
+    pub fn get_pinned_templates(&self) -> &HashMap<u16, parser::Template> {
+        &self.pinned_templates
+    }
+
+    pub fn get_pinned_options_templates(&self) -> &HashMap<u16, parser::OptionsTemplate> {
+        &self.pinned_options_templates
+    }
+
+    /// the options template `id`, pinned or learned unless scoped to its
+    /// observation domain, see `get_domain_options_template`
     pub fn get_options_template(&self, id: &u16) -> Option<&parser::OptionsTemplate> {
-        self.options_templates.get(id)
+        self.pinned_options_templates
+            .get(id)
+            .or_else(|| self.unscoped.options_templates.get(id))
+    }
+
+    /// the options template `id` used in observation domain `domain`,
+    /// pinned or learned
+    pub fn get_domain_options_template(
+        &self,
+        domain: u32,
+        id: &u16,
+    ) -> Option<&parser::OptionsTemplate> {
+        self.pinned_options_templates
+            .get(id)
+            .or_else(|| self.scope(domain)?.options_templates.get(id))
+    }
+
+    /// field specifiers of a template or an options template
+    pub fn get_field_specifiers(&self, id: &u16) -> Option<&[parser::FieldSpecifier]> {
+        match self.get_template(id) {
+            Some(t) => Some(&t.field_specifiers),
+            None => self
+                .get_options_template(id)
+                .map(|t| &t.field_specifiers[..]),
+        }
+    }
+
+    /// field specifiers of a template or an options template used in
+    /// observation domain `domain`
+    pub fn get_domain_field_specifiers(
+        &self,
+        domain: u32,
+        id: &u16,
+    ) -> Option<&[parser::FieldSpecifier]> {
+        match self.get_domain_template(domain, id) {
+            Some(t) => Some(&t.field_specifiers),
+            None => self
+                .get_domain_options_template(domain, id)
+                .map(|t| &t.field_specifiers[..]),
+        }
     }
 
     pub fn templates_len(&self) -> usize {
-        self.templates.len()
+        let learned: usize = self.scopes().map(|scope| scope.templates.len()).sum();
+        learned + self.pinned_templates.len()
     }
 
     pub fn options_templates_len(&self) -> usize {
-        self.options_templates.len()
+        let learned: usize = self
+            .scopes()
+            .map(|scope| scope.options_templates.len())
+            .sum();
+        learned + self.pinned_options_templates.len()
     }
 
     pub fn len(&self) -> usize {
@@ -49,4 +523,479 @@ impl State {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    // charge the templates to the budget shared by the sessions
+    pub(crate) fn set_budget(&mut self, budget: Arc<Budget>) {
+        budget.force_resize(0, self.memory);
+        self.release_budget();
+        self.budget = Some(budget);
+    }
+
+    // give the memory of the templates back to the budget
+    pub(crate) fn release_budget(&mut self) {
+        if let Some(budget) = self.budget.take() {
+            budget.force_resize(self.memory, 0);
+        }
+    }
+
+    fn scopes<'s>(&'s self) -> impl Iterator<Item = &'s Scope> + 's {
+        iter::once(&self.unscoped).chain(self.scoped.values())
+    }
+
+    // the templates used in observation domain `domain`
+    fn scope(&self, domain: u32) -> Option<&Scope> {
+        if self.domain_scoped {
+            self.scoped.get(&domain)
+        } else {
+            Some(&self.unscoped)
+        }
+    }
+
+    fn scope_mut(&mut self, domain: u32) -> &mut Scope {
+        if self.domain_scoped {
+            self.scoped.entry(domain).or_default()
+        } else {
+            &mut self.unscoped
+        }
+    }
+
+    // store a definition last defined at `updated` unless its id is pinned
+    fn define(
+        &mut self,
+        domain: u32,
+        id: u16,
+        definition: Definition,
+        updated: u64,
+    ) -> Result<Outcome> {
+        if self.is_pinned(id) {
+            let identical = match definition {
+                Definition::Template(ref t) => self.pinned_templates.get(&id) == Some(t),
+                Definition::OptionsTemplate(ref t) => {
+                    self.pinned_options_templates.get(&id) == Some(t)
+                }
+            };
+            return Ok(if identical {
+                Outcome::Pinned
+            } else {
+                Outcome::Refused
+            });
+        }
+        let replaced = self.scope(domain).and_then(|scope| scope.defined(id));
+        let fields = match definition {
+            Definition::Template(ref t) => t.field_specifiers.len(),
+            Definition::OptionsTemplate(ref t) => t.field_specifiers.len(),
+        };
+        self.reserve(domain, id, fields, replaced)?;
+        let scope = self.scope_mut(domain);
+        match definition {
+            Definition::Template(t) => {
+                scope.options_templates.remove(&id);
+                scope.templates.insert(id, t);
+            }
+            Definition::OptionsTemplate(t) => {
+                scope.templates.remove(&id);
+                scope.options_templates.insert(id, t);
+            }
+        }
+        scope.defined.insert(id, (domain, updated));
+        Ok(match replaced {
+            Some(_) => Outcome::Replaced,
+            None => Outcome::Added,
+        })
+    }
+
+    // count a definition received from an exporter, false when it was refused
+    fn count(&mut self, domain: u32, outcome: Outcome) -> bool {
+        match outcome {
+            Outcome::Added => self.stats.add_definition(domain, false),
+            Outcome::Replaced => self.stats.add_definition(domain, true),
+            Outcome::Pinned => {}
+            Outcome::Refused => {
+                self.refused += 1;
+                return false;
+            }
+        }
+        true
+    }
+
+    // check the limits before a template with `fields` field specifiers is
+    // stored, `replaced` being the domain and the field count of the
+    // template it replaces.
+    fn reserve(
+        &mut self,
+        domain: u32,
+        id: u16,
+        fields: usize,
+        replaced: Option<(u32, usize)>,
+    ) -> Result<()> {
+        let limits = self.limits;
+        if fields > limits.max_fields_per_template {
+            return Err(LimitError(format!(
+                "template {} has {} fields, at most {} are allowed",
+                id, fields, limits.max_fields_per_template
+            ))
+            .into());
+        }
+
+        let old = replaced.map_or(0, |(_, fields)| template_size(fields));
+        let memory = self.memory - old + template_size(fields);
+        if memory > limits.max_memory {
+            return Err(LimitError(format!(
+                "template {} would grow the state to {} bytes, at most {} are allowed",
+                id, memory, limits.max_memory
+            ))
+            .into());
+        }
+
+        // a template of another domain changes hands
+        let owner = replaced.map(|(owner, _)| owner);
+        let count = self.domains.get(&domain).cloned().unwrap_or(0);
+        if owner != Some(domain) {
+            if count == 0 && self.domains.len() >= limits.max_domains {
+                return Err(LimitError(format!(
+                    "observation domain {} is over the limit of {} domains",
+                    domain, limits.max_domains
+                ))
+                .into());
+            }
+            if count >= limits.max_templates_per_domain {
+                return Err(LimitError(format!(
+                    "template {} is over the limit of {} templates in observation domain {}",
+                    id, limits.max_templates_per_domain, domain
+                ))
+                .into());
+            }
+        }
+
+        if let Some(ref budget) = self.budget {
+            if !budget.resize(self.memory, memory) {
+                return Err(LimitError(format!(
+                    "template {} would grow the templates of all sessions over {} bytes",
+                    id, budget.max_memory
+                ))
+                .into());
+            }
+        }
+        if owner != Some(domain) {
+            if let Some(owner) = owner {
+                self.disown(owner);
+            }
+            self.domains.insert(domain, count + 1);
+        }
+        self.memory = memory;
+        Ok(())
+    }
+
+    fn disown(&mut self, domain: u32) {
+        let empty = match self.domains.get_mut(&domain) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if empty {
+            self.domains.remove(&domain);
+        }
+    }
+
+    // drop the templates learned under an id that was just pinned
+    fn unlearn(&mut self, id: u16) {
+        self.unscoped.remove(id);
+        for scope in self.scoped.values_mut() {
+            scope.remove(id);
+        }
+        self.recount();
+    }
+
+    // rebuild the accounting after templates were dropped or pinned
+    fn recount(&mut self) {
+        self.scoped.retain(|_, scope| !scope.defined.is_empty());
+        self.domains.clear();
+        let pinned = self
+            .pinned_templates
+            .values()
+            .map(|t| t.field_specifiers.len())
+            .chain(
+                self.pinned_options_templates
+                    .values()
+                    .map(|t| t.field_specifiers.len()),
+            );
+        let mut memory: usize = pinned.map(template_size).sum();
+        for scope in iter::once(&self.unscoped).chain(self.scoped.values()) {
+            for &(domain, _) in scope.defined.values() {
+                *self.domains.entry(domain).or_insert(0) += 1;
+            }
+            memory += scope.memory();
+        }
+        if let Some(ref budget) = self.budget {
+            budget.force_resize(self.memory, memory);
+        }
+        self.memory = memory;
+    }
+}
+
+impl Scope {
+    // the domain and the field count of the template or options template `id`
+    fn defined(&self, id: u16) -> Option<(u32, usize)> {
+        let fields = match self.templates.get(&id) {
+            Some(t) => t.field_specifiers.len(),
+            None => self.options_templates.get(&id)?.field_specifiers.len(),
+        };
+        Some((self.defined.get(&id)?.0, fields))
+    }
+
+    fn remove(&mut self, id: u16) {
+        self.templates.remove(&id);
+        self.options_templates.remove(&id);
+        self.defined.remove(&id);
+    }
+
+    fn memory(&self) -> usize {
+        let templates = self.templates.values().map(|t| t.field_specifiers.len());
+        let options_templates = self
+            .options_templates
+            .values()
+            .map(|t| t.field_specifiers.len());
+        templates.chain(options_templates).map(template_size).sum()
+    }
+
+    // the templates as saved in a snapshot
+    fn entries<'s>(&'s self) -> impl Iterator<Item = TemplateEntry> + 's {
+        let templates = self
+            .templates
+            .iter()
+            .map(|(id, t)| (*id, Definition::Template(t.clone())));
+        let options_templates = self
+            .options_templates
+            .iter()
+            .map(|(id, t)| (*id, Definition::OptionsTemplate(t.clone())));
+        templates
+            .chain(options_templates)
+            .map(move |(id, definition)| {
+                let (domain, updated) = self.defined.get(&id).cloned().unwrap_or((0, 0));
+                TemplateEntry {
+                    observation_domain_id: domain,
+                    template_id: id,
+                    updated,
+                    definition,
+                }
+            })
+    }
+}
+
+/// the copy isn't charged to the budget of the sessions of the original
+impl Clone for State {
+    fn clone(&self) -> Self {
+        Self {
+            unscoped: self.unscoped.clone(),
+            scoped: self.scoped.clone(),
+            domain_scoped: self.domain_scoped,
+            pinned_templates: self.pinned_templates.clone(),
+            pinned_options_templates: self.pinned_options_templates.clone(),
+            refused: self.refused,
+            limits: self.limits,
+            domains: self.domains.clone(),
+            memory: self.memory,
+            stats: self.stats.clone(),
+            budget: None,
+        }
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        self.release_budget();
+    }
+}
+
+impl<K: Hash + Eq> Sessions<K> {
+    /// sessions bounded by `budget`, which can be shared with other sessions
+    pub fn with_budget(budget: Arc<Budget>) -> Self {
+        Self {
+            states: HashMap::default(),
+            budget,
+        }
+    }
+
+    pub fn get_budget(&self) -> &Arc<Budget> {
+        &self.budget
+    }
+
+    /// get the state of a session, creating an empty one if it doesn't
+    /// exist, an error when the budget has no session left
+    pub fn get_or_insert(&mut self, key: K) -> Result<&mut State> {
+        self.get_or_insert_with(key, State::new)
+    }
+
+    /// get the state of a session, creating it with `init` if it doesn't
+    /// exist, an error when the budget has no session left. The session
+    /// counts as used, see `evict_idle`.
+    pub fn get_or_insert_with<F: FnOnce() -> State>(
+        &mut self,
+        key: K,
+        init: F,
+    ) -> Result<&mut State> {
+        let now = Instant::now();
+        match self.states.entry(key) {
+            Entry::Occupied(e) => {
+                let session = e.into_mut();
+                session.used = now;
+                Ok(&mut session.state)
+            }
+            Entry::Vacant(e) => {
+                if !self.budget.open_session() {
+                    return Err(LimitError(format!(
+                        "over the limit of {} sessions",
+                        self.budget.max_sessions
+                    ))
+                    .into());
+                }
+                let mut state = init();
+                state.set_budget(self.budget.clone());
+                Ok(&mut e.insert(Session { state, used: now }).state)
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&State> {
+        self.states.get(key).map(|s| &s.state)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut State> {
+        self.states.get_mut(key).map(|s| &mut s.state)
+    }
+
+    /// drop a session and all of its templates, giving them back to the budget
+    pub fn remove(&mut self, key: &K) -> Option<State> {
+        let mut state = self.states.remove(key)?.state;
+        state.release_budget();
+        self.budget.close_sessions(1);
+        Some(state)
+    }
+
+    /// drop the sessions unused for `idle` or longer, returning their keys
+    pub fn evict_idle(&mut self, idle: Duration) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let idle: Vec<K> = self
+            .states
+            .iter()
+            .filter(|(_, s)| s.used.elapsed() >= idle)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &idle {
+            self.remove(key);
+        }
+        idle
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &State)> {
+        self.states.iter().map(|(k, s)| (k, &s.state))
+    }
+
+    /// copy the templates of every session
+    pub fn snapshot(&self) -> Vec<(K, StateSnapshot)>
+    where
+        K: Clone,
+    {
+        self.states
+            .iter()
+            .map(|(k, s)| (k.clone(), s.state.snapshot()))
+            .collect()
+    }
+
+    /// restore saved sessions, the state of a session that doesn't exist
+    /// yet is created with `init` before the templates are added. Returns
+    /// the number of templates that couldn't be restored, along with those
+    /// of the sessions over the budget.
+    pub fn restore<F: Fn() -> State>(
+        &mut self,
+        sessions: Vec<(K, StateSnapshot)>,
+        init: F,
+    ) -> usize {
+        let mut failed = 0;
+        for (key, snapshot) in sessions {
+            failed += match self.get_or_insert_with(key, &init) {
+                Ok(state) => state.restore(snapshot),
+                Err(_) => snapshot.templates.len(),
+            };
+        }
+        failed
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// the states give their memory back as they're dropped
+impl<K: Hash + Eq> Drop for Sessions<K> {
+    fn drop(&mut self) {
+        self.budget.close_sessions(self.states.len());
+    }
+}
+
+impl Serialize for State {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut state = State::new();
+        match state.restore(StateSnapshot::deserialize(deserializer)?) {
+            0 => Ok(state),
+            failed => Err(de::Error::custom(format!(
+                "{} templates are over the limits",
+                failed
+            ))),
+        }
+    }
+}
+
+/// write the snapshot of sessions along with the format version
+pub fn write_snapshot<K: Serialize, W: Write>(
+    writer: W,
+    sessions: Vec<(K, StateSnapshot)>,
+) -> Result<()> {
+    let snapshot = SessionsSnapshot {
+        version: SNAPSHOT_VERSION,
+        saved: now(),
+        sessions,
+    };
+    serde_json::to_writer(writer, &snapshot)?;
+    Ok(())
+}
+
+/// read sessions written by `write_snapshot`
+pub fn read_snapshot<K: DeserializeOwned, R: Read>(reader: R) -> Result<Vec<(K, StateSnapshot)>> {
+    let snapshot: SessionsSnapshot<K> = serde_json::from_reader(reader)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(anyhow!(
+            "unsupported snapshot version {}, expected {}",
+            snapshot.version,
+            SNAPSHOT_VERSION
+        ));
+    }
+    Ok(snapshot.sessions)
+}
+
+// seconds since the UNIX epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// approximate memory taken by a template with `fields` field specifiers
+fn template_size(fields: usize) -> usize {
+    mem::size_of::<parser::Template>() + fields * mem::size_of::<parser::FieldSpecifier>()
 }
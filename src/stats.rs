@@ -0,0 +1,197 @@
+use anyhow::Result;
+use parser;
+use state;
+use std::collections::BTreeMap;
+
+/// Stats counts what a session received, kept by `state::State` as messages
+/// are parsed. Messages are counted per observation domain, errors per
+/// session since a message that fails to parse may not have one.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub domains: BTreeMap<u32, DomainStats>,
+    pub parse_errors: ParseErrors,
+    /// templates and options templates held after the last message
+    pub templates: usize,
+    /// messages of observation domains over `state::Limits::max_domains`,
+    /// not counted per domain
+    pub untracked_messages: u64,
+}
+
+/// counters of a single observation domain
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DomainStats {
+    pub messages: u64,
+    pub bytes: u64,
+    pub template_sets: u64,
+    pub options_template_sets: u64,
+    pub data_sets: u64,
+    /// data records decoded per template id
+    pub records: BTreeMap<u16, u64>,
+    /// data sets dropped because their template wasn't known
+    pub missing_templates: u64,
+    /// messages whose sequence number didn't follow the records seen before
+    pub sequence_gaps: u64,
+    /// data records skipped by the sequence numbers, going by the gaps
+    pub lost_records: u64,
+    /// sequence number expected in the next message, unknown after a data
+    /// set couldn't be decoded
+    pub expected_sequence: Option<u32>,
+    /// templates and options templates defined for the first time
+    pub template_definitions: u64,
+    /// templates and options templates defined again, refreshed or changed
+    pub template_redefinitions: u64,
+}
+
+/// parse errors by kind
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ParseErrors {
+    /// messages with a malformed message or set header
+    pub headers: u64,
+    /// sets that couldn't be decoded with a known template, decoded no
+    /// record or left bytes other than padding after their records
+    pub sets: u64,
+    /// messages dropped because a `state::Limits` was exceeded
+    pub limits: u64,
+    /// messages rejected by the strict parser
+    pub violations: u64,
+}
+
+impl Stats {
+    /// messages received over all observation domains
+    pub fn messages(&self) -> u64 {
+        self.domains.values().map(|d| d.messages).sum()
+    }
+
+    /// bytes received over all observation domains
+    pub fn bytes(&self) -> u64 {
+        self.domains.values().map(|d| d.bytes).sum()
+    }
+
+    /// count a message of `length` bytes parsed with `state`. The state
+    /// counts the messages of `Parser::parse_message` itself, messages of
+    /// `Parser::parse_message_async` are counted by every thread on its own.
+    pub fn count(&mut self, state: &state::State, length: usize, result: &Result<parser::Message>) {
+        let max_domains = state.get_limits().max_domains;
+        self.add_message(length, result, max_domains, |domain, id| {
+            state.get_domain_field_specifiers(domain, &id).is_some()
+        });
+        self.templates = state.len();
+    }
+
+    /// add the counters of `other`, e.g. counted by another thread. The
+    /// sequence numbers expected are the ones of `other` when it has them.
+    pub fn merge(&mut self, other: &Stats) {
+        for (id, o) in &other.domains {
+            let d = self.domains.entry(*id).or_default();
+            d.messages += o.messages;
+            d.bytes += o.bytes;
+            d.template_sets += o.template_sets;
+            d.options_template_sets += o.options_template_sets;
+            d.data_sets += o.data_sets;
+            for (template, records) in &o.records {
+                *d.records.entry(*template).or_insert(0) += records;
+            }
+            d.missing_templates += o.missing_templates;
+            d.sequence_gaps += o.sequence_gaps;
+            d.lost_records += o.lost_records;
+            if o.expected_sequence.is_some() {
+                d.expected_sequence = o.expected_sequence;
+            }
+            d.template_definitions += o.template_definitions;
+            d.template_redefinitions += o.template_redefinitions;
+        }
+        let (e, o) = (&mut self.parse_errors, &other.parse_errors);
+        e.headers += o.headers;
+        e.sets += o.sets;
+        e.limits += o.limits;
+        e.violations += o.violations;
+        self.templates = self.templates.max(other.templates);
+        self.untracked_messages += other.untracked_messages;
+    }
+
+    pub(crate) fn add_definition(&mut self, domain: u32, replaced: bool) {
+        let d = self.domains.entry(domain).or_default();
+        if replaced {
+            d.template_redefinitions += 1;
+        } else {
+            d.template_definitions += 1;
+        }
+    }
+
+    // count a parsed message, `known` tells whether a template id is
+    // defined in an observation domain. Only `max_domains` domains are
+    // counted apart.
+    fn add_message<F: Fn(u32, u16) -> bool>(
+        &mut self,
+        length: usize,
+        result: &Result<parser::Message>,
+        max_domains: usize,
+        known: F,
+    ) {
+        let msg = match *result {
+            Ok(ref msg) => msg,
+            Err(ref e) => {
+                if e.is::<state::LimitError>() {
+                    self.parse_errors.limits += 1;
+                } else if e.is::<parser::Violation>() {
+                    self.parse_errors.violations += 1;
+                } else {
+                    self.parse_errors.headers += 1;
+                }
+                return;
+            }
+        };
+
+        let domain = msg.observation_domain_id;
+        if !self.domains.contains_key(&domain) && self.domains.len() >= max_domains {
+            self.untracked_messages += 1;
+            return;
+        }
+        let d = self.domains.entry(domain).or_default();
+        d.messages += 1;
+        d.bytes += length as u64;
+        let mut records = Some(0u32);
+        for set in &msg.sets {
+            match set.stype {
+                parser::SetType::Template => d.template_sets += 1,
+                parser::SetType::OptionTemplate => d.options_template_sets += 1,
+                parser::SetType::DataSet => {
+                    d.data_sets += 1;
+                    if set.data.is_empty() {
+                        records = None;
+                        if known(msg.observation_domain_id, set.hdr.set_id) {
+                            self.parse_errors.sets += 1;
+                        } else {
+                            d.missing_templates += 1;
+                        }
+                        continue;
+                    }
+                    // nothing decoded, or something left that isn't padding
+                    if set
+                        .data
+                        .iter()
+                        .any(|ds| ds.records.is_empty() || ds.trailing.iter().any(|b| *b != 0))
+                    {
+                        self.parse_errors.sets += 1;
+                    }
+                    let n: usize = set.data.iter().map(|ds| ds.records.len()).sum();
+                    *d.records.entry(set.hdr.set_id).or_insert(0) += n as u64;
+                    records = records.map(|r| r.wrapping_add(n as u32));
+                }
+            }
+        }
+
+        // the sequence number counts the data records sent before the message
+        if let Some(expected) = d.expected_sequence {
+            if expected != msg.sequence_number {
+                d.sequence_gaps += 1;
+                // a sequence number going backwards is a reordered message
+                let skipped = msg.sequence_number.wrapping_sub(expected);
+                if skipped < 1 << 31 {
+                    d.lost_records += u64::from(skipped);
+                }
+            }
+        }
+        d.expected_sequence = records.map(|r| msg.sequence_number.wrapping_add(r));
+    }
+}
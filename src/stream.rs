@@ -0,0 +1,231 @@
+use anyhow::{anyhow, Result};
+#[cfg(feature = "codec")]
+use owned;
+use parser;
+use state;
+use std::io::{self, Read};
+#[cfg(feature = "codec")]
+use std::sync::Arc;
+
+#[cfg(feature = "codec")]
+use bytes::BytesMut;
+#[cfg(feature = "codec")]
+use tokio_util::codec::Decoder;
+
+/// size of the IPFIX message header
+pub const MESSAGE_HEADER_LEN: usize = 16;
+
+/// get the length of the message at the start of `buf` from its header,
+/// returns `None` when the header isn't complete yet.
+pub fn message_len(buf: &[u8]) -> Result<Option<usize>> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+    let version = u16::from_be_bytes([buf[0], buf[1]]);
+    let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+    if version != 10 {
+        return Err(anyhow!("unexpected message version {}", version));
+    }
+    if length < MESSAGE_HEADER_LEN {
+        return Err(anyhow!("invalid message length {}", length));
+    }
+    Ok(Some(length))
+}
+
+// outcome of reading a message off a stream
+pub(crate) enum Frame {
+    Message,
+    Eof,
+    // the stream ended in the middle of a message
+    Truncated,
+}
+
+// read a single message into `buf`, partial reads are retried until
+// the whole message is read or the stream ends.
+pub(crate) fn read_frame<R: Read>(inner: &mut R, buf: &mut Vec<u8>) -> Result<Frame> {
+    buf.resize(MESSAGE_HEADER_LEN, 0);
+    let n = read_full(inner, buf)?;
+    if n == 0 {
+        return Ok(Frame::Eof);
+    }
+    if n < MESSAGE_HEADER_LEN {
+        return Ok(Frame::Truncated);
+    }
+
+    let length = message_len(buf)?.unwrap_or(MESSAGE_HEADER_LEN);
+    buf.resize(length, 0);
+    let n = read_full(inner, &mut buf[MESSAGE_HEADER_LEN..])?;
+    if n < length - MESSAGE_HEADER_LEN {
+        return Ok(Frame::Truncated);
+    }
+    Ok(Frame::Message)
+}
+
+/// MessageDecoder splits a stream of concatenated messages (IPFIX over TCP,
+/// RFC 7011 10.4) into single messages framed by their length. It keeps no
+/// templates, see `SessionDecoder` to decode the messages of a session.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessageDecoder;
+
+#[cfg(feature = "codec")]
+impl Decoder for MessageDecoder {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        let length = match message_len(src) {
+            Ok(Some(length)) => length,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        };
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+        Ok(Some(src.split_to(length)))
+    }
+}
+
+/// SessionDecoder decodes the messages of a stream such as a TCP connection
+/// with the templates of its session, which are cleared once the stream ends.
+#[cfg(feature = "codec")]
+pub struct SessionDecoder {
+    parser: Arc<parser::Parser>,
+    state: state::State,
+}
+
+#[cfg(feature = "codec")]
+impl SessionDecoder {
+    pub fn new(parser: Arc<parser::Parser>) -> Self {
+        Self::with_state(parser, state::State::new())
+    }
+
+    /// create a decoder whose session starts with `state`, e.g. one made by
+    /// `policy::Policy::new_state` or with `state::Limits`
+    pub fn with_state(parser: Arc<parser::Parser>, state: state::State) -> Self {
+        Self { parser, state }
+    }
+
+    /// the templates of the session, empty once the stream ended
+    pub fn get_state(&self) -> &state::State {
+        &self.state
+    }
+}
+
+#[cfg(feature = "codec")]
+impl Decoder for SessionDecoder {
+    type Item = owned::Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<owned::Message>> {
+        let buf = match MessageDecoder.decode(src)? {
+            Some(buf) => buf,
+            None => return Ok(None),
+        };
+        match self.parser.parse_message(&mut self.state, &buf) {
+            Ok(msg) => Ok(Some(owned::Message::from(&msg))),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<owned::Message>> {
+        if let Some(msg) = self.decode(src)? {
+            return Ok(Some(msg));
+        }
+        // a message cut short by the end of the stream is dropped, and the
+        // templates are scoped to the transport session (RFC 7011 8.1)
+        src.clear();
+        self.state.clear();
+        Ok(None)
+    }
+}
+
+/// Reader reads messages off a stream such as a TCP connection, the
+/// stream is a single session whose templates are cleared once it closes.
+pub struct Reader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    state: state::State,
+    closed: bool,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_state(inner, state::State::new())
+    }
+
+    /// create a reader whose session starts with `state`, e.g. one made by
+    /// `policy::Policy::new_state` or with `state::Limits`
+    pub fn with_state(inner: R, state: state::State) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            state,
+            closed: false,
+        }
+    }
+
+    /// read the next raw message, returns `None` once the stream is closed
+    pub fn read_raw(&mut self) -> Result<Option<&[u8]>> {
+        if self.closed {
+            return Ok(None);
+        }
+        match read_frame(&mut self.inner, &mut self.buf) {
+            Ok(Frame::Message) => Ok(Some(&self.buf)),
+            Ok(Frame::Eof) | Ok(Frame::Truncated) => {
+                self.close();
+                Ok(None)
+            }
+            Err(e) => {
+                // framing is lost, nothing after this can be trusted
+                self.close();
+                Err(e)
+            }
+        }
+    }
+
+    /// read and parse the next message, returns `None` once the stream is closed
+    pub fn read_message<'a>(
+        &'a mut self,
+        parser: &'a parser::Parser,
+    ) -> Result<Option<parser::Message<'a>>> {
+        if self.read_raw()?.is_none() {
+            return Ok(None);
+        }
+        let Reader {
+            ref mut state,
+            ref buf,
+            ..
+        } = *self;
+        parser.parse_message(state, buf).map(Some)
+    }
+
+    /// the templates of the session, empty once the stream is closed
+    pub fn get_state(&self) -> &state::State {
+        &self.state
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // templates are scoped to the transport session (RFC 7011 8.1)
+    fn close(&mut self) {
+        self.state.clear();
+        self.closed = true;
+    }
+}
+
+// like `read_exact` except it reports how much was read before EOF
+pub(crate) fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
@@ -0,0 +1,221 @@
+use parser::{DataRecord, DataRecordKey, DataRecordValue, Message};
+use rustc_hash::FxHashMap as HashMap;
+use std::net::IpAddr;
+
+/// seconds between the NTP epoch (1900) and the UNIX epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+// the keys a flow boundary can be expressed with, in order of preference
+struct Boundary {
+    millis: &'static str,
+    seconds: &'static str,
+    micros: &'static str,
+    nanos: &'static str,
+    delta_micros: &'static str,
+    sys_uptime: &'static str,
+}
+
+const START: Boundary = Boundary {
+    millis: "flowStartMilliSeconds",
+    seconds: "flowStartSeconds",
+    micros: "flowStartMicroSeconds",
+    nanos: "flowStartNanoSeconds",
+    delta_micros: "flowStartDeltaMicroSeconds",
+    sys_uptime: "flowStartSysUpTime",
+};
+
+const END: Boundary = Boundary {
+    millis: "flowEndMilliSeconds",
+    seconds: "flowEndSeconds",
+    micros: "flowEndMicroSeconds",
+    nanos: "flowEndNanoSeconds",
+    delta_micros: "flowEndDeltaMicroSeconds",
+    sys_uptime: "flowEndSysUpTime",
+};
+
+/// Normalizer converts the various flow timestamp styles into absolute
+/// `flowStartMilliSeconds` / `flowEndMilliSeconds` values (milliseconds since
+/// the UNIX epoch, UTC). `systemInitTimeMilliSeconds` is cached per exporter
+/// from options data to resolve sysUpTime relative fields.
+#[derive(Debug, Default, new)]
+pub struct Normalizer {
+    #[new(default)]
+    init_times: HashMap<IpAddr, u64>,
+}
+
+impl Normalizer {
+    /// learn `systemInitTimeMilliSeconds` from the options records in `msg`
+    pub fn learn(&mut self, exporter: IpAddr, msg: &Message) {
+        for record in msg.get_options_records() {
+            if let Some(init_time) = get_u64(record, "systemInitTimeMilliSeconds") {
+                self.init_times.insert(exporter, init_time);
+            }
+        }
+    }
+
+    /// set the exporter's init time when known from elsewhere, e.g. a NetFlow v9
+    /// header or the exporter's configuration.
+    pub fn set_system_init_time(&mut self, exporter: IpAddr, init_time_ms: u64) {
+        self.init_times.insert(exporter, init_time_ms);
+    }
+
+    pub fn get_system_init_time(&self, exporter: &IpAddr) -> Option<u64> {
+        self.init_times.get(exporter).cloned()
+    }
+
+    /// add absolute `flowStartMilliSeconds` and `flowEndMilliSeconds` to the record,
+    /// `export_time` is the export time of the message the record came in.
+    /// existing absolute millisecond values are kept as is.
+    pub fn normalize<'a>(&self, exporter: IpAddr, export_time: u32, record: &mut DataRecord<'a>) {
+        // an init time carried by the record itself takes precedence
+        let init_time = get_u64(record, "systemInitTimeMilliSeconds")
+            .or_else(|| self.get_system_init_time(&exporter));
+
+        for boundary in &[START, END] {
+            if record
+                .values
+                .contains_key(&DataRecordKey::Str(boundary.millis))
+            {
+                continue;
+            }
+            if let Some(ms) = absolute_millis(record, boundary, export_time, init_time) {
+                record.values.insert(
+                    DataRecordKey::Str(boundary.millis),
+                    DataRecordValue::U64(ms),
+                );
+            }
+        }
+    }
+
+    /// learn from the options records in `msg` then normalize its data records
+    pub fn process(&mut self, exporter: IpAddr, msg: &mut Message) {
+        self.learn(exporter, msg);
+        let export_time = msg.export_time;
+        for set in &mut msg.sets {
+            for ds in set.data.iter_mut().filter(|ds| !ds.is_options) {
+                for record in &mut ds.records {
+                    self.normalize(exporter, export_time, record);
+                }
+            }
+        }
+    }
+
+    /// forget the init time learned for an exporter
+    pub fn remove(&mut self, exporter: &IpAddr) -> Option<u64> {
+        self.init_times.remove(exporter)
+    }
+}
+
+/// absolute start of a flow in milliseconds since the UNIX epoch, the value
+/// `Normalizer::normalize` adds as `flowStartMilliSeconds`. sysUpTime fields
+/// are resolved with the record's `systemInitTimeMilliSeconds` or else
+/// `init_time`.
+pub fn flow_start_millis(
+    record: &DataRecord,
+    export_time: u32,
+    init_time: Option<u64>,
+) -> Option<u64> {
+    boundary_millis(record, &START, export_time, init_time)
+}
+
+/// absolute end of a flow in milliseconds since the UNIX epoch, see
+/// `flow_start_millis`
+pub fn flow_end_millis(
+    record: &DataRecord,
+    export_time: u32,
+    init_time: Option<u64>,
+) -> Option<u64> {
+    boundary_millis(record, &END, export_time, init_time)
+}
+
+/// convert an NTP timestamp into `per_second` units a second since the UNIX
+/// epoch, negative before 1970
+pub fn ntp_to_unix(ntp: u64, per_second: u64) -> i64 {
+    let seconds = (ntp >> 32) as i64 - NTP_UNIX_OFFSET as i64;
+    let fraction = ((ntp & 0xffff_ffff) * per_second) >> 32;
+    seconds * per_second as i64 + fraction as i64
+}
+
+/// convert an NTP timestamp (dateTimeMicroseconds / dateTimeNanoseconds)
+/// into milliseconds since the UNIX epoch.
+pub fn ntp_to_unix_millis(ntp: u64) -> Option<u64> {
+    let seconds = ntp >> 32;
+    let fraction = ntp & 0xffff_ffff;
+    seconds
+        .checked_sub(NTP_UNIX_OFFSET)
+        .map(|s| s * 1000 + ((fraction * 1000) >> 32))
+}
+
+/// format seconds and nanoseconds since the UNIX epoch as an RFC 3339 UTC
+/// timestamp with `digits` (up to 9) fraction digits, e.g.
+/// `2021-01-01T10:00:00.250Z`
+pub fn to_rfc3339(seconds: i64, nanos: u32, digits: usize) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    let mut out = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    );
+    if digits > 0 {
+        let fraction = format!("{:09}", nanos);
+        out.push('.');
+        out.push_str(&fraction[..digits.min(9)]);
+    }
+    out.push('Z');
+    out
+}
+
+// proleptic Gregorian (year, month, day) of days since the UNIX epoch
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn boundary_millis(
+    record: &DataRecord,
+    boundary: &Boundary,
+    export_time: u32,
+    init_time: Option<u64>,
+) -> Option<u64> {
+    let init_time = get_u64(record, "systemInitTimeMilliSeconds").or(init_time);
+    get_u64(record, boundary.millis)
+        .or_else(|| absolute_millis(record, boundary, export_time, init_time))
+}
+
+fn absolute_millis(
+    record: &DataRecord,
+    boundary: &Boundary,
+    export_time: u32,
+    init_time: Option<u64>,
+) -> Option<u64> {
+    get_u64(record, boundary.seconds)
+        .map(|s| s * 1000)
+        .or_else(|| get_u64(record, boundary.micros).and_then(ntp_to_unix_millis))
+        .or_else(|| get_u64(record, boundary.nanos).and_then(ntp_to_unix_millis))
+        .or_else(|| {
+            // delta fields count backwards from the message export time
+            get_u64(record, boundary.delta_micros)
+                .and_then(|d| (u64::from(export_time) * 1000).checked_sub(d / 1000))
+        })
+        .or_else(|| {
+            let uptime = get_u64(record, boundary.sys_uptime)?;
+            init_time.map(|init| init + uptime)
+        })
+}
+
+fn get_u64(record: &DataRecord, key: &str) -> Option<u64> {
+    record.get(key).and_then(|v| v.as_u64())
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use parser;
+use ring::digest;
+use rustls::crypto::ring as provider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use rustls_pemfile;
+use state;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stream;
+
+/// time an exporter has to complete the TLS handshake by default
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// a TLS session over TCP
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Acceptor terminates IPFIX over TLS (RFC 7011 11), exporters must present
+/// a certificate issued by one of the configured client CAs.
+pub struct Acceptor {
+    config: Arc<ServerConfig>,
+    handshake_timeout: Option<Duration>,
+}
+
+/// Connection is an authenticated TLS session, its templates are kept
+/// for the lifetime of the connection and never shared with other sessions.
+pub struct Connection {
+    key: state::SessionKey,
+    reader: stream::Reader<TlsStream>,
+}
+
+impl Acceptor {
+    /// create an acceptor from the PEM encoded server certificate chain,
+    /// server private key and the CA certificates exporters are verified against.
+    pub fn from_pem(cert_chain: &[u8], key: &[u8], client_cas: &[u8]) -> Result<Self> {
+        let provider = Arc::new(provider::default_provider());
+
+        let mut roots = RootCertStore::empty();
+        for ca in load_certs(client_cas)? {
+            roots
+                .add(ca)
+                .map_err(|e| anyhow!("invalid client CA certificate : {}", e))?;
+        }
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|e| anyhow!("failed to build client verifier : {}", e))?;
+
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(load_certs(cert_chain)?, load_private_key(key)?)
+            .map_err(|e| anyhow!("invalid server certificate : {}", e))?;
+        // exporters hold long lived sessions and never read from the
+        // connection, unread tickets would have it reset when they close.
+        config.send_tls13_tickets = 0;
+        Ok(Self::with_config(Arc::new(config)))
+    }
+
+    /// create an acceptor from a custom rustls configuration, the
+    /// configuration is expected to require client authentication.
+    pub fn with_config(config: Arc<ServerConfig>) -> Self {
+        Self {
+            config,
+            handshake_timeout: Some(DEFAULT_HANDSHAKE_TIMEOUT),
+        }
+    }
+
+    /// time an exporter has to complete the handshake once accepted, `None`
+    /// waits for as long as it takes.
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake_timeout = timeout;
+    }
+
+    /// complete the TLS handshake on an accepted TCP connection,
+    /// fails unless the exporter presented a valid certificate.
+    pub fn accept(&self, sock: TcpStream) -> Result<Connection> {
+        self.accept_with_state(sock, state::State::new())
+    }
+
+    /// like `accept` except the session starts with `state`, e.g. one made
+    /// by `policy::Policy::new_state` or with `state::Limits`
+    pub fn accept_with_state(&self, sock: TcpStream, state: state::State) -> Result<Connection> {
+        let exporter = sock.peer_addr()?;
+        let timeouts = (sock.read_timeout()?, sock.write_timeout()?);
+        let deadline = self.handshake_timeout.map(|t| Instant::now() + t);
+        let mut tls = StreamOwned::new(ServerConnection::new(self.config.clone())?, sock);
+        while tls.conn.is_handshaking() {
+            // every read and write is bounded by what's left of the handshake
+            if let Some(deadline) = deadline {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.as_nanos() == 0 {
+                    return Err(anyhow!("TLS handshake with {} timed out", exporter));
+                }
+                tls.sock.set_read_timeout(Some(left))?;
+                tls.sock.set_write_timeout(Some(left))?;
+            }
+            tls.conn
+                .complete_io(&mut tls.sock)
+                .map_err(|e| anyhow!("TLS handshake with {} failed : {}", exporter, e))?;
+        }
+        // exporters can stay quiet for long once connected
+        tls.sock.set_read_timeout(timeouts.0)?;
+        tls.sock.set_write_timeout(timeouts.1)?;
+
+        let identity = match tls.conn.peer_certificates() {
+            Some(certs) if !certs.is_empty() => peer_identity(&certs[0]),
+            _ => return Err(anyhow!("{} didn't present a certificate", exporter)),
+        };
+        Ok(Connection {
+            key: state::SessionKey::new(exporter, Some(identity)),
+            reader: stream::Reader::with_state(tls, state),
+        })
+    }
+}
+
+impl Connection {
+    /// the exporter's address along with its verified identity
+    pub fn session_key(&self) -> &state::SessionKey {
+        &self.key
+    }
+
+    /// read the next raw message, returns `None` once the session is closed
+    pub fn read_raw(&mut self) -> Result<Option<&[u8]>> {
+        self.reader.read_raw()
+    }
+
+    /// read and parse the next message, returns `None` once the session is closed
+    pub fn read_message<'a>(
+        &'a mut self,
+        parser: &'a parser::Parser,
+    ) -> Result<Option<parser::Message<'a>>> {
+        self.reader.read_message(parser)
+    }
+
+    pub fn get_state(&self) -> &state::State {
+        self.reader.get_state()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.reader.is_closed()
+    }
+}
+
+/// identity of a peer certificate, its SHA-256 fingerprint
+pub fn peer_identity(cert: &CertificateDer) -> String {
+    let fingerprint = digest::digest(&digest::SHA256, cert.as_ref());
+    let hex = fingerprint
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("sha256:{}", hex)
+}
+
+/// load all certificates of a PEM document
+pub fn load_certs(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow!("invalid PEM certificate : {}", e))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificate found"));
+    }
+    Ok(certs)
+}
+
+/// load the first private key of a PEM document
+pub fn load_private_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut &pem[..])
+        .map_err(|e| anyhow!("invalid PEM private key : {}", e))?
+        .ok_or_else(|| anyhow!("no private key found"))
+}
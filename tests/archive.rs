@@ -0,0 +1,127 @@
+mod common;
+
+#[cfg(all(test, feature = "parquet"))]
+mod tests {
+    extern crate parquet;
+    extern crate rsipfix;
+
+    use self::parquet::file::reader::{FileReader, SerializedFileReader};
+    use self::rsipfix::archive::{Config, Sink};
+    use self::rsipfix::columnar::Decoder;
+    use self::rsipfix::{parser, state};
+    use common;
+    use std::env;
+    use std::fs::{self, File};
+    use std::net::IpAddr;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    fn rows(path: &PathBuf) -> i64 {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.metadata().file_metadata().num_rows()
+    }
+
+    #[test]
+    fn rollover() {
+        let dir = env::temp_dir().join(format!("rsipfix-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = Config {
+            dir: dir.clone(),
+            bucket: Duration::from_secs(900),
+            ..Default::default()
+        };
+        let mut sink = Sink::new(config).unwrap();
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut decoder = Decoder::new(&p);
+        let exporter: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let template = |fields: &[(u16, u16)]| common::set(2, &common::template(256, fields));
+        let data = || common::set(256, &[10, 0, 0, 1, 10, 0, 0, 2]);
+        // 2021-01-01 10:00:00 and 10:05:00 UTC share the 10:00 bucket
+        let messages = [
+            common::message(1_609_495_200, 0, 1, &[template(&[(8, 4)]), data()]),
+            common::message(1_609_495_500, 2, 1, &[data()]),
+            // a changed template starts a new file
+            common::message(1_609_495_560, 4, 1, &[template(&[(12, 4)]), data()]),
+            // 10:15 is the next bucket
+            common::message(1_609_496_100, 6, 1, &[data()]),
+        ];
+        for msg in &messages {
+            for batch in decoder.decode_message(&mut s, msg).unwrap() {
+                sink.write(exporter, &batch).unwrap();
+            }
+        }
+        let mut files = sink.take_completed();
+        assert!(files.len() == 2);
+        assert!(sink.take_completed().is_empty());
+        assert!(sink.open_files() == 1);
+
+        files.extend(sink.close().unwrap());
+        let hour = dir.join("exporter=10.0.0.1/date=2021-01-01/hour=10");
+        assert!(
+            files
+                == [
+                    hour.join("1-256-1000-0.parquet"),
+                    hour.join("1-256-1000-1.parquet"),
+                    hour.join("1-256-1015-0.parquet"),
+                ]
+        );
+        assert!(files.iter().map(rows).collect::<Vec<_>>() == [4, 2, 2]);
+        assert!(fs::read_dir(&hour).unwrap().count() == 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn late_batches() {
+        let dir = env::temp_dir().join(format!("rsipfix-archive-late-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = Config {
+            dir: dir.clone(),
+            bucket: Duration::from_secs(900),
+            ..Default::default()
+        };
+        let mut sink = Sink::new(config).unwrap();
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut decoder = Decoder::new(&p);
+        let exporter: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let template = common::set(2, &common::template(256, &[(8, 4)]));
+        let data = || common::set(256, &[10, 0, 0, 1]);
+        // 10:14:59, 10:15:00 then 10:14:59 again across the boundary,
+        // years behind the collector's clock
+        let messages = [
+            common::message(1_609_496_099, 0, 1, &[template, data()]),
+            common::message(1_609_496_100, 1, 1, &[data()]),
+            common::message(1_609_496_099, 2, 1, &[data()]),
+            common::message(1_609_496_100, 3, 1, &[data()]),
+        ];
+        for msg in &messages {
+            for batch in decoder.decode_message(&mut s, msg).unwrap() {
+                sink.write(exporter, &batch).unwrap();
+            }
+        }
+        assert!(sink.take_completed().len() == 1);
+        assert!(sink.open_files() == 1);
+
+        // the bucket is over by the exporter's clock only after 15 minutes
+        let now = SystemTime::now();
+        assert!(sink.close_expired(now).unwrap().is_empty());
+        let closed = sink.close_expired(now + Duration::from_secs(900)).unwrap();
+        assert!(closed.len() == 1);
+        assert!(rows(&closed[0]) == 3);
+        assert!(sink.open_files() == 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_bucket() {
+        let config = Config {
+            dir: env::temp_dir(),
+            bucket: Duration::from_secs(7 * 60),
+            ..Default::default()
+        };
+        assert!(Sink::new(config).is_err());
+    }
+}
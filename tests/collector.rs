@@ -0,0 +1,172 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{collector, parser, policy};
+    use common;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn collect_udp() {
+        let config = collector::Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            workers: 2,
+            stats_interval: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let c = collector::Collector::bind(config).unwrap();
+        let (handle, records) = c
+            .spawn_channel(Arc::new(parser::Parser::new()), 16)
+            .unwrap();
+
+        let template = common::template(256, &[(8, 4), (7, 2)]);
+        let mut data = Vec::new();
+        data.extend_from_slice(&[10, 0, 0, 1]);
+        data.extend_from_slice(&53u16.to_be_bytes());
+
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = handle.local_addr();
+        exporter
+            .send_to(
+                &common::message(1000, 0, 7, &[common::set(2, &template)]),
+                target,
+            )
+            .unwrap();
+        exporter
+            .send_to(
+                &common::message(1001, 0, 7, &[common::set(256, &data)]),
+                target,
+            )
+            .unwrap();
+        exporter.send_to(&[0, 9, 0, 16], target).unwrap();
+
+        let record = records.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(record.exporter == exporter.local_addr().unwrap());
+        assert!(record.observation_domain_id == 7);
+        assert!(record.template_id == 256);
+        assert!(record.json.contains("\"sourceTransportPort\":53"));
+
+        // wait for the bad datagram to be accounted for
+        for _ in 0..50 {
+            if handle.counters().parse_errors == 1 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let counters = handle.counters();
+        assert!(counters.received == 3);
+        assert!(counters.messages == 2);
+        assert!(counters.parse_errors == 1);
+        assert!(counters.dropped == 0);
+
+        // statistics are published periodically
+        let exporter = exporter.local_addr().unwrap();
+        let mut stats = handle.exporter_stats();
+        for _ in 0..50 {
+            if stats.get(&exporter).map(|s| s.messages()) == Some(2) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+            stats = handle.exporter_stats();
+        }
+        let stats = &stats[&exporter];
+        assert!(stats.messages() == 2);
+        assert!(stats.parse_errors.headers == 1);
+        assert!(stats.domains[&7].records[&256] == 1);
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn reject_exporter() {
+        let mut allowed = policy::Policy::new();
+        allowed.allow_exporter("192.0.2.0/24".parse().unwrap());
+        let config = collector::Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            workers: 1,
+            policy: allowed,
+            ..Default::default()
+        };
+        let c = collector::Collector::bind(config).unwrap();
+        let (handle, records) = c
+            .spawn_channel(Arc::new(parser::Parser::new()), 16)
+            .unwrap();
+
+        let template = common::template(256, &[(8, 4)]);
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        exporter
+            .send_to(
+                &common::message(1000, 0, 7, &[common::set(2, &template)]),
+                handle.local_addr(),
+            )
+            .unwrap();
+
+        for _ in 0..50 {
+            if handle.counters().rejected == 1 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let counters = handle.counters();
+        assert!(counters.received == 1);
+        assert!(counters.rejected == 1);
+        assert!(counters.messages == 0);
+        assert!(records.try_recv().is_err());
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn evict_idle_exporters() {
+        let config = collector::Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            workers: 1,
+            stats_interval: Duration::from_millis(10),
+            idle_timeout: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let c = collector::Collector::bind(config).unwrap();
+        let (handle, records) = c
+            .spawn_channel(Arc::new(parser::Parser::new()), 16)
+            .unwrap();
+
+        let template = common::template(256, &[(8, 4)]);
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        exporter
+            .send_to(
+                &common::message(1000, 0, 7, &[common::set(2, &template)]),
+                handle.local_addr(),
+            )
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let mut seen = false;
+        for _ in 0..100 {
+            let stats = handle.exporter_stats();
+            seen |= stats.contains_key(&addr);
+            if seen && stats.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(seen && handle.exporter_stats().is_empty());
+
+        // the template went with the session
+        exporter
+            .send_to(
+                &common::message(1001, 0, 7, &[common::set(256, &[10, 0, 0, 1])]),
+                handle.local_addr(),
+            )
+            .unwrap();
+        for _ in 0..50 {
+            if handle.counters().messages == 2 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(handle.counters().messages == 2);
+        assert!(records.try_recv().is_err());
+        handle.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+mod common;
+
+#[cfg(all(test, feature = "arrow"))]
+mod tests {
+    extern crate arrow;
+    extern crate rsipfix;
+
+    use self::arrow::array::{
+        BinaryArray, FixedSizeBinaryArray, StringArray, TimestampMillisecondArray, UInt32Array,
+        UInt64Array,
+    };
+    use self::arrow::datatypes::{DataType, TimeUnit};
+    use self::rsipfix::columnar::{schema, Decoder};
+    use self::rsipfix::{parser, state};
+    use common;
+
+    #[test]
+    fn decode() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut decoder = Decoder::new(&p);
+        // octetDeltaCount in 4 bytes (reduced size), sourceIPv4Address,
+        // sourceIPv6Address, flowStartMilliSeconds, interfaceName, unknown field
+        let fields = [(1, 4), (8, 4), (27, 16), (152, 8), (82, 65535), (999, 2)];
+        let template = common::set(2, &common::template(256, &fields));
+        let mut records = Vec::new();
+        for (i, name) in [&b"eth0"[..], &b"eth10"[..]].iter().enumerate() {
+            records.extend_from_slice(&(1000 * (i as u32 + 1)).to_be_bytes());
+            records.extend_from_slice(&[10, 0, 0, i as u8 + 1]);
+            records
+                .extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+            records.extend_from_slice(&1_600_000_000_000u64.to_be_bytes());
+            records.extend_from_slice(&common::varlen(name));
+            records.extend_from_slice(&[0xab, 0xcd]);
+        }
+        // padding
+        records.push(0);
+        let msg = common::message(1000, 0, 7, &[template, common::set(256, &records)]);
+
+        let batches = decoder.decode_message(&mut s, &msg).unwrap();
+        assert!(batches.len() == 1);
+        assert!(batches[0].observation_domain_id == 7 && batches[0].template_id == 256);
+        let batch = &batches[0].batch;
+        assert!(batch.num_rows() == 2);
+
+        let schema = batch.schema();
+        let types: Vec<(&str, &DataType)> = schema
+            .fields()
+            .iter()
+            .map(|f| (f.name().as_str(), f.data_type()))
+            .collect();
+        assert!(types[0] == ("octetDeltaCount", &DataType::UInt64));
+        assert!(types[1] == ("sourceIPv4Address", &DataType::UInt32));
+        assert!(types[2] == ("sourceIPv6Address", &DataType::FixedSizeBinary(16)));
+        assert!(
+            types[3]
+                == (
+                    "flowStartMilliSeconds",
+                    &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+                )
+        );
+        assert!(types[4] == ("interfaceName", &DataType::Utf8));
+        assert!(types[5] == ("field_999", &DataType::Binary));
+
+        let column = |i: usize| batch.column(i).as_any();
+        let octets = column(0).downcast_ref::<UInt64Array>().unwrap();
+        assert!(octets.value(0) == 1000 && octets.value(1) == 2000);
+        let ipv4 = column(1).downcast_ref::<UInt32Array>().unwrap();
+        assert!(ipv4.value(1) == 0x0a00_0002);
+        let ipv6 = column(2).downcast_ref::<FixedSizeBinaryArray>().unwrap();
+        assert!(ipv6.value(0)[15] == 1);
+        let start = column(3)
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert!(start.value(0) == 1_600_000_000_000);
+        let names = column(4).downcast_ref::<StringArray>().unwrap();
+        assert!(names.value(0) == "eth0" && names.value(1) == "eth10");
+        let unknown = column(5).downcast_ref::<BinaryArray>().unwrap();
+        assert!(unknown.value(1) == [0xab, 0xcd]);
+    }
+
+    #[test]
+    fn schema_per_template() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut decoder = Decoder::new(&p);
+        let define = |fields: &[(u16, u16)]| {
+            common::message(
+                1000,
+                0,
+                1,
+                &[common::set(2, &common::template(300, fields))],
+            )
+        };
+
+        decoder
+            .decode_message(&mut s, &define(&[(8, 4), (8, 4), (12, 4)]))
+            .unwrap();
+        let first = decoder.schema(&s, 1, 300).unwrap();
+        let names: Vec<&str> = first.fields().iter().map(|f| f.name().as_str()).collect();
+        assert!(
+            names
+                == [
+                    "sourceIPv4Address",
+                    "sourceIPv4Address_2",
+                    "destinationIPv4Address"
+                ]
+        );
+        assert!(*first == schema(&p, &s.get_template(&300).unwrap().field_specifiers));
+
+        // a redefined template gets a new schema, a wrong length is kept as bytes
+        decoder.decode_message(&mut s, &define(&[(8, 6)])).unwrap();
+        let second = decoder.schema(&s, 1, 300).unwrap();
+        assert!(second.fields().len() == 1);
+        assert!(*second.field(0).data_type() == DataType::Binary);
+        assert!(decoder.schema(&s, 1, 301).is_none());
+    }
+}
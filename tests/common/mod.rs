@@ -0,0 +1,64 @@
+// helpers to assemble IPFIX messages for tests, not every test
+// binary uses all of them.
+#![allow(dead_code)]
+
+/// assemble a message from its sets
+pub fn message(export_time: u32, sequence_number: u32, domain: u32, sets: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = sets.concat();
+    let mut buf = Vec::with_capacity(16 + body.len());
+    buf.extend_from_slice(&10u16.to_be_bytes());
+    buf.extend_from_slice(&(16 + body.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&export_time.to_be_bytes());
+    buf.extend_from_slice(&sequence_number.to_be_bytes());
+    buf.extend_from_slice(&domain.to_be_bytes());
+    buf.extend_from_slice(&body);
+    buf
+}
+
+/// assemble a set from its id and body
+pub fn set(set_id: u16, body: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4 + body.len());
+    buf.extend_from_slice(&set_id.to_be_bytes());
+    buf.extend_from_slice(&(4 + body.len() as u16).to_be_bytes());
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// template record with (ident, length) field specifiers
+pub fn template(id: u16, fields: &[(u16, u16)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    for (ident, length) in fields {
+        buf.extend_from_slice(&ident.to_be_bytes());
+        buf.extend_from_slice(&length.to_be_bytes());
+    }
+    buf
+}
+
+/// options template record with (ident, length) field specifiers,
+/// the first `scope_count` fields are scope fields.
+pub fn options_template(id: u16, scope_count: u16, fields: &[(u16, u16)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&scope_count.to_be_bytes());
+    for (ident, length) in fields {
+        buf.extend_from_slice(&ident.to_be_bytes());
+        buf.extend_from_slice(&length.to_be_bytes());
+    }
+    buf
+}
+
+/// variable length encoded field value, in the long form from 255 bytes
+pub fn varlen(value: &[u8]) -> Vec<u8> {
+    let mut buf = if value.len() < 255 {
+        vec![value.len() as u8]
+    } else {
+        let mut prefix = vec![255];
+        prefix.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        prefix
+    };
+    buf.extend_from_slice(value);
+    buf
+}
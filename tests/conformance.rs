@@ -0,0 +1,114 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::conformance::{Checker, Deviation, Report};
+    use self::rsipfix::parser;
+    use common;
+    use std::net::SocketAddr;
+
+    fn kinds(report: &Report) -> Vec<Deviation> {
+        let e = &report.exporters[0];
+        let mut kinds: Vec<Deviation> = e
+            .findings
+            .iter()
+            .chain(e.templates.iter().flat_map(|t| t.findings.iter()))
+            .map(|f| f.kind)
+            .collect();
+        kinds.sort();
+        kinds
+    }
+
+    #[test]
+    fn conforming() {
+        let exporter: SocketAddr = "192.0.2.1:4739".parse().unwrap();
+        let mut c = Checker::new(parser::Parser::new());
+        let template = common::set(2, &common::template(256, &[(8, 4), (1, 8)]));
+        let record = [10, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 42];
+        let records = common::set(256, &[&record[..], &record[..]].concat());
+        let msg = common::message(1000, 0, 1, &[template.clone(), records.clone()]);
+        c.check(exporter, &msg).unwrap();
+        let msg = common::message(1100, 2, 1, &[template, records]);
+        c.check(exporter, &msg).unwrap();
+
+        let report = c.report();
+        assert!(kinds(&report).is_empty());
+        assert!(report.exporters[0].messages == 2);
+        assert!(report.exporters[0].templates[0].definitions == 2);
+        assert!(report.exporters[0].templates[0].data_sets == 2);
+    }
+
+    #[test]
+    fn deviations() {
+        let exporter: SocketAddr = "192.0.2.1:4739".parse().unwrap();
+        let mut c = Checker::new(parser::Parser::new());
+
+        // data before its template
+        let early = common::set(256, &[10, 0, 0, 1]);
+        c.check(exporter, &common::message(1000, 0, 1, &[early]))
+            .unwrap();
+
+        // IPv4 address in 16 bytes, reduced size counter and unknown field
+        let template = common::template(256, &[(8, 16), (1, 4), (32000, 2)]);
+        c.check(
+            exporter,
+            &common::message(1001, 5, 1, &[common::set(2, &template)]),
+        )
+        .unwrap();
+
+        // the sequence number skips a record, template not refreshed
+        let records = [common::set(256, &[0; 22])];
+        c.check(exporter, &common::message(1002, 5, 1, &records))
+            .unwrap();
+        c.check(exporter, &common::message(2000, 9, 1, &records))
+            .unwrap();
+
+        let report = c.report();
+        assert!(
+            kinds(&report)
+                == vec![
+                    Deviation::MissingTemplate,
+                    Deviation::MissingTemplateRefresh,
+                    Deviation::SequenceNumber,
+                    Deviation::LengthMismatch,
+                    Deviation::ReducedSize,
+                    Deviation::UnknownField,
+                ]
+        );
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"kind\":\"missingTemplateRefresh\""));
+        assert!(json.contains("\"observationDomainId\":1"));
+    }
+
+    #[test]
+    fn padding() {
+        let exporter: SocketAddr = "192.0.2.1:4739".parse().unwrap();
+        let mut c = Checker::new(parser::Parser::new());
+        let mut padded = common::template(256, &[(8, 4)]);
+        padded.extend_from_slice(&[0, 1]);
+        c.check(
+            exporter,
+            &common::message(1000, 0, 1, &[common::set(2, &padded)]),
+        )
+        .unwrap();
+        let report = c.report();
+        assert!(kinds(&report) == vec![Deviation::Padding]);
+        assert!(report.exporters[0].findings[0].count == 1);
+    }
+
+    #[test]
+    fn truncated_record() {
+        let exporter: SocketAddr = "192.0.2.1:4739".parse().unwrap();
+        let mut c = Checker::new(parser::Parser::with_mode(parser::Mode::Strict));
+        let template = common::set(2, &common::template(256, &[(8, 4)]));
+        let records = common::set(256, &[10, 0, 0, 1, 10, 0]);
+        c.check(exporter, &common::message(1000, 0, 1, &[template, records]))
+            .unwrap();
+        let report = c.report();
+        assert!(kinds(&report) == vec![Deviation::TruncatedRecord]);
+        assert!(report.exporters[0].templates[0].data_sets == 1);
+        assert!(report.exporters[0].templates[0].definitions == 1);
+    }
+}
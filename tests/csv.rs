@@ -0,0 +1,121 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{csv, parser, state};
+    use common;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn sections() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut w = csv::Writer::new(&p, Vec::new());
+        let exporter = Some("10.0.0.9:4739".parse().unwrap());
+
+        // sourceIPv4Address, sourceMacAddress, flowStartMilliSeconds,
+        // interfaceName, unknown field
+        let fields = [(8, 4), (56, 6), (152, 8), (82, 65535), (999, 2)];
+        let mut record = vec![10, 0, 0, 1, 0, 0x1b, 0x21, 0x3c, 0x4d, 0x5e];
+        record.extend_from_slice(&1_609_495_200_250u64.to_be_bytes());
+        record.extend_from_slice(&common::varlen(b"eth0, uplink"));
+        record.extend_from_slice(&[0xab, 0xcd]);
+        let sets = [
+            common::set(2, &common::template(256, &fields)),
+            common::set(2, &common::template(257, &[(7, 2), (7, 2)])),
+            common::set(256, &record),
+            common::set(257, &[0, 80, 1, 187]),
+        ];
+        let msg = common::message(1_609_495_260, 0, 3, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        w.write_message(&s, exporter, &parsed).unwrap();
+
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(
+            out == "exporter,exportTime,observationDomainId,templateId,sourceIPv4Address,\
+                    sourceMacAddress,flowStartMilliSeconds,interfaceName,field_999\n\
+                    10.0.0.9:4739,2021-01-01T10:01:00Z,3,256,10.0.0.1,00:1b:21:3c:4d:5e,\
+                    2021-01-01T10:00:00.250Z,\"eth0, uplink\",0xabcd\n\
+                    \n\
+                    exporter,exportTime,observationDomainId,templateId,sourceTransportPort,\
+                    sourceTransportPort_2\n\
+                    10.0.0.9:4739,2021-01-01T10:01:00Z,3,257,80,443\n"
+        );
+    }
+
+    #[test]
+    fn files() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let dir = env::temp_dir().join(format!("rsipfix-csv-{}", std::process::id()));
+        let mut files = csv::Files::new(&p, &dir).unwrap();
+
+        let define = |fields: &[(u16, u16)]| common::set(2, &common::template(256, fields));
+        let messages = [
+            common::message(1000, 0, 1, &[define(&[(7, 2)]), common::set(256, &[0, 80])]),
+            common::message(1001, 1, 1, &[common::set(256, &[0, 81])]),
+            // redefined with other fields, a new file
+            common::message(
+                1002,
+                2,
+                1,
+                &[define(&[(11, 2)]), common::set(256, &[0, 53])],
+            ),
+        ];
+        for msg in &messages {
+            let parsed = p.parse_message(&mut s, msg).unwrap();
+            files.write_message(&s, None, &parsed).unwrap();
+        }
+        files.flush().unwrap();
+
+        assert!(files.paths() == [dir.join("1-256.csv"), dir.join("1-256-2.csv")]);
+        let first = fs::read_to_string(dir.join("1-256.csv")).unwrap();
+        assert!(first.lines().count() == 3);
+        assert!(first.ends_with(",1,256,81\n"));
+        let second = fs::read_to_string(dir.join("1-256-2.csv")).unwrap();
+        assert!(second.starts_with(
+            "exporter,exportTime,observationDomainId,templateId,destinationTransportPort\n"
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bounded_open_files() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let dir = env::temp_dir().join(format!("rsipfix-csv-open-{}", std::process::id()));
+        let mut files = csv::Files::with_max_open(&p, &dir, 1).unwrap();
+
+        let templates = [
+            common::set(2, &common::template(256, &[(7, 2)])),
+            common::set(2, &common::template(257, &[(11, 2)])),
+        ];
+        let msg = common::message(1000, 0, 1, &templates);
+        p.parse_message(&mut s, &msg).unwrap();
+        // alternate between the templates, reopening their files
+        for (i, id) in [256, 257, 256, 257].iter().enumerate() {
+            let msg = common::message(1001, i as u32, 1, &[common::set(*id, &[0, i as u8])]);
+            let parsed = p.parse_message(&mut s, &msg).unwrap();
+            files.write_message(&s, None, &parsed).unwrap();
+            assert!(files.open_files() == 1);
+        }
+        files.flush().unwrap();
+
+        for (name, rows) in &[("1-256.csv", ",1,256,0\n"), ("1-257.csv", ",1,257,1\n")] {
+            let content = fs::read_to_string(dir.join(name)).unwrap();
+            assert!(content.lines().count() == 3);
+            assert!(
+                content
+                    .lines()
+                    .filter(|l| l.starts_with("exporter"))
+                    .count()
+                    == 1
+            );
+            assert!(content.contains(rows));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+// the derived impls refer to `::rsipfix`, which has to be declared at the
+// crate root
+#[cfg(feature = "derive")]
+extern crate rsipfix;
+
+mod common;
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use common;
+    use rsipfix::parser::ValueError;
+    use rsipfix::{formatter, parser, state, IpfixRecord};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[derive(IpfixRecord, Debug, PartialEq)]
+    struct Flow {
+        #[ipfix(ie = 8)]
+        src: IpAddr,
+        #[ipfix(ie = 12)]
+        dst: Ipv4Addr,
+        // sent reduced to 4 bytes
+        #[ipfix(ie = 1)]
+        octets: u64,
+        #[ipfix(ie = 7)]
+        src_port: Option<u16>,
+        #[ipfix(ie = 2, default)]
+        packets: u64,
+        #[ipfix(pen = 35632, ie = 205)]
+        query: String,
+        #[ipfix(skip)]
+        seen: usize,
+    }
+
+    // only built to fail
+    #[allow(dead_code)]
+    #[derive(IpfixRecord, Debug)]
+    struct Narrow {
+        #[ipfix(ie = 1)]
+        octets: u8,
+    }
+
+    // field 999 has no formatter in either enterprise
+    #[derive(IpfixRecord, Debug)]
+    struct Unformatted {
+        #[ipfix(ie = 999)]
+        iana: Option<Vec<u8>>,
+        #[ipfix(pen = 35632, ie = 999)]
+        enterprise: Option<Vec<u8>>,
+    }
+
+    #[test]
+    fn unformatted_fields() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let sets = [
+            common::set(2, &common::template(256, &[(999, 2)])),
+            common::set(256, &[0xab, 0xcd]),
+        ];
+        let msg = common::message(1000, 0, 1, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        let r = &parsed.sets[1].data[0].records[0];
+
+        // the record doesn't tell which enterprise the field belongs to
+        let u = Unformatted::from_record(&p, r).unwrap();
+        assert!(u.iana.is_none() && u.enterprise.is_none());
+    }
+
+    #[test]
+    fn from_record() {
+        let mut p = parser::Parser::new();
+        p.add_custom_field(35632, 205, "DNS_QUERY", formatter::be_string);
+        let mut s = state::State::new();
+
+        // sourceIPv4Address, destinationIPv4Address, octetDeltaCount and
+        // DNS_QUERY of enterprise 35632
+        let mut template = common::template(256, &[(8, 4), (12, 4), (1, 4)]);
+        template[3] = 4;
+        template.extend_from_slice(&[0x80, 205, 0xff, 0xff, 0, 0, 0x8b, 0x30]);
+        let mut record = vec![10, 0, 0, 1, 10, 0, 0, 2, 0, 0, 0x05, 0xdc];
+        record.extend_from_slice(&common::varlen(b"example.com"));
+        let sets = [common::set(2, &template), common::set(256, &record)];
+        let msg = common::message(1000, 0, 1, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        let r = &parsed.sets[1].data[0].records[0];
+
+        let flow = Flow::from_record(&p, r).unwrap();
+        assert!(
+            flow == Flow {
+                src: "10.0.0.1".parse().unwrap(),
+                dst: Ipv4Addr::new(10, 0, 0, 2),
+                octets: 1500,
+                src_port: None,
+                packets: 0,
+                query: "example.com".to_string(),
+                seen: 0,
+            }
+        );
+        assert!(
+            Narrow::from_record(&p, r).unwrap_err()
+                == ValueError::Overflow {
+                    name: "octetDeltaCount",
+                    value: 1500
+                }
+        );
+        // without the custom field the enterprise is unknown
+        let plain = parser::Parser::new();
+        let mut s = state::State::new();
+        let parsed = plain.parse_message(&mut s, &msg).unwrap();
+        let r = &parsed.sets[1].data[0].records[0];
+        assert!(Flow::from_record(&plain, r).unwrap_err() == ValueError::Missing { name: "query" });
+    }
+}
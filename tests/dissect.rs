@@ -0,0 +1,63 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::dissect::{dissect, Node};
+    use self::rsipfix::{parser, state};
+    use common;
+
+    fn find<'a>(node: &'a Node, label: &str) -> &'a Node {
+        node.children.iter().find(|n| n.label == label).unwrap()
+    }
+
+    #[test]
+    fn offsets() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let template = common::set(2, &common::template(256, &[(8, 4), (82, 65535)]));
+        let mut record = vec![10, 0, 0, 1];
+        record.extend_from_slice(&common::varlen(b"eth0"));
+        let mut records = record.clone();
+        records.extend_from_slice(&record);
+        records.extend_from_slice(&[0, 0]);
+        let msg = common::message(1000, 7, 1, &[template, common::set(256, &records)]);
+
+        let root = dissect(&p, &mut s, &msg).unwrap();
+        assert!(root.length == msg.len());
+        assert!(find(&root, "sequenceNumber").value == Some("7".to_string()));
+
+        // the template is learned before the data set
+        let t = find(find(&root, "Template Set 2"), "Template 256");
+        assert!(t.offset == 20 && t.length == 12);
+        assert!(t.children[3].offset == 28);
+        assert!(t.children[3].field.as_ref().unwrap().ident == 82);
+
+        let ds = find(&root, "Data Set 256");
+        assert!(ds.offset == 32);
+        let r1 = find(ds, "Record 1");
+        assert!(r1.offset == 45 && r1.length == 9);
+        let name = &r1.children[1];
+        assert!(name.label == "interfaceName");
+        assert!(name.offset == 49 && name.length == 5);
+        assert!(name.value == Some("eth0".to_string()));
+        let padding = find(ds, "padding");
+        assert!(padding.offset == 54 && padding.length == 2);
+
+        // the templates were kept for the next message
+        assert!(s.get_template(&256).is_some());
+        let text = root.to_string();
+        assert!(text.contains("0031     5       interfaceName: eth0 (id 82, length 65535)"));
+    }
+
+    #[test]
+    fn unknown_template() {
+        let p = parser::Parser::new();
+        let msg = common::message(1000, 0, 1, &[common::set(300, &[1, 2, 3, 4])]);
+        let root = dissect(&p, &mut state::State::new(), &msg).unwrap();
+        let unknown = find(find(&root, "Data Set 300"), "unknown template");
+        assert!(unknown.offset == 20 && unknown.length == 4);
+        assert!(dissect(&p, &mut state::State::new(), &msg[..10]).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{enrich, parser, state};
+    use common;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn options_message() -> Vec<u8> {
+        // interface table: ingressInterface (scope), interfaceName
+        let if_template = common::options_template(300, 1, &[(10, 4), (82, 65535)]);
+        // vrf table: ingressVRFID (scope), VRFname
+        let vrf_template = common::options_template(301, 1, &[(234, 4), (236, 65535)]);
+
+        let mut if_data = Vec::new();
+        if_data.extend_from_slice(&537u32.to_be_bytes());
+        if_data.extend_from_slice(&common::varlen(b"Gi0/0/1\0\0"));
+        if_data.extend_from_slice(&538u32.to_be_bytes());
+        if_data.extend_from_slice(&common::varlen(b"Gi0/0/2"));
+
+        let mut vrf_data = Vec::new();
+        vrf_data.extend_from_slice(&7u32.to_be_bytes());
+        vrf_data.extend_from_slice(&common::varlen(b"customer-a"));
+
+        common::message(
+            1000,
+            0,
+            1,
+            &[
+                common::set(3, &[if_template, vrf_template].concat()),
+                common::set(300, &if_data),
+                common::set(301, &vrf_data),
+            ],
+        )
+    }
+
+    fn data_message() -> Vec<u8> {
+        let template = common::template(256, &[(8, 4), (10, 4), (14, 2), (234, 4)]);
+        let mut data = Vec::new();
+        data.extend_from_slice(&[10, 0, 0, 1]);
+        data.extend_from_slice(&537u32.to_be_bytes());
+        data.extend_from_slice(&538u16.to_be_bytes());
+        data.extend_from_slice(&7u32.to_be_bytes());
+        common::message(
+            1001,
+            1,
+            1,
+            &[common::set(2, &template), common::set(256, &data)],
+        )
+    }
+
+    #[test]
+    fn enrich_interface_and_vrf_names() {
+        let exporter = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let mut s = state::State::new();
+        let p = parser::Parser::new();
+        let mut e = enrich::Enricher::new();
+
+        let options_bytes = options_message();
+        let mut msg = p.parse_message(&mut s, &options_bytes).unwrap();
+        assert!(msg.get_options_records().len() == 3);
+        e.process(exporter, &mut msg);
+
+        let names = e.get(&exporter).unwrap();
+        assert!(names.interfaces_len() == 2);
+        assert!(names.interface_name(537) == Some("Gi0/0/1"));
+        assert!(names.vrf_name(7) == Some("customer-a"));
+
+        let data_bytes = data_message();
+        let mut msg = p.parse_message(&mut s, &data_bytes).unwrap();
+        e.process(exporter, &mut msg);
+        let records = msg.get_dataset_records();
+        assert!(records.len() == 1);
+        let record = records[0];
+        assert!(
+            record
+                .values
+                .get(&parser::DataRecordKey::Str("ingressInterfaceName"))
+                .unwrap()
+                == &parser::DataRecordValue::String("Gi0/0/1".to_string())
+        );
+        assert!(
+            record
+                .values
+                .get(&parser::DataRecordKey::Str("egressInterfaceName"))
+                .unwrap()
+                == &parser::DataRecordValue::String("Gi0/0/2".to_string())
+        );
+        assert!(
+            record
+                .values
+                .get(&parser::DataRecordKey::Str("vrfName"))
+                .unwrap()
+                == &parser::DataRecordValue::String("customer-a".to_string())
+        );
+
+        // names are cached per exporter
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let mut msg = p.parse_message(&mut s, &data_bytes).unwrap();
+        e.process(other, &mut msg);
+        let records = msg.get_dataset_records();
+        assert!(!records[0]
+            .values
+            .contains_key(&parser::DataRecordKey::Str("ingressInterfaceName")));
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{file, parser};
+    use common;
+    use std::io::Cursor;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn write_and_read_file() {
+        let template = common::template(256, &[(8, 4), (7, 2)]);
+        let mut data = Vec::new();
+        data.extend_from_slice(&[10, 0, 0, 1]);
+        data.extend_from_slice(&53u16.to_be_bytes());
+        let template_msg = common::message(1000, 0, 1, &[common::set(2, &template)]);
+        let data_msg = common::message(1001, 0, 1, &[common::set(256, &data)]);
+
+        let details = file::ExportSessionDetails {
+            exporter: "192.0.2.1:4739".parse().unwrap(),
+            collector: "[2001:db8::1]:4739".parse().unwrap(),
+            transport_protocol: 17,
+            observation_domain_id: 1,
+            min_export_seconds: 1000,
+            max_export_seconds: 1001,
+        };
+
+        let mut w = file::Writer::new(Vec::new());
+        w.write_export_session_details(&details).unwrap();
+        w.write_message(&template_msg).unwrap();
+        w.write_message(&data_msg).unwrap();
+        assert!(w.write_message(&data_msg[..10]).is_err());
+        let mut buf = w.into_inner();
+        // a truncated tail
+        buf.extend_from_slice(&data_msg[..20]);
+
+        let p = parser::Parser::new();
+        let mut r = file::Reader::new(Cursor::new(buf));
+
+        let msg = r.read_message(&p).unwrap().unwrap();
+        let options = msg.get_options_records();
+        assert!(options.len() == 1);
+        assert!(
+            options[0].get("exporterIPv4Address")
+                == Some(&parser::DataRecordValue::IPv4(Ipv4Addr::new(192, 0, 2, 1)))
+        );
+        assert!(
+            options[0]
+                .get("collectorTransportPort")
+                .and_then(|v| v.as_u64())
+                == Some(4739)
+        );
+        assert!(options[0].get("maxExportSeconds").and_then(|v| v.as_u64()) == Some(1001));
+
+        // templates are kept across messages
+        assert!(r.read_message(&p).unwrap().is_some());
+        let msg = r.read_message(&p).unwrap().unwrap();
+        let records = msg.get_dataset_records();
+        assert!(records.len() == 1);
+        assert!(records[0].get("sourceTransportPort") == Some(&parser::DataRecordValue::U16(53)));
+
+        assert!(r.read_message(&p).unwrap().is_none());
+        assert!(r.is_truncated());
+        assert!(r.get_state().templates_len() == 1);
+        assert!(r.get_state().options_templates_len() == 1);
+    }
+}
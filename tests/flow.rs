@@ -0,0 +1,112 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::flow::{Direction, Flow};
+    use self::rsipfix::{parser, state};
+    use common;
+
+    fn flows(fields: &[(u16, u16)], record: &[u8], init_time: Option<u64>) -> Vec<Flow> {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let sets = [
+            common::set(2, &common::template(256, fields)),
+            common::set(256, record),
+        ];
+        let msg = common::message(1_609_495_260, 0, 1, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        Flow::from_message(&parsed, init_time)
+    }
+
+    #[test]
+    fn ipv4() {
+        // sourceIPv4Address, destinationIPv4Address, sourceTransportPort,
+        // tcpSourcePort (ignored), protocolIdentifier, octetDeltaCount,
+        // octetTotalCount (ignored), flowStartSeconds, flowDurationMilliSeconds,
+        // tcpControlBits, flowDirection
+        let fields = [
+            (8, 4),
+            (12, 4),
+            (7, 2),
+            (182, 2),
+            (4, 1),
+            (1, 8),
+            (85, 8),
+            (150, 4),
+            (161, 4),
+            (6, 1),
+            (61, 1),
+        ];
+        let mut record = vec![10, 0, 0, 1, 10, 0, 0, 2, 0, 80, 0, 81, 6];
+        record.extend_from_slice(&1500u64.to_be_bytes());
+        record.extend_from_slice(&9000u64.to_be_bytes());
+        record.extend_from_slice(&1_609_495_200u32.to_be_bytes());
+        record.extend_from_slice(&250u32.to_be_bytes());
+        record.extend_from_slice(&[0x12, 1]);
+
+        let flows = flows(&fields, &record, None);
+        assert!(
+            flows
+                == [Flow {
+                    src_addr: Some("10.0.0.1".parse().unwrap()),
+                    dst_addr: Some("10.0.0.2".parse().unwrap()),
+                    src_port: Some(80),
+                    dst_port: None,
+                    protocol: Some(6),
+                    bytes: Some(1500),
+                    packets: None,
+                    start: Some(1_609_495_200_000),
+                    end: Some(1_609_495_200_250),
+                    ingress_interface: None,
+                    egress_interface: None,
+                    direction: Some(Direction::Egress),
+                    tcp_flags: Some(0x12),
+                }]
+        );
+    }
+
+    #[test]
+    fn fallbacks() {
+        // sourceIPv4Address zeroed, sourceIPv6Address, udpDestinationPort, nextHeaderIPv6,
+        // postOctetDeltaCount, packetTotalCount, flowStartSysUpTime,
+        // flowEndSysUpTime, ingressPhysicalInterface, egressInterface
+        let fields = [
+            (8, 4),
+            (27, 16),
+            (181, 2),
+            (193, 1),
+            (23, 4),
+            (86, 4),
+            (22, 4),
+            (21, 4),
+            (252, 4),
+            (14, 2),
+        ];
+        let mut record = vec![
+            0, 0, 0, 0, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        record.extend_from_slice(&[0, 53, 17]);
+        record.extend_from_slice(&120u32.to_be_bytes());
+        record.extend_from_slice(&2u32.to_be_bytes());
+        record.extend_from_slice(&1000u32.to_be_bytes());
+        record.extend_from_slice(&3000u32.to_be_bytes());
+        record.extend_from_slice(&7u32.to_be_bytes());
+        record.extend_from_slice(&[0, 9]);
+
+        let flow = &flows(&fields, &record, Some(1_609_495_000_000))[0];
+        assert!(flow.src_addr == Some("2001:db8::1".parse().unwrap()));
+        assert!(flow.dst_port == Some(53));
+        assert!(flow.protocol == Some(17));
+        assert!(flow.bytes == Some(120));
+        assert!(flow.packets == Some(2));
+        assert!(flow.start == Some(1_609_495_001_000));
+        assert!(flow.end == Some(1_609_495_003_000));
+        assert!(flow.ingress_interface == Some(7));
+        assert!(flow.egress_interface == Some(9));
+        assert!(flow.direction.is_none());
+        // without an init time sysUpTime can't be resolved
+        assert!(flows(&fields, &record, None)[0].start.is_none());
+    }
+}
@@ -0,0 +1,108 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::parser::ValueError;
+    use self::rsipfix::{formatter, ie, parser, state};
+    use common;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn typed_getters() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        // sourceIPv4Address, octetDeltaCount reduced to 4 bytes,
+        // protocolIdentifier, sourceMacAddress, interfaceName
+        let fields = [(8, 4), (1, 4), (4, 1), (56, 6), (82, 65535)];
+        let mut record = vec![10, 0, 0, 1, 0, 1, 0, 0, 6, 0, 0x1b, 0x21, 0x3c, 0x4d, 0x5e];
+        record.extend_from_slice(&common::varlen(b"eth0"));
+        let sets = [
+            common::set(2, &common::template(256, &fields)),
+            common::set(256, &record),
+        ];
+        let msg = common::message(1000, 0, 1, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        let r = &parsed.sets[1].data[0].records[0];
+
+        assert!(r.get_ipv4(ie::SOURCE_IPV4_ADDRESS) == Ok(Ipv4Addr::new(10, 0, 0, 1)));
+        assert!(r.get_u64(ie::OCTET_DELTA_COUNT) == Ok(65536));
+        assert!(r.get_u32(ie::OCTET_DELTA_COUNT) == Ok(65536));
+        assert!(r.get_u64(ie::PROTOCOL_IDENTIFIER) == Ok(6));
+        assert!(r.get_mac(ie::SOURCE_MAC_ADDRESS) == Ok([0, 0x1b, 0x21, 0x3c, 0x4d, 0x5e]));
+        assert!(r.get_str(ie::INTERFACE_NAME) == Ok("eth0"));
+
+        assert!(
+            r.get_u16(ie::OCTET_DELTA_COUNT)
+                == Err(ValueError::Overflow {
+                    name: "octetDeltaCount",
+                    value: 65536
+                })
+        );
+        assert!(
+            r.get_u64(ie::SOURCE_IPV4_ADDRESS)
+                == Err(ValueError::Mismatch {
+                    name: "sourceIPv4Address",
+                    expected: "unsigned",
+                    found: "ipv4"
+                })
+        );
+        assert!(
+            r.get_ipv6(ie::DESTINATION_IPV6_ADDRESS)
+                == Err(ValueError::Missing {
+                    name: "destinationIPv6Address"
+                })
+        );
+    }
+
+    #[test]
+    fn lookups() {
+        assert!(ie::by_id(8) == Some(ie::SOURCE_IPV4_ADDRESS));
+        assert!(ie::by_name("octetDeltaCount") == Some(ie::OCTET_DELTA_COUNT));
+        assert!(ie::by_id(65000).is_none());
+        assert!(ie::OCTET_DELTA_COUNT.data_type == Some(formatter::DataType::Unsigned64));
+        // the registry beyond the default formatters
+        assert!(ie::by_id(457) == Some(ie::HTTP_STATUS_CODE));
+    }
+
+    #[test]
+    fn named_like_records() {
+        // named like the fields of the records, registry names aside
+        let p = parser::Parser::new();
+        for (&id, &(name, _)) in &formatter::get_default_parsers() {
+            assert!(ie::by_id(id).map(|e| e.name) == Some(name), "{}", id);
+        }
+        for e in ie::ALL {
+            let name = p.get_field_name(0, e.id);
+            assert!(name.is_none() || name == Some(e.name));
+        }
+        assert!(ie::OBSERVATION_DOMAIN_ID.name == "sourceId");
+        assert!(ie::by_name("sourceId") == Some(ie::OBSERVATION_DOMAIN_ID));
+        assert!(ie::FLOW_START_MILLISECONDS.name == "flowStartMilliSeconds");
+    }
+
+    #[test]
+    fn decoded_as_before() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        // bgpNextHopIPv4Address, decoded as an integer, and
+        // httpStatusCode, without a formatter
+        let fields = [(18, 4), (457, 2)];
+        let sets = [
+            common::set(2, &common::template(256, &fields)),
+            common::set(256, &[192, 0, 2, 1, 0x01, 0xf4]),
+        ];
+        let msg = common::message(1000, 0, 1, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        let r = &parsed.sets[1].data[0].records[0];
+
+        assert!(r.get("bgpNextHopIPv4Address") == Some(&parser::DataRecordValue::U32(0xc000_0201)));
+        assert!(r.get_ipv4(ie::BGP_NEXT_HOP_IPV4_ADDRESS) == Ok(Ipv4Addr::new(192, 0, 2, 1)));
+        assert!(r.get("httpStatusCode").is_none());
+        assert!(r
+            .values
+            .contains_key(&parser::DataRecordKey::Unrecognized(457)));
+        assert!(r.get_u16(ie::HTTP_STATUS_CODE) == Ok(500));
+    }
+}
@@ -0,0 +1,168 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::file;
+    use common;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    // run ipfixdump with `input` on its stdin
+    fn ipfixdump(args: &[&str], input: &[u8]) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ipfixdump"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // ipfixdump exits without reading stdin on bad arguments
+        let _ = child.stdin.take().unwrap().write_all(input);
+        child.wait_with_output().unwrap()
+    }
+
+    fn stdout(out: &Output) -> String {
+        String::from_utf8(out.stdout.clone()).unwrap()
+    }
+
+    // a template and a record of it, in its own message
+    fn messages(domain: u32, template_id: u16, port: u16) -> Vec<Vec<u8>> {
+        let template = common::template(template_id, &[(8, 4), (7, 2)]);
+        let mut data = vec![10, 0, 0, 1];
+        data.extend_from_slice(&port.to_be_bytes());
+        vec![
+            common::message(1000, 0, domain, &[common::set(2, &template)]),
+            common::message(1001, 0, domain, &[common::set(template_id, &data)]),
+        ]
+    }
+
+    #[test]
+    fn arguments() {
+        let rejected = [
+            (&[][..], "no input given"),
+            (&["-f"][..], "missing value for -f"),
+            (&["-f", "-", "--verbose"][..], "unknown argument --verbose"),
+            (&["-f", "-", "-o", "xml"][..], "unknown output format xml"),
+            (&["-f", "-", "--domain", "one"][..], "invalid number one"),
+            (&["-u", "localhost"][..], "invalid address localhost"),
+            (
+                &["-f", "-", "--exporter", "192.0.2.1"][..],
+                "--exporter can't filter an IPFIX file",
+            ),
+        ];
+        for &(args, error) in &rejected {
+            let out = ipfixdump(args, &[]);
+            let stderr = String::from_utf8(out.stderr).unwrap();
+            assert!(out.status.code() == Some(2), "{:?}", args);
+            assert!(stderr.starts_with(error), "{:?}: {}", args, stderr);
+            assert!(stderr.contains("usage: ipfixdump"));
+        }
+
+        let out = ipfixdump(&["--help"], &[]);
+        assert!(out.status.success());
+        assert!(stdout(&out).starts_with("usage: ipfixdump"));
+
+        let out = ipfixdump(&["-f", "-"], &[]);
+        assert!(out.status.success() && out.stdout.is_empty());
+    }
+
+    #[test]
+    fn filters() {
+        let mut w = file::Writer::new(Vec::new());
+        for msg in messages(1, 256, 53)
+            .iter()
+            .chain(&messages(2, 257, 80))
+            .chain(&messages(2, 258, 443))
+        {
+            w.write_message(msg).unwrap();
+        }
+        let input = w.into_inner();
+
+        let out = ipfixdump(&["-f", "-"], &input);
+        assert!(out.status.success());
+        assert!(stdout(&out).matches("sourceTransportPort = ").count() == 3);
+
+        let out = ipfixdump(&["-f", "-", "--domain", "2"], &input);
+        let tree = stdout(&out);
+        assert!(tree.matches("message ").count() == 4);
+        assert!(!tree.contains("domain=1"));
+        assert!(tree.contains("template id=257") && tree.contains("template id=258"));
+
+        let out = ipfixdump(&["-f", "-", "--template", "257"], &input);
+        let tree = stdout(&out);
+        // messages are shown, without the sets of other templates
+        assert!(tree.matches("message ").count() == 6);
+        assert!(tree.matches("template id=").count() == 1);
+        assert!(tree.contains("sourceTransportPort = 80"));
+        assert!(!tree.contains("sourceTransportPort = 53"));
+
+        let out = ipfixdump(&["-f", "-", "-o", "json", "--template", "258"], &input);
+        let json = stdout(&out);
+        assert!(json.lines().count() == 1);
+        assert!(
+            json.contains("\"templateId\":258") && json.contains("\"sourceTransportPort\":443")
+        );
+
+        let out = ipfixdump(&["-f", "-", "-o", "csv", "--domain", "1"], &input);
+        let csv = stdout(&out);
+        assert!(csv.lines().count() == 2);
+        assert!(csv.contains(",53"));
+    }
+
+    // a classic pcap of raw IP packets, UDP from the given exporters to 4739
+    fn capture(datagrams: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        capture.extend_from_slice(&65535u32.to_le_bytes());
+        capture.extend_from_slice(&101u32.to_le_bytes());
+        for &(exporter, ref payload) in datagrams {
+            let mut packet = vec![0x45, 0];
+            packet.extend_from_slice(&(28 + payload.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0x40, 0, 64, 17, 0, 0]);
+            packet.extend_from_slice(&exporter);
+            packet.extend_from_slice(&[192, 0, 2, 100]);
+            packet.extend_from_slice(&50000u16.to_be_bytes());
+            packet.extend_from_slice(&4739u16.to_be_bytes());
+            packet.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0]);
+            packet.extend_from_slice(payload);
+
+            capture.extend_from_slice(&[0; 8]);
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&packet);
+        }
+        capture
+    }
+
+    #[test]
+    fn exporter_filter() {
+        let mut datagrams = Vec::new();
+        for msg in messages(1, 256, 53) {
+            datagrams.push(([192, 0, 2, 1], msg));
+        }
+        for msg in messages(1, 256, 80) {
+            datagrams.push(([192, 0, 2, 2], msg));
+        }
+        let path = env::temp_dir().join(format!("rsipfix-dump-{}.pcap", std::process::id()));
+        fs::write(&path, capture(&datagrams)).unwrap();
+        let path = path.to_str().unwrap();
+
+        let out = ipfixdump(&["-r", path, "-o", "json"], &[]);
+        assert!(stdout(&out).lines().count() == 2);
+
+        let out = ipfixdump(&["-r", path, "-o", "json", "--exporter", "192.0.2.2"], &[]);
+        let json = stdout(&out);
+        assert!(json.lines().count() == 1);
+        assert!(json.contains("192.0.2.2:50000") && json.contains("\"sourceTransportPort\":80"));
+
+        let out = ipfixdump(&["-r", path, "--exporter", "192.0.2.3"], &[]);
+        assert!(out.status.success() && out.stdout.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::jsonl::{self, ByteEncoding, Naming, Options, Writer};
+    use self::rsipfix::{parser, state};
+    use common;
+
+    fn write(options: Options) -> String {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        // 65000 has no formatter, so its value stays bytes
+        let sets = [
+            common::set(
+                2,
+                &common::template(256, &[(8, 4), (7, 2), (65000 & 0x7fff, 4)]),
+            ),
+            common::set(256, &[10, 0, 0, 1, 0, 53, 0xfb, 0xff, 0x00, 0x01]),
+        ];
+        let buf = common::message(1000, 0, 3, &sets);
+        let msg = p.parse_message(&mut s, &buf).unwrap();
+        let mut w = Writer::new(Vec::new(), options);
+        w.write_message(Some("192.0.2.1:4739".parse().unwrap()), &msg)
+            .unwrap();
+        String::from_utf8(w.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn records() {
+        assert!(
+            write(Options::default())
+                == concat!(
+                    r#"{"sourceIPv4Address":"10.0.0.1","sourceTransportPort":53,"32232":"fbff0001"}"#,
+                    "\n"
+                )
+        );
+        let options = Options {
+            bytes: ByteEncoding::Base64,
+            naming: Naming::SnakeCase,
+            metadata: true,
+        };
+        assert!(
+            write(options)
+                == concat!(
+                    r#"{"exporter":"192.0.2.1:4739","observation_domain_id":3,"export_time":1000,"#,
+                    r#""template_id":256,"record":{"source_ipv4_address":"10.0.0.1","#,
+                    r#""source_transport_port":53,"32232":"+/8AAQ=="}}"#,
+                    "\n"
+                )
+        );
+    }
+
+    #[test]
+    fn snake_case() {
+        assert!(jsonl::snake_case("octetDeltaCount") == "octet_delta_count");
+        assert!(jsonl::snake_case("postNATSourceIPv6Address") == "post_nat_source_ipv6_address");
+        assert!(jsonl::snake_case("dot1qVlanId") == "dot1q_vlan_id");
+        assert!(jsonl::snake_case("ingressVRFID") == "ingress_vrfid");
+    }
+}
@@ -0,0 +1,223 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate anyhow;
+    extern crate rsipfix;
+
+    use self::anyhow::Result;
+    use self::rsipfix::{parser, state};
+    use common;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn templates(domain: u32, ids: &[u16]) -> Vec<u8> {
+        let body = ids
+            .iter()
+            .flat_map(|id| common::template(*id, &[(8, 4)]))
+            .collect::<Vec<u8>>();
+        common::message(1000, 0, domain, &[common::set(2, &body)])
+    }
+
+    fn is_limit(r: Result<parser::Message>) -> bool {
+        match r {
+            Err(e) => e.is::<state::LimitError>(),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn fields_per_template() {
+        let p = parser::Parser::new();
+        let mut s = state::State::with_limits(state::Limits {
+            max_fields_per_template: 2,
+            ..Default::default()
+        });
+        let fields = [(8, 4), (12, 4), (7, 2)];
+        let msg = common::message(
+            1000,
+            0,
+            1,
+            &[common::set(2, &common::template(256, &fields))],
+        );
+        assert!(is_limit(p.parse_message(&mut s, &msg)));
+        assert!(s.is_empty());
+
+        // the field count is checked before anything is allocated
+        let mut huge = 256u16.to_be_bytes().to_vec();
+        huge.extend_from_slice(&u16::MAX.to_be_bytes());
+        let msg = common::message(1000, 0, 1, &[common::set(2, &huge)]);
+        assert!(is_limit(p.parse_message(&mut s, &msg)));
+    }
+
+    #[test]
+    fn templates_and_domains() {
+        let p = parser::Parser::new();
+        let mut s = state::State::with_limits(state::Limits {
+            max_templates_per_domain: 2,
+            max_domains: 2,
+            ..Default::default()
+        });
+        assert!(p.parse_message(&mut s, &templates(1, &[256, 257])).is_ok());
+        // redefinitions don't count twice
+        assert!(p.parse_message(&mut s, &templates(1, &[256])).is_ok());
+        assert!(is_limit(p.parse_message(&mut s, &templates(1, &[258]))));
+        assert!(p.parse_message(&mut s, &templates(2, &[300])).is_ok());
+        assert!(is_limit(p.parse_message(&mut s, &templates(3, &[400]))));
+        assert!(s.templates_len() == 3);
+        assert!(s.domains_len() == 2);
+
+        s.clear();
+        assert!(s.memory() == 0);
+        assert!(p.parse_message(&mut s, &templates(3, &[400])).is_ok());
+    }
+
+    #[test]
+    fn records_per_set() {
+        let p = parser::Parser::new();
+        let mut s = state::State::with_limits(state::Limits {
+            max_records_per_set: 2,
+            ..Default::default()
+        });
+        p.parse_message(&mut s, &templates(1, &[256])).unwrap();
+
+        let two = common::message(1001, 1, 1, &[common::set(256, &[10, 0, 0, 1, 10, 0, 0, 2])]);
+        assert!(p.parse_message(&mut s, &two).is_ok());
+        let three = common::message(
+            1002,
+            2,
+            1,
+            &[common::set(256, &[10, 0, 0, 1, 10, 0, 0, 2, 10, 0, 0, 3])],
+        );
+        assert!(is_limit(p.parse_message(&mut s, &three)));
+    }
+
+    #[test]
+    fn memory() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        p.parse_message(&mut s, &templates(1, &[256])).unwrap();
+        let one = s.memory();
+        assert!(one > 0);
+
+        s.set_limits(state::Limits {
+            max_memory: one * 2,
+            ..Default::default()
+        });
+        assert!(p.parse_message(&mut s, &templates(1, &[257])).is_ok());
+        assert!(is_limit(p.parse_message(&mut s, &templates(1, &[258]))));
+        assert!(s.memory() == one * 2);
+    }
+
+    #[test]
+    fn templates_per_domain() {
+        let p = parser::Parser::new();
+        let mut s = state::State::domain_scoped();
+        s.set_limits(state::Limits {
+            max_templates_per_domain: 1,
+            ..Default::default()
+        });
+        // the same id in two domains is two templates
+        p.parse_message(&mut s, &templates(1, &[256])).unwrap();
+        let other = common::template(256, &[(8, 4), (12, 4)]);
+        let msg = common::message(1000, 0, 2, &[common::set(2, &other)]);
+        p.parse_message(&mut s, &msg).unwrap();
+        assert!(s.templates_len() == 2 && s.domains_len() == 2);
+        assert!(
+            s.get_domain_template(1, &256)
+                .unwrap()
+                .field_specifiers
+                .len()
+                == 1
+        );
+        assert!(
+            s.get_domain_template(2, &256)
+                .unwrap()
+                .field_specifiers
+                .len()
+                == 2
+        );
+        assert!(s.get_domain_template(3, &256).is_none());
+
+        // redefining it in one domain leaves the other alone
+        p.parse_message(&mut s, &templates(2, &[256])).unwrap();
+        assert!(s.templates_len() == 2 && s.domains_len() == 2);
+        assert!(is_limit(p.parse_message(&mut s, &templates(2, &[257]))));
+
+        let record = common::message(1001, 1, 1, &[common::set(256, &[10, 0, 0, 1])]);
+        let msg = p.parse_message(&mut s, &record).unwrap();
+        assert!(msg.get_dataset_records().len() == 1);
+    }
+
+    #[test]
+    fn templates_shared_by_domains() {
+        let p = parser::Parser::new();
+        let mut s = state::State::with_limits(state::Limits {
+            max_templates_per_domain: 1,
+            ..Default::default()
+        });
+        // unscoped, the id is one template counted against its last definer
+        p.parse_message(&mut s, &templates(1, &[256])).unwrap();
+        p.parse_message(&mut s, &templates(2, &[256])).unwrap();
+        assert!(s.templates_len() == 1 && s.domains_len() == 1);
+        assert!(s.get_domain_template(3, &256) == s.get_template(&256));
+        assert!(p.parse_message(&mut s, &templates(1, &[257])).is_ok());
+        assert!(is_limit(p.parse_message(&mut s, &templates(2, &[258]))));
+    }
+
+    #[test]
+    fn budget() {
+        let p = parser::Parser::new();
+        let mut one = state::State::new();
+        p.parse_message(&mut one, &templates(1, &[256])).unwrap();
+        let size = one.memory();
+
+        let budget = Arc::new(state::Budget::new(2, size * 3));
+        let mut workers = (
+            state::Sessions::with_budget(budget.clone()),
+            state::Sessions::with_budget(budget.clone()),
+        );
+        let s = workers.0.get_or_insert(1).unwrap();
+        assert!(p.parse_message(s, &templates(1, &[256, 257])).is_ok());
+        let s = workers.1.get_or_insert(2).unwrap();
+        assert!(p.parse_message(s, &templates(1, &[256])).is_ok());
+        assert!(budget.sessions() == 2 && budget.memory() == size * 3);
+
+        // both limits hold over the sessions sharing the budget
+        assert!(is_limit(p.parse_message(s, &templates(1, &[257]))));
+        let e = workers.1.get_or_insert(3).unwrap_err();
+        assert!(e.is::<state::LimitError>());
+
+        workers.0.remove(&1);
+        assert!(budget.sessions() == 1 && budget.memory() == size);
+        let s = workers.1.get_or_insert(3).unwrap();
+        assert!(p.parse_message(s, &templates(1, &[256, 257])).is_ok());
+
+        // copies, e.g. a scratch state, aren't charged
+        let copy = s.clone();
+        assert!(copy.memory() == size * 2);
+        drop(copy);
+        assert!(budget.memory() == size * 3);
+
+        drop(workers);
+        assert!(budget.sessions() == 0 && budget.memory() == 0);
+    }
+
+    #[test]
+    fn evict_idle() {
+        let p = parser::Parser::new();
+        let budget = Arc::new(state::Budget::default());
+        let mut sessions = state::Sessions::with_budget(budget.clone());
+        let s = sessions.get_or_insert(1).unwrap();
+        p.parse_message(s, &templates(1, &[256])).unwrap();
+        sessions.get_or_insert(2).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        // a message keeps the session alive
+        sessions.get_or_insert(2).unwrap();
+        assert!(sessions.evict_idle(Duration::from_millis(50)) == [1]);
+        assert!(sessions.get(&1).is_none() && sessions.get(&2).is_some());
+        assert!(budget.sessions() == 1 && budget.memory() == 0);
+    }
+}
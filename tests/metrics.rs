@@ -0,0 +1,87 @@
+mod common;
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{collector, metrics, parser};
+    use common;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream, UdpSocket};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn scrape() {
+        let config = collector::Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            workers: 1,
+            stats_interval: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let c = collector::Collector::bind(config).unwrap();
+        let (handle, records) = c
+            .spawn_channel(Arc::new(parser::Parser::new()), 16)
+            .unwrap();
+        let server = handle
+            .serve_metrics("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+
+        let template = common::template(256, &[(8, 4)]);
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sets = [common::set(2, &template), common::set(256, &[10, 0, 0, 1])];
+        exporter
+            .send_to(&common::message(1000, 0, 7, &sets), handle.local_addr())
+            .unwrap();
+        records.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let labels = format!(
+            "{{exporter=\"{}\",domain=\"7\"",
+            exporter.local_addr().unwrap()
+        );
+        // statistics are published periodically
+        let messages = format!("ipfix_exporter_messages_total{}}} 1\n", labels);
+        let mut response = get(server.local_addr(), "/metrics");
+        for _ in 0..50 {
+            if response.contains(&messages) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+            response = get(server.local_addr(), "/metrics");
+        }
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("text/plain; version=0.0.4"));
+        assert!(response.contains("# TYPE ipfix_collector_datagrams_received_total counter\n"));
+        assert!(response.contains("ipfix_collector_messages_total 1\n"));
+        assert!(response.contains(&messages));
+        assert!(response.contains(&format!(
+            "ipfix_exporter_records_total{},template=\"256\"}} 1\n",
+            labels
+        )));
+        assert!(get(server.local_addr(), "/").starts_with("HTTP/1.1 404"));
+
+        server.shutdown();
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn idle_client() {
+        let render: metrics::Render = Arc::new(|| "up 1\n".to_string());
+        let server = metrics::Server::bind("127.0.0.1:0".parse().unwrap(), render).unwrap();
+
+        // a client that never sends its request doesn't hold up scrapes
+        let _idle = TcpStream::connect(server.local_addr()).unwrap();
+        let start = Instant::now();
+        assert!(get(server.local_addr(), "/metrics").ends_with("\r\n\r\nup 1\n"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        server.shutdown();
+    }
+}
@@ -185,6 +185,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn display_values() {
+        let p = parser::Parser::new();
+        assert_eq!(p.get_field_name(0, 8), Some("sourceIPv4Address"));
+        assert_eq!(p.get_field_name(0, 65000), None);
+
+        let ip = parser::DataRecordValue::IPv4(Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(ip.to_string(), "10.0.0.1");
+        assert_eq!(
+            parser::DataRecordValue::Bytes(&[0xde, 0xad]).to_string(),
+            "0xdead"
+        );
+        assert_eq!(
+            parser::DataRecordValue::MPLS(16, 0, 1).to_string(),
+            "16/0/1"
+        );
+        assert_eq!(parser::DataRecordKey::Unrecognized(999).to_string(), "999");
+    }
+
     #[test]
     fn concurrency() {
         // A state to be shared between parsing threads
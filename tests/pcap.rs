@@ -0,0 +1,287 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{parser, pcap, state};
+    use common;
+    use std::io::Cursor;
+    use std::net::SocketAddr;
+
+    fn ipv4(protocol: u8, src: [u8; 4], dst: [u8; 4], transport: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0];
+        ip.extend_from_slice(&(20 + transport.len() as u16).to_be_bytes());
+        ip.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+        ip.extend_from_slice(&src);
+        ip.extend_from_slice(&dst);
+        ip.extend_from_slice(transport);
+        ip
+    }
+
+    fn udp(sport: u16, dport: u16, payload: &[u8]) -> Vec<u8> {
+        let mut udp = Vec::new();
+        udp.extend_from_slice(&sport.to_be_bytes());
+        udp.extend_from_slice(&dport.to_be_bytes());
+        udp.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(payload);
+        udp
+    }
+
+    fn tcp(sport: u16, dport: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut tcp = Vec::new();
+        tcp.extend_from_slice(&sport.to_be_bytes());
+        tcp.extend_from_slice(&dport.to_be_bytes());
+        tcp.extend_from_slice(&seq.to_be_bytes());
+        tcp.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        tcp.extend_from_slice(payload);
+        tcp
+    }
+
+    fn ethernet(ip: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(ip);
+        frame
+    }
+
+    // a classic pcap of raw IP packets
+    fn raw_capture(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        capture.extend_from_slice(&65535u32.to_le_bytes());
+        capture.extend_from_slice(&101u32.to_le_bytes());
+        for (i, packet) in packets.iter().enumerate() {
+            capture.extend_from_slice(&(i as u32).to_le_bytes());
+            capture.extend_from_slice(&0u32.to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(packet);
+        }
+        capture
+    }
+
+    fn messages() -> (Vec<u8>, Vec<u8>) {
+        let template = common::template(256, &[(8, 4), (7, 2)]);
+        let mut data = Vec::new();
+        data.extend_from_slice(&[10, 0, 0, 1]);
+        data.extend_from_slice(&53u16.to_be_bytes());
+        (
+            common::message(1000, 0, 1, &[common::set(2, &template)]),
+            common::message(1001, 0, 1, &[common::set(256, &data)]),
+        )
+    }
+
+    #[test]
+    fn pcap_udp() {
+        let (template_msg, data_msg) = messages();
+        let exporter = [192, 0, 2, 1];
+        let collector = [192, 0, 2, 100];
+        let packets = [
+            ethernet(&ipv4(
+                17,
+                exporter,
+                collector,
+                &udp(50000, 4739, &template_msg),
+            )),
+            ethernet(&ipv4(17, exporter, collector, &udp(50000, 53, &data_msg))),
+            ethernet(&ipv4(17, exporter, collector, &udp(50000, 4739, &data_msg))),
+        ];
+
+        // little endian, microsecond resolution, ethernet
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        capture.extend_from_slice(&65535u32.to_le_bytes());
+        capture.extend_from_slice(&1u32.to_le_bytes());
+        for (i, packet) in packets.iter().enumerate() {
+            capture.extend_from_slice(&1000u32.to_le_bytes());
+            capture.extend_from_slice(&(i as u32 * 10).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(packet);
+        }
+
+        let datagrams = pcap::Reader::new(Cursor::new(capture))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(datagrams.len() == 2);
+        assert!(datagrams[0].source == "192.0.2.1:50000".parse::<SocketAddr>().unwrap());
+        assert!(datagrams[0].transport == pcap::Transport::Udp);
+        assert!(datagrams[1].timestamp.subsec_micros() == 20);
+
+        let p = parser::Parser::new();
+        let mut sessions = state::Sessions::new();
+        for datagram in &datagrams {
+            let _ = pcap::parse_datagram(&p, &mut sessions, datagram).unwrap();
+        }
+        let msg = pcap::parse_datagram(&p, &mut sessions, &datagrams[1]).unwrap();
+        assert!(msg.get_dataset_records().len() == 1);
+        assert!(sessions.len() == 1);
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        while body.len() & 3 != 0 {
+            body.push(0);
+        }
+        let length = 12 + body.len() as u32;
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_be_bytes());
+        block.extend_from_slice(&length.to_be_bytes());
+        block.extend_from_slice(&body);
+        block.extend_from_slice(&length.to_be_bytes());
+        block
+    }
+
+    fn enhanced_packet(ts: u64, packet: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_be_bytes());
+        body.extend_from_slice(&(ts as u32).to_be_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        body.extend_from_slice(packet);
+        pcapng_block(6, &body)
+    }
+
+    #[test]
+    fn pcapng_tcp_reassembly() {
+        let (template_msg, data_msg) = messages();
+        let stream = [template_msg.clone(), data_msg].concat();
+        let exporter = [192, 0, 2, 1];
+        let collector = [192, 0, 2, 100];
+        let isn = 0xffff_fff0u32;
+        let split = 30;
+
+        // big endian section, raw IP interface with nanosecond resolution
+        let mut capture = pcapng_block(
+            0x0A0D_0D0A,
+            &[&0x1A2B_3C4Du32.to_be_bytes()[..], &[0, 1, 0, 0], &[0xff; 8]].concat(),
+        );
+        capture.extend_from_slice(&pcapng_block(
+            1,
+            &[
+                &101u16.to_be_bytes()[..],
+                &[0, 0, 0, 0, 0xff, 0xff],
+                &[0, 9, 0, 1, 9, 0, 0, 0],
+                &[0, 0, 0, 0],
+            ]
+            .concat(),
+        ));
+        let segments = [
+            tcp(50000, 4739, isn, 0x02, &[]),
+            // the second segment arrives first
+            tcp(
+                50000,
+                4739,
+                isn.wrapping_add(1 + split as u32),
+                0x18,
+                &stream[split..],
+            ),
+            tcp(50000, 4739, isn.wrapping_add(1), 0x18, &stream[..split]),
+            tcp(50000, 4739, isn.wrapping_add(1), 0x18, &stream[..split]),
+            tcp(
+                50000,
+                4739,
+                isn.wrapping_add(1 + stream.len() as u32),
+                0x11,
+                &[],
+            ),
+        ];
+        for (i, segment) in segments.iter().enumerate() {
+            let ts = 1_000_000_000_000 + i as u64;
+            capture.extend_from_slice(&enhanced_packet(ts, &ipv4(6, exporter, collector, segment)));
+        }
+
+        let datagrams = pcap::Reader::new(Cursor::new(capture))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(datagrams.len() == 2);
+        assert!(datagrams[0].transport == pcap::Transport::Tcp);
+        assert!(datagrams[0].payload == template_msg);
+        assert!(datagrams[0].timestamp.as_secs() == 1000);
+        assert!(datagrams[0].timestamp.subsec_nanos() == 2);
+
+        let p = parser::Parser::new();
+        let mut sessions = state::Sessions::new();
+        let _ = pcap::parse_datagram(&p, &mut sessions, &datagrams[0]).unwrap();
+        let msg = pcap::parse_datagram(&p, &mut sessions, &datagrams[1]).unwrap();
+        assert!(msg.get_dataset_records().len() == 1);
+    }
+
+    #[test]
+    fn tcp_pending_bytes() {
+        let (_, data_msg) = messages();
+        let exporter = [192, 0, 2, 1];
+        let collector = [192, 0, 2, 100];
+        let segment = data_msg.repeat(2000);
+        let at = |i: usize| 1 + (i * segment.len()) as u32;
+
+        // every segment but the first arrives early, more than can be held
+        let mut packets = vec![ipv4(
+            6,
+            exporter,
+            collector,
+            &tcp(50000, 4739, 0, 0x02, &[]),
+        )];
+        for i in 1..30 {
+            packets.push(ipv4(
+                6,
+                exporter,
+                collector,
+                &tcp(50000, 4739, at(i), 0x18, &segment),
+            ));
+        }
+        packets.push(ipv4(
+            6,
+            exporter,
+            collector,
+            &tcp(50000, 4739, at(0), 0x18, &segment),
+        ));
+
+        let datagrams = pcap::Reader::new(Cursor::new(raw_capture(&packets)))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(datagrams.len() > 2000 && datagrams.len() < 30 * 2000);
+        assert!(datagrams.iter().all(|d| d.payload == data_msg));
+    }
+
+    #[test]
+    fn tcp_streams_evicted() {
+        let (_, data_msg) = messages();
+        let collector = [192, 0, 2, 100];
+        let split = 13;
+        let first = |seq: u32, flags: u8, payload: &[u8]| {
+            ipv4(
+                6,
+                [192, 0, 2, 1],
+                collector,
+                &tcp(50000, 4739, seq, flags, payload),
+            )
+        };
+        let start = vec![first(0, 0x02, &[]), first(1, 0x18, &data_msg[..split])];
+        let rest = first(1 + split as u32, 0x18, &data_msg[split..]);
+
+        let count = |packets: Vec<Vec<u8>>| {
+            pcap::Reader::new(Cursor::new(raw_capture(&packets)))
+                .unwrap()
+                .count()
+        };
+        assert!(count([start.clone(), vec![rest.clone()]].concat()) == 1);
+
+        // as many streams opened since, the first one was dropped midway
+        let mut packets = start;
+        for port in 0..4096u16 {
+            let syn = tcp(10000 + port, 4739, 0, 0x02, &[]);
+            packets.push(ipv4(6, [192, 0, 2, 2], collector, &syn));
+        }
+        packets.push(rest);
+        assert!(count(packets) == 0);
+    }
+}
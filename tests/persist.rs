@@ -0,0 +1,195 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+    extern crate serde_json;
+
+    use self::rsipfix::{collector, parser, state};
+    use common;
+    use std::env;
+    use std::fs;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn learned() -> state::State {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut pen = common::template(257, &[(0x8000 | 1, 65535)]);
+        pen.extend_from_slice(&29305u32.to_be_bytes());
+        let sets = [
+            common::set(2, &common::template(256, &[(8, 4), (7, 2)])),
+            common::set(2, &pen),
+            common::set(3, &common::options_template(258, 1, &[(149, 4), (41, 8)])),
+        ];
+        p.parse_message(&mut s, &common::message(1000, 0, 7, &sets))
+            .unwrap();
+        s
+    }
+
+    #[test]
+    fn state_roundtrip() {
+        let s = learned();
+        let json = serde_json::to_string(&s).unwrap();
+        let restored: state::State = serde_json::from_str(&json).unwrap();
+        assert!(restored.get_templates() == s.get_templates());
+        assert!(restored.get_options_templates() == s.get_options_templates());
+        assert!(restored.snapshot() == s.snapshot());
+
+        let entries = s.snapshot().templates;
+        assert!(entries.len() == 3);
+        assert!(entries.iter().all(|t| t.observation_domain_id == 7));
+        assert!(entries.iter().all(|t| t.updated > 0));
+
+        // restored templates decode right away
+        let data = common::message(1001, 1, 7, &[common::set(256, &[10, 0, 0, 1, 0, 53])]);
+        let mut restored = restored;
+        let p = parser::Parser::new();
+        let msg = p.parse_message(&mut restored, &data).unwrap();
+        assert!(msg.get_dataset_records().len() == 1);
+    }
+
+    #[test]
+    fn restore_per_entry() {
+        let p = parser::Parser::new();
+        let mut s = state::State::domain_scoped();
+        for domain in &[7, 8] {
+            let sets = [common::set(2, &common::template(256, &[(8, 4)]))];
+            p.parse_message(&mut s, &common::message(1000, 0, *domain, &sets))
+                .unwrap();
+        }
+        let snapshot = s.snapshot();
+        let domains: Vec<u32> = snapshot
+            .templates
+            .iter()
+            .map(|t| t.observation_domain_id)
+            .collect();
+        assert!(domains == [7, 8]);
+
+        // the template over the limits is skipped, the other one restored
+        let mut restored = state::State::domain_scoped();
+        restored.set_limits(state::Limits {
+            max_domains: 1,
+            ..Default::default()
+        });
+        assert!(restored.restore(snapshot) == 1);
+        assert!(restored.get_domain_template(7, &256).is_some());
+        assert!(restored.get_domain_template(8, &256).is_none());
+        // restoring isn't counted as definitions from the exporter
+        assert!(restored.get_stats().domains.is_empty());
+    }
+
+    #[test]
+    fn snapshot_version() {
+        let exporter: SocketAddr = "192.0.2.1:4739".parse().unwrap();
+        let mut buf = Vec::new();
+        state::write_snapshot(&mut buf, vec![(exporter, learned().snapshot())]).unwrap();
+        let sessions = state::read_snapshot::<SocketAddr, _>(&buf[..]).unwrap();
+        assert!(sessions.len() == 1 && sessions[0].0 == exporter);
+
+        let json = String::from_utf8(buf).unwrap();
+        let newer = json.replace("\"version\":1", "\"version\":99");
+        assert!(state::read_snapshot::<SocketAddr, _>(newer.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn collector_restart() {
+        let path = env::temp_dir().join(format!("rsipfix-state-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let config = collector::Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            workers: 2,
+            state_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let exporter = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let c = collector::Collector::bind(config.clone()).unwrap();
+        let handle = c
+            .spawn(Arc::new(parser::Parser::new()), Arc::new(|_, _| {}))
+            .unwrap();
+        let template = common::template(256, &[(8, 4), (7, 2)]);
+        exporter
+            .send_to(
+                &common::message(1000, 0, 7, &[common::set(2, &template)]),
+                handle.local_addr(),
+            )
+            .unwrap();
+        for _ in 0..50 {
+            if handle.counters().messages == 1 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        handle.shutdown().unwrap();
+        assert!(path.exists());
+
+        // the restarted collector decodes data without the template
+        let c = collector::Collector::bind(config).unwrap();
+        let (handle, records) = c
+            .spawn_channel(Arc::new(parser::Parser::new()), 16)
+            .unwrap();
+        exporter
+            .send_to(
+                &common::message(1001, 0, 7, &[common::set(256, &[10, 0, 0, 1, 0, 53])]),
+                handle.local_addr(),
+            )
+            .unwrap();
+        let record = records.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(record.json.contains("\"sourceTransportPort\":53"));
+        handle.shutdown().unwrap();
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn collector_saves_periodically() {
+        let path = env::temp_dir().join(format!("rsipfix-saves-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        // sessions over the limit aren't restored
+        let exporters: Vec<SocketAddr> = vec![
+            "192.0.2.1:4739".parse().unwrap(),
+            "192.0.2.2:4739".parse().unwrap(),
+        ];
+        let saved = exporters
+            .iter()
+            .map(|e| (*e, learned().snapshot()))
+            .collect();
+        state::write_snapshot(fs::File::create(&path).unwrap(), saved).unwrap();
+        let config = collector::Config {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            workers: 1,
+            max_sessions: 1,
+            state_file: Some(path.clone()),
+            save_interval: Duration::from_millis(50),
+            ..Default::default()
+        };
+
+        let c = collector::Collector::bind(config).unwrap();
+        let handle = c
+            .spawn(Arc::new(parser::Parser::new()), Arc::new(|_, _| {}))
+            .unwrap();
+        for _ in 0..50 {
+            if handle.counters().restore_errors > 0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(handle.counters().restore_errors == 3);
+
+        // saved while running, without the session that wasn't restored
+        let mut sessions = Vec::new();
+        for _ in 0..50 {
+            std::thread::sleep(Duration::from_millis(20));
+            sessions = state::read_snapshot::<SocketAddr, _>(fs::File::open(&path).unwrap())
+                .unwrap_or_default();
+            if sessions.len() == 1 {
+                break;
+            }
+        }
+        assert!(sessions.len() == 1);
+        assert!(sessions[0].1.templates.len() == 3);
+        handle.shutdown().unwrap();
+        let _ = fs::remove_file(&path);
+    }
+}
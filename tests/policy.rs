@@ -0,0 +1,85 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::policy::{AddrRange, Policy};
+    use self::rsipfix::{parser, state};
+    use common;
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn addr_range() {
+        let r: AddrRange = "10.1.0.0/16".parse().unwrap();
+        assert!(r.contains(ip("10.1.200.3")));
+        assert!(!r.contains(ip("10.2.0.1")));
+        assert!(r.contains(ip("::ffff:10.1.0.1")));
+        assert!(r.to_string() == "10.1.0.0/16");
+
+        let r: AddrRange = "2001:db8::/33".parse().unwrap();
+        assert!(r.contains(ip("2001:db8:7fff::1")));
+        assert!(!r.contains(ip("2001:db8:8000::1")));
+        assert!(!r.contains(ip("10.1.0.1")));
+
+        let r: AddrRange = "192.0.2.1".parse().unwrap();
+        assert!(r.contains(ip("192.0.2.1")));
+        assert!(!r.contains(ip("192.0.2.2")));
+
+        assert!("10.0.0.0/33".parse::<AddrRange>().is_err());
+        assert!("10.0.0/8".parse::<AddrRange>().is_err());
+    }
+
+    #[test]
+    fn admit() {
+        let msg = common::message(1000, 0, 7, &[]);
+        assert!(Policy::new().admit(ip("192.0.2.1"), &msg).is_ok());
+
+        let mut p = Policy::new();
+        p.allow_exporter("10.0.0.0/8".parse().unwrap());
+        p.allow_domain(7);
+        assert!(p.admit(ip("10.0.0.1"), &msg).is_ok());
+        assert!(p.admit(ip("192.0.2.1"), &msg).is_err());
+        assert!(p
+            .admit(ip("10.0.0.1"), &common::message(1000, 0, 8, &[]))
+            .is_err());
+        assert!(p.admit(ip("10.0.0.1"), &msg[..8]).is_err());
+    }
+
+    #[test]
+    fn pinned_template() {
+        let parser = parser::Parser::new();
+        let template = common::template(256, &[(8, 4), (7, 2)]);
+        let mut learned = state::State::new();
+        parser
+            .parse_message(
+                &mut learned,
+                &common::message(1000, 0, 1, &[common::set(2, &template)]),
+            )
+            .unwrap();
+
+        let mut p = Policy::new();
+        p.pin_template(learned.get_template(&256).cloned().unwrap());
+        let mut s = p.new_state();
+        assert!(s.is_pinned(256));
+
+        // the same definition is accepted, a conflicting one refused
+        let poison = common::template(256, &[(12, 4)]);
+        let sets = [common::set(2, &template), common::set(2, &poison)];
+        parser
+            .parse_message(&mut s, &common::message(1001, 1, 1, &sets))
+            .unwrap();
+        assert!(s.refused_templates() == 1);
+        assert!(s.get_template(&256) == learned.get_template(&256));
+        // in every observation domain
+        assert!(s.get_domain_template(9, &256) == learned.get_template(&256));
+
+        // pinned templates outlive the session
+        s.clear();
+        assert!(s.templates_len() == 1);
+    }
+}
@@ -0,0 +1,253 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+    extern crate serde_json;
+
+    use self::rsipfix::{jsonl, parser, schema, state};
+    use self::serde_json::Value;
+    use common;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn state(p: &parser::Parser) -> state::State {
+        let mut s = state::State::new();
+        let sets = [
+            common::set(
+                2,
+                &common::template(
+                    256,
+                    &[
+                        (1, 4),
+                        (8, 4),
+                        (56, 6),
+                        (152, 8),
+                        (82, 65535),
+                        (999, 3),
+                        (27, 16),
+                    ],
+                ),
+            ),
+            common::set(3, &common::options_template(300, 1, &[(149, 4), (999, 2)])),
+        ];
+        p.parse_message(&mut s, &common::message(1000, 0, 1, &sets))
+            .unwrap();
+        s
+    }
+
+    // the subset of JSON Schema the generated schemas use
+    fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+        if let Some(any) = schema["anyOf"].as_array() {
+            if any.iter().any(|s| validate(s, value).is_ok()) {
+                return Ok(());
+            }
+            return Err(format!("{} matches none of {}", value, schema));
+        }
+        let types = match schema["type"] {
+            Value::Array(ref types) => types.clone(),
+            ref t => vec![t.clone()],
+        };
+        let typed = types.iter().any(|t| match t.as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("integer") => value.is_u64() || value.is_i64(),
+            Some("string") => value.is_string(),
+            Some("null") => value.is_null(),
+            _ => false,
+        });
+        if !typed {
+            return Err(format!("{} isn't {}", value, schema["type"]));
+        }
+        if let Some(n) = value.as_u64() {
+            if schema["minimum"].as_u64().is_some_and(|min| n < min)
+                || schema["maximum"].as_u64().is_some_and(|max| n > max)
+            {
+                return Err(format!("{} out of range", value));
+            }
+        }
+        if let Some(s) = value.as_str() {
+            let valid = match (
+                schema["format"].as_str(),
+                schema["contentEncoding"].as_str(),
+            ) {
+                (Some("ipv4"), _) => s.parse::<Ipv4Addr>().is_ok(),
+                (Some("ipv6"), _) => s.parse::<Ipv6Addr>().is_ok(),
+                (_, Some("base16")) => {
+                    s.len() / 2 * 2 == s.len() && s.chars().all(|c| c.is_ascii_hexdigit())
+                }
+                (_, Some("base64")) => {
+                    s.len() / 4 * 4 == s.len()
+                        && s.chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c))
+                }
+                _ => true,
+            };
+            if !valid {
+                return Err(format!("{} doesn't match {}", value, schema));
+            }
+        }
+        if let Some(items) = value.as_array() {
+            if schema["minItems"]
+                .as_u64()
+                .is_some_and(|n| (items.len() as u64) < n)
+                || schema["maxItems"]
+                    .as_u64()
+                    .is_some_and(|n| (items.len() as u64) > n)
+            {
+                return Err(format!("{} has the wrong length", value));
+            }
+            for item in items {
+                validate(&schema["items"], item)?;
+            }
+        }
+        if let Some(object) = value.as_object() {
+            for required in schema["required"].as_array().into_iter().flatten() {
+                if !object.contains_key(required.as_str().unwrap_or_default()) {
+                    return Err(format!("{} is missing", required));
+                }
+            }
+            for (key, v) in object {
+                match schema["properties"].get(key) {
+                    Some(property) => validate(property, v)?,
+                    None if schema["additionalProperties"] == false => {
+                        return Err(format!("{} isn't allowed", key))
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn json_schema() {
+        let p = parser::Parser::new();
+        let schemas = schema::generate(&p, &state(&p));
+        assert!(schemas.len() == 2);
+        assert!(schemas[0].template_id == 256 && !schemas[0].is_options);
+        assert!(schemas[1].template_id == 300 && schemas[1].is_options);
+
+        let s = &schemas[0].json_schema;
+        assert!(s["title"] == "template 256");
+        assert!(s["additionalProperties"] == false);
+        let properties = &s["properties"];
+        assert!(properties["octetDeltaCount"]["type"] == "integer");
+        assert!(properties["octetDeltaCount"]["maximum"] == u64::from(u32::MAX));
+        assert!(properties["sourceIPv4Address"]["format"] == "ipv4");
+        assert!(properties["sourceIPv6Address"]["format"] == "ipv6");
+        // written as they are held: 6 bytes as hex, milliseconds as integers
+        assert!(properties["sourceMacAddress"]["contentEncoding"] == "base16");
+        assert!(properties["flowStartMilliSeconds"]["type"] == "integer");
+        assert!(properties["interfaceName"]["type"] == "string");
+        assert!(properties["999"]["contentEncoding"] == "base16");
+        assert!(schemas[1].json_schema["properties"]["999"]["type"] == "string");
+    }
+
+    #[test]
+    fn json_schema_validates_records() {
+        let p = parser::Parser::new();
+        let mut s = state(&p);
+        let mut record = vec![0, 0, 0x05, 0xdc, 10, 0, 0, 1];
+        record.extend_from_slice(&[0x00, 0x1b, 0x21, 0x3c, 0x4d, 0x5e]);
+        record.extend_from_slice(&1_600_000_000_000u64.to_be_bytes());
+        record.extend_from_slice(&common::varlen(b"eth0"));
+        record.extend_from_slice(&[1, 2, 3]);
+        record.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        let msg = common::message(1000, 0, 1, &[common::set(256, &record)]);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        let fields = &s.get_templates()[&256].field_specifiers;
+
+        let options = [
+            jsonl::Options::default(),
+            jsonl::Options {
+                bytes: jsonl::ByteEncoding::Base64,
+                naming: jsonl::Naming::SnakeCase,
+                metadata: true,
+            },
+        ];
+        for options in &options {
+            let mut w = jsonl::Writer::new(Vec::new(), *options);
+            let exporter = "192.0.2.1:4739".parse().ok();
+            w.write_message(exporter, &parsed).unwrap();
+            let line: Value = serde_json::from_slice(&w.into_inner()).unwrap();
+            let schema = schema::json_schema_with(&p, 256, fields, options);
+            assert!(validate(&schema, &line) == Ok(()));
+        }
+
+        // and a record that isn't written that way is rejected
+        let schema = schema::json_schema(&p, 256, fields);
+        let mut line: Value = serde_json::from_str(
+            r#"{"999":"010203","flowStartMilliSeconds":1,"interfaceName":"eth0","octetDeltaCount":1500,"sourceIPv4Address":"10.0.0.1","sourceIPv6Address":"::1","sourceMacAddress":"001b213c4d5e"}"#,
+        )
+        .unwrap();
+        assert!(validate(&schema, &line) == Ok(()));
+        line["sourceMacAddress"] = "00:1b:21:3c:4d:5e".into();
+        assert!(validate(&schema, &line).is_err());
+        line["sourceMacAddress"] = "001b213c4d5e".into();
+        line["field_999"] = "010203".into();
+        assert!(validate(&schema, &line).is_err());
+    }
+
+    #[test]
+    fn json_schema_keys_fields_by_enterprise() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        // sourceIPv4Address twice, then the same id of enterprise 12345
+        let mut template = common::template(256, &[(8, 4), (8, 4)]);
+        template[3] = 3;
+        template.extend_from_slice(&(0x8000u16 | 8).to_be_bytes());
+        template.extend_from_slice(&4u16.to_be_bytes());
+        template.extend_from_slice(&12345u32.to_be_bytes());
+        let record = [10, 0, 0, 1, 10, 0, 0, 2, 1, 2, 3, 4];
+        let sets = [common::set(2, &template), common::set(256, &record)];
+        let msg = common::message(1000, 0, 1, &sets);
+        let parsed = p.parse_message(&mut s, &msg).unwrap();
+        let values = &parsed.sets[1].data[0].records[0].values;
+        assert!(values.len() == 2);
+
+        let fields = &s.get_templates()[&256].field_specifiers;
+        let schema = schema::json_schema(&p, 256, fields);
+        assert!(schema["properties"].as_object().unwrap().len() == 2);
+        assert!(schema["required"].as_array().unwrap().len() == 2);
+        let mut w = jsonl::Writer::new(Vec::new(), jsonl::Options::default());
+        w.write_message(None, &parsed).unwrap();
+        let line: Value = serde_json::from_slice(&w.into_inner()).unwrap();
+        assert!(line["sourceIPv4Address"] == "10.0.0.2");
+        assert!(validate(&schema, &line) == Ok(()));
+    }
+
+    #[test]
+    fn avro_schema() {
+        let p = parser::Parser::new();
+        let schemas = schema::generate(&p, &state(&p));
+        let expected = r#"{"fields":[{"doc":"ie 149","name":"sourceId","type":"long"},{"doc":"ie 999","name":"field_999","type":"bytes"}],"name":"template_300","namespace":"rsipfix","type":"record"}"#;
+        assert!(serde_json::to_string(&schemas[1].avro).unwrap() == expected);
+
+        let fields = &schemas[0].avro["fields"];
+        assert!(fields[3]["name"] == "flowStartMilliSeconds");
+        assert!(fields[3]["type"]["logicalType"] == "timestamp-millis");
+        assert!(fields[4]["type"] == "string");
+
+        // unsigned64 doesn't fit a long, the fixed is defined once
+        let mut s = state::State::new();
+        let sets = [common::set(2, &common::template(400, &[(1, 8), (2, 8)]))];
+        p.parse_message(&mut s, &common::message(1000, 0, 1, &sets))
+            .unwrap();
+        let fields = &s.get_templates()[&400].field_specifiers;
+        let avro = schema::avro_schema(&p, 400, fields);
+        let uint64 = &avro["fields"][0]["type"];
+        assert!(uint64["type"] == "fixed" && uint64["name"] == "uint64");
+        assert!(uint64["logicalType"] == "decimal" && uint64["precision"] == 20);
+        assert!(avro["fields"][1]["type"] == "uint64");
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn arrow_schema() {
+        let p = parser::Parser::new();
+        let s = state(&p);
+        let schemas = schema::generate(&p, &s);
+        let mut decoder = rsipfix::columnar::Decoder::new(&p);
+        assert!(*decoder.schema(&s, 1, 256).unwrap() == schemas[0].arrow);
+    }
+}
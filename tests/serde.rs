@@ -0,0 +1,96 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+    extern crate serde_json;
+
+    use self::rsipfix::{ie, owned, parser, state};
+    use common;
+
+    #[test]
+    fn message_json() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let sets = [
+            common::set(2, &common::template(256, &[(8, 4)])),
+            common::set(256, &[10, 0, 0, 1]),
+        ];
+        let buf = common::message(1000, 3, 1, &sets);
+        let msg = p.parse_message(&mut s, &buf).unwrap();
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(
+            json == concat!(
+                r#"{"version":10,"length":36,"export_time":1000,"sequence_number":3,"#,
+                r#""observation_domain_id":1,"sets":["#,
+                r#"{"hdr":{"set_id":2,"length":12},"stype":"Template","templates":[{"#,
+                r#""header":{"template_id":256,"field_count":1},"#,
+                r#""field_specifiers":[{"ident":8,"field_length":4,"enterprise_number":null,"#,
+                r#""is_variable":false,"is_pen":false}]"#,
+                r#"}],"data":[]},"#,
+                r#"{"hdr":{"set_id":256,"length":8},"stype":"DataSet","data":[{"#,
+                r#""header":{"set_id":256,"length":4},"#,
+                r#""records":[{"sourceIPv4Address":"10.0.0.1"}],"is_options":false}]}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn template_roundtrip() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let mut body = common::template(256, &[(82, 65535)]);
+        let mut pen = common::template(257, &[(0x8000 | 1, 4)]);
+        pen.extend_from_slice(&29305u32.to_be_bytes());
+        body.extend_from_slice(&pen);
+        let buf = common::message(1000, 0, 1, &[common::set(2, &body)]);
+        p.parse_message(&mut s, &buf).unwrap();
+
+        let variable = s.get_template(&256).unwrap();
+        assert!(variable.field_specifiers[0].is_variable());
+        assert!(!variable.field_specifiers[0].is_pen());
+        let enterprise = s.get_template(&257).unwrap();
+        assert!(enterprise.field_specifiers[0].is_pen());
+        assert!(!enterprise.field_specifiers[0].is_variable());
+
+        let json = serde_json::to_string(enterprise).unwrap();
+        assert!(json.contains(r#""ident":1,"field_length":4,"enterprise_number":29305"#));
+        let t: parser::Template = serde_json::from_str(&json).unwrap();
+        assert!(&t == enterprise);
+        let t: parser::Template =
+            serde_json::from_str(&serde_json::to_string(variable).unwrap()).unwrap();
+        assert!(&t == variable);
+    }
+
+    #[test]
+    fn owned_roundtrip() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        // sourceIPv4Address, octetDeltaCount, interfaceName, sourceMacAddress
+        // and a field without a formatter
+        let fields = [(8, 4), (1, 4), (82, 65535), (56, 6), (999, 2)];
+        let mut record = vec![10, 0, 0, 1, 0, 0, 0x05, 0xdc];
+        record.extend_from_slice(&common::varlen(b"eth0"));
+        record.extend_from_slice(&[0, 0x1b, 0x21, 0x3c, 0x4d, 0x5e, 0xab, 0xcd]);
+        let sets = [
+            common::set(2, &common::template(256, &fields)),
+            common::set(3, &common::options_template(300, 1, &[(149, 4)])),
+            common::set(256, &record),
+        ];
+        let buf = common::message(1000, 3, 1, &sets);
+        let msg = owned::Message::from(&p.parse_message(&mut s, &buf).unwrap());
+
+        assert!(msg.sets[0].templates[0] == *s.get_template(&256).unwrap());
+        assert!(msg.sets[1].options_templates[0] == *s.get_options_template(&300).unwrap());
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""octetDeltaCount":{"U32":1500}"#));
+        assert!(json.contains(r#""999":{"Bytes":[171,205]}"#));
+        let back: owned::Message = serde_json::from_str(&json).unwrap();
+        assert!(back == msg);
+
+        // and reads like a parsed record
+        let record = back.sets[2].data[0].records[0].as_record();
+        assert!(record.get_u64(ie::OCTET_DELTA_COUNT) == Ok(1500));
+        assert!(record.get_str(ie::INTERFACE_NAME) == Ok("eth0"));
+    }
+}
@@ -0,0 +1,116 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{parser, state, stats};
+    use common;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn domain_stats() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let template = common::set(2, &common::template(256, &[(8, 4)]));
+        let records = || common::set(256, &[10, 0, 0, 1, 10, 0, 0, 2]);
+
+        // data before its template, then the template twice
+        let early = common::message(1000, 0, 1, &[records()]);
+        p.parse_message(&mut s, &early).unwrap();
+        let msg = common::message(1001, 0, 1, &[template.clone(), records()]);
+        p.parse_message(&mut s, &msg).unwrap();
+        let msg = common::message(1002, 2, 1, &[template, records()]);
+        p.parse_message(&mut s, &msg).unwrap();
+        // two records went missing
+        let msg = common::message(1003, 6, 1, &[records()]);
+        p.parse_message(&mut s, &msg).unwrap();
+        assert!(p.parse_message(&mut s, &[0, 10, 0, 16]).is_err());
+
+        let stats = s.get_stats();
+        let d = &stats.domains[&1];
+        assert!(d.messages == 4 && stats.messages() == 4);
+        assert!(d.bytes == 28 + 40 + 40 + 28);
+        assert!(d.template_sets == 2);
+        assert!(d.data_sets == 4);
+        assert!(d.records[&256] == 6);
+        assert!(d.missing_templates == 1);
+        assert!(d.template_definitions == 1);
+        assert!(d.template_redefinitions == 1);
+        assert!(d.sequence_gaps == 1);
+        assert!(d.lost_records == 2);
+        assert!(d.expected_sequence == Some(8));
+        assert!(stats.parse_errors.headers == 1);
+        assert!(stats.parse_errors.sets == 0);
+        assert!(stats.templates == 1);
+    }
+
+    #[test]
+    fn malformed_sets() {
+        let p = parser::Parser::new();
+        let mut s = state::State::new();
+        let template = common::set(2, &common::template(256, &[(8, 4)]));
+        p.parse_message(&mut s, &common::message(1000, 0, 1, &[template]))
+            .unwrap();
+
+        // padding is fine, a record cut short or no record at all isn't
+        let sets = vec![
+            common::set(256, &[10, 0, 0, 1, 0, 0]),
+            common::set(256, &[10, 0, 0, 1, 10, 0]),
+            common::set(256, &[10, 0]),
+        ];
+        for (i, set) in sets.into_iter().enumerate() {
+            let msg = common::message(1001, i as u32, 1, &[set]);
+            p.parse_message(&mut s, &msg).unwrap();
+        }
+        let stats = s.get_stats();
+        assert!(stats.parse_errors.sets == 2);
+        assert!(stats.domains[&1].records[&256] == 2);
+    }
+
+    #[test]
+    fn domains_over_the_limit() {
+        let p = parser::Parser::new();
+        let mut s = state::State::with_limits(state::Limits {
+            max_domains: 1,
+            ..Default::default()
+        });
+        for domain in &[1, 2, 1] {
+            p.parse_message(&mut s, &common::message(1000, 0, *domain, &[]))
+                .unwrap();
+        }
+        let stats = s.get_stats();
+        assert!(stats.domains.len() == 1 && stats.domains[&1].messages == 2);
+        assert!(stats.untracked_messages == 1);
+    }
+
+    #[test]
+    fn per_thread() {
+        let p = parser::Parser::new();
+        let s = Arc::new(RwLock::new(state::State::new()));
+        let template = common::set(2, &common::template(256, &[(8, 4)]));
+        let records = || common::set(256, &[10, 0, 0, 1, 10, 0, 0, 2]);
+
+        // every thread counts the messages it parsed
+        let mut threads = vec![stats::Stats::default(), stats::Stats::default()];
+        let messages = [
+            common::message(1000, 0, 1, &[template]),
+            common::message(1001, 0, 1, &[records()]),
+            common::message(1002, 2, 1, &[records()]),
+        ];
+        for (i, msg) in messages.iter().enumerate() {
+            let result = p.parse_message_async(s.clone(), msg);
+            threads[i % 2].count(&s.read().unwrap(), msg.len(), &result);
+        }
+        assert!(s.read().unwrap().get_stats().messages() == 0);
+
+        let mut total = stats::Stats::default();
+        for t in &threads {
+            total.merge(t);
+        }
+        let d = &total.domains[&1];
+        assert!(d.messages == 3 && d.template_sets == 1 && d.data_sets == 2);
+        assert!(d.records[&256] == 4);
+        assert!(total.templates == 1);
+    }
+}
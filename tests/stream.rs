@@ -0,0 +1,135 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "codec")]
+    extern crate bytes;
+    extern crate rsipfix;
+    #[cfg(feature = "codec")]
+    extern crate tokio_util;
+
+    use self::rsipfix::{parser, state, stream};
+    use common;
+    use std::io::{self, Read};
+
+    // hands out the stream a few bytes at a time like a slow socket would
+    struct Trickle {
+        buf: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3).min(self.buf.len() - self.pos);
+            buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    fn messages() -> Vec<u8> {
+        let template = common::template(256, &[(8, 4), (7, 2)]);
+        let mut data = Vec::new();
+        data.extend_from_slice(&[10, 0, 0, 1]);
+        data.extend_from_slice(&53u16.to_be_bytes());
+        data.extend_from_slice(&[10, 0, 0, 2]);
+        data.extend_from_slice(&53u16.to_be_bytes());
+        [
+            common::message(1000, 0, 1, &[common::set(2, &template)]),
+            common::message(1001, 0, 1, &[common::set(256, &data)]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn message_len() {
+        let buf = messages();
+        assert!(stream::message_len(&buf[..3]).unwrap().is_none());
+        assert!(stream::message_len(&buf).unwrap() == Some(32));
+        assert!(stream::message_len(&[0, 9, 0, 16]).is_err());
+        assert!(stream::message_len(&[0, 10, 0, 15]).is_err());
+    }
+
+    #[test]
+    fn read_stream() {
+        let p = parser::Parser::new();
+        let mut r = stream::Reader::new(Trickle {
+            buf: messages(),
+            pos: 0,
+        });
+
+        assert!(r.read_message(&p).unwrap().is_some());
+        assert!(r.get_state().templates_len() == 1);
+        let msg = r.read_message(&p).unwrap().unwrap();
+        assert!(msg.get_dataset_records().len() == 2);
+
+        // templates don't outlive the connection
+        assert!(r.read_message(&p).unwrap().is_none());
+        assert!(r.is_closed());
+        assert!(r.get_state().is_empty());
+    }
+
+    #[test]
+    fn read_stream_with_state() {
+        let p = parser::Parser::new();
+        let s = state::State::with_limits(state::Limits {
+            max_templates_per_domain: 0,
+            ..Default::default()
+        });
+        let mut r = stream::Reader::with_state(
+            Trickle {
+                buf: messages(),
+                pos: 0,
+            },
+            s,
+        );
+        assert!(r.read_message(&p).is_err());
+        assert!(r.get_state().templates_len() == 0);
+    }
+
+    #[cfg(feature = "codec")]
+    #[test]
+    fn decode_stream() {
+        use self::bytes::BytesMut;
+        use self::tokio_util::codec::Decoder;
+
+        let buf = messages();
+        let mut decoder = stream::MessageDecoder;
+        let mut src = BytesMut::new();
+        let mut decoded = Vec::new();
+        for chunk in buf.chunks(5) {
+            src.extend_from_slice(chunk);
+            while let Some(msg) = decoder.decode(&mut src).unwrap() {
+                decoded.push(msg);
+            }
+        }
+        assert!(decoded.len() == 2);
+        assert!(decoded[0].len() == 32);
+        assert!(src.is_empty());
+
+        let mut bad = BytesMut::from(&[0u8, 9, 0, 16][..]);
+        assert!(decoder.decode(&mut bad).is_err());
+    }
+
+    #[cfg(feature = "codec")]
+    #[test]
+    fn decode_session() {
+        use self::bytes::BytesMut;
+        use self::tokio_util::codec::Decoder;
+        use std::sync::Arc;
+
+        let buf = messages();
+        let mut decoder = stream::SessionDecoder::new(Arc::new(parser::Parser::new()));
+        let mut src = BytesMut::from(&buf[..]);
+        assert!(decoder.decode(&mut src).unwrap().is_some());
+        assert!(decoder.get_state().templates_len() == 1);
+        let msg = decoder.decode(&mut src).unwrap().unwrap();
+        assert!(msg.sets[0].data[0].records.len() == 2);
+
+        // templates don't outlive the connection, nor does a partial message
+        src.extend_from_slice(&buf[..20]);
+        assert!(decoder.decode_eof(&mut src).unwrap().is_none());
+        assert!(decoder.get_state().is_empty());
+        assert!(src.is_empty());
+    }
+}
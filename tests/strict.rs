@@ -0,0 +1,132 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate anyhow;
+    extern crate rsipfix;
+
+    use self::anyhow::Result;
+    use self::rsipfix::parser::{self, Mode, Violation};
+    use self::rsipfix::state;
+    use common;
+
+    fn template_set() -> Vec<u8> {
+        common::set(2, &common::template(256, &[(8, 4), (82, 65535)]))
+    }
+
+    fn violation(r: Result<parser::Message>) -> Option<Violation> {
+        r.err().and_then(|e| e.downcast_ref::<Violation>().cloned())
+    }
+
+    // parse `sets` after the template in both modes, lenient has to take it
+    fn strict(sets: &[Vec<u8>]) -> Option<Violation> {
+        let mut all = vec![template_set()];
+        all.extend_from_slice(sets);
+        let msg = common::message(1000, 0, 1, &all);
+
+        let lenient = parser::Parser::new();
+        assert!(lenient
+            .parse_message(&mut state::State::new(), &msg)
+            .is_ok());
+        let p = parser::Parser::with_mode(Mode::Strict);
+        violation(p.parse_message(&mut state::State::new(), &msg))
+    }
+
+    #[test]
+    fn conforming() {
+        let mut record = vec![10, 0, 0, 1];
+        record.extend_from_slice(&common::varlen(b"eth0"));
+        record.extend_from_slice(&[0, 0]);
+        assert!(strict(&[common::set(256, &record)]).is_none());
+
+        // long form variable length
+        let mut record = vec![10, 0, 0, 1, 255];
+        record.extend_from_slice(&300u16.to_be_bytes());
+        record.extend_from_slice(&[b'a'; 300]);
+        let p = parser::Parser::with_mode(Mode::Strict);
+        let mut s = state::State::new();
+        let msg = common::message(1000, 0, 1, &[template_set(), common::set(256, &record)]);
+        let msg = p.parse_message(&mut s, &msg).unwrap();
+        let records = msg.get_dataset_records();
+        assert!(
+            records[0]
+                .get("interfaceName")
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .len()
+                == 300
+        );
+    }
+
+    #[test]
+    fn violations() {
+        assert!(strict(&[common::set(4, &[])]) == Some(Violation::ReservedSetId { set_id: 4 }));
+        assert!(
+            strict(&[common::set(2, &common::template(255, &[(8, 4)]))])
+                == Some(Violation::TemplateId {
+                    set_id: 2,
+                    template_id: 255
+                })
+        );
+        assert!(
+            strict(&[common::set(2, &common::template(257, &[(8, 0)]))])
+                == Some(Violation::ZeroLengthField {
+                    template_id: 257,
+                    ident: 8
+                })
+        );
+        assert!(
+            strict(&[common::set(
+                3,
+                &common::options_template(258, 0, &[(149, 4)])
+            )]) == Some(Violation::ScopeFieldCount {
+                template_id: 258,
+                scope_field_count: 0,
+                field_count: 1
+            })
+        );
+
+        let mut padded = common::template(257, &[(8, 4)]);
+        padded.extend_from_slice(&[0, 1]);
+        assert!(
+            strict(&[common::set(2, &padded)])
+                == Some(Violation::Padding {
+                    set_id: 2,
+                    length: 2
+                })
+        );
+        assert!(
+            strict(&[common::set(256, &[10, 0, 0, 1, 5, b'e'])])
+                == Some(Violation::TruncatedRecord {
+                    set_id: 256,
+                    length: 6
+                })
+        );
+
+        let mut pen = common::template(259, &[(0x8000 | 1, 4)]);
+        pen.extend_from_slice(&99999u32.to_be_bytes());
+        assert!(
+            strict(&[common::set(2, &pen), common::set(259, &[0, 0, 0, 1])])
+                == Some(Violation::UnknownPen {
+                    set_id: 259,
+                    pen: 99999,
+                    ident: 1
+                })
+        );
+    }
+
+    #[test]
+    fn message_length() {
+        let p = parser::Parser::with_mode(Mode::Strict);
+        let mut msg = common::message(1000, 0, 1, &[template_set()]);
+        msg.extend_from_slice(&[0, 0]);
+        assert!(
+            violation(p.parse_message(&mut state::State::new(), &msg))
+                == Some(Violation::MessageLength {
+                    header: 32,
+                    actual: 34
+                })
+        );
+    }
+}
@@ -0,0 +1,83 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{parser, state, timestamp};
+    use common;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn get_u64(record: &parser::DataRecord, key: &str) -> Option<u64> {
+        record.get(key).and_then(|v| v.as_u64())
+    }
+
+    #[test]
+    fn ntp_conversion() {
+        // 2016-11-22T18:56:00.500Z
+        let ntp = ((1479840960u64 + 2_208_988_800) << 32) | 0x8000_0000;
+        assert!(timestamp::ntp_to_unix_millis(ntp) == Some(1479840960500));
+        assert!(timestamp::ntp_to_unix_millis(0).is_none());
+    }
+
+    #[test]
+    fn normalize_sysuptime_and_delta() {
+        let exporter = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let init_time = 1479840000000u64;
+        let export_time = 1479840960u32;
+
+        // exportingProcessId (scope), systemInitTimeMilliSeconds
+        let options_template = common::options_template(300, 1, &[(144, 4), (160, 8)]);
+        let mut options_data = Vec::new();
+        options_data.extend_from_slice(&1u32.to_be_bytes());
+        options_data.extend_from_slice(&init_time.to_be_bytes());
+
+        // flowStartSysUpTime, flowEndSysUpTime
+        let uptime_template = common::template(256, &[(22, 4), (21, 4)]);
+        let mut uptime_data = Vec::new();
+        uptime_data.extend_from_slice(&1000u32.to_be_bytes());
+        uptime_data.extend_from_slice(&5000u32.to_be_bytes());
+
+        // flowStartDeltaMicroSeconds, flowEndDeltaMicroSeconds
+        let delta_template = common::template(257, &[(158, 4), (159, 4)]);
+        let mut delta_data = Vec::new();
+        delta_data.extend_from_slice(&2_000_000u32.to_be_bytes());
+        delta_data.extend_from_slice(&500_000u32.to_be_bytes());
+
+        let bytes = common::message(
+            export_time,
+            0,
+            1,
+            &[
+                common::set(3, &options_template),
+                common::set(300, &options_data),
+                common::set(2, &[uptime_template, delta_template].concat()),
+                common::set(256, &uptime_data),
+                common::set(257, &delta_data),
+            ],
+        );
+
+        let mut s = state::State::new();
+        let p = parser::Parser::new();
+        let mut n = timestamp::Normalizer::new();
+        let mut msg = p.parse_message(&mut s, &bytes).unwrap();
+        n.process(exporter, &mut msg);
+        assert!(n.get_system_init_time(&exporter) == Some(init_time));
+
+        let records = msg.get_dataset_records();
+        let uptime = records
+            .iter()
+            .find(|r| r.values.len() == 4 && get_u64(r, "flowStartSysUpTime").is_some())
+            .unwrap();
+        assert!(get_u64(uptime, "flowStartMilliSeconds") == Some(init_time + 1000));
+        assert!(get_u64(uptime, "flowEndMilliSeconds") == Some(init_time + 5000));
+
+        let delta = records
+            .iter()
+            .find(|r| get_u64(r, "flowStartDeltaMicroSeconds").is_some())
+            .unwrap();
+        let export_ms = u64::from(export_time) * 1000;
+        assert!(get_u64(delta, "flowStartMilliSeconds") == Some(export_ms - 2000));
+        assert!(get_u64(delta, "flowEndMilliSeconds") == Some(export_ms - 500));
+    }
+}
@@ -0,0 +1,157 @@
+mod common;
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    extern crate rsipfix;
+    extern crate rustls;
+
+    use self::rsipfix::{parser, state, tls};
+    use self::rustls::pki_types::ServerName;
+    use self::rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+    use common;
+    use std::convert::TryFrom;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn acceptor() -> tls::Acceptor {
+        tls::Acceptor::from_pem(
+            include_bytes!("./tls/server.pem"),
+            include_bytes!("./tls/server.key"),
+            include_bytes!("./tls/ca.pem"),
+        )
+        .unwrap()
+    }
+
+    // connect as an exporter and send `msg` over TLS
+    fn export(addr: std::net::SocketAddr, cert: &[u8], key: &[u8], msg: Vec<u8>) {
+        let mut roots = RootCertStore::empty();
+        for ca in tls::load_certs(include_bytes!("./tls/ca.pem")).unwrap() {
+            roots.add(ca).unwrap();
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(
+                tls::load_certs(cert).unwrap(),
+                tls::load_private_key(key).unwrap(),
+            )
+            .unwrap();
+        let name = ServerName::try_from("collector.test").unwrap();
+        let conn = ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut stream = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+        // the server may refuse the certificate, errors are checked on its side
+        if stream.write_all(&msg).is_ok() {
+            stream.conn.send_close_notify();
+            let _ = stream.flush();
+        }
+    }
+
+    fn messages() -> Vec<u8> {
+        let template = common::template(256, &[(8, 4)]);
+        [
+            common::message(1000, 0, 1, &[common::set(2, &template)]),
+            common::message(1001, 0, 1, &[common::set(256, &[10, 0, 0, 1])]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn authenticated_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exporter = thread::spawn(move || {
+            export(
+                addr,
+                include_bytes!("./tls/exporter.pem"),
+                include_bytes!("./tls/exporter.key"),
+                messages(),
+            )
+        });
+
+        let (sock, _) = listener.accept().unwrap();
+        let mut conn = acceptor().accept(sock).unwrap();
+        let cert = &tls::load_certs(include_bytes!("./tls/exporter.pem")).unwrap()[0];
+        assert!(conn.session_key().peer_identity == Some(tls::peer_identity(cert)));
+
+        let p = parser::Parser::new();
+        assert!(conn.read_message(&p).unwrap().is_some());
+        {
+            let msg = conn.read_message(&p).unwrap().unwrap();
+            assert!(msg.get_dataset_records().len() == 1);
+        }
+        assert!(conn.read_message(&p).unwrap().is_none());
+        assert!(conn.is_closed());
+        exporter.join().unwrap();
+    }
+
+    #[test]
+    fn distinct_identities() {
+        let a = &tls::load_certs(include_bytes!("./tls/exporter.pem")).unwrap()[0];
+        let b = &tls::load_certs(include_bytes!("./tls/exporter2.pem")).unwrap()[0];
+        assert!(tls::peer_identity(a).starts_with("sha256:"));
+        assert!(tls::peer_identity(a) != tls::peer_identity(b));
+    }
+
+    #[test]
+    fn untrusted_exporter() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exporter = thread::spawn(move || {
+            export(
+                addr,
+                include_bytes!("./tls/rogue.pem"),
+                include_bytes!("./tls/rogue.key"),
+                messages(),
+            )
+        });
+
+        let (sock, _) = listener.accept().unwrap();
+        assert!(acceptor().accept(sock).is_err());
+        exporter.join().unwrap();
+    }
+
+    #[test]
+    fn handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // connects and never says hello
+        let _idle = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (sock, _) = listener.accept().unwrap();
+
+        let mut acceptor = acceptor();
+        acceptor.set_handshake_timeout(Some(Duration::from_millis(100)));
+        let start = Instant::now();
+        assert!(acceptor.accept(sock).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn session_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exporter = thread::spawn(move || {
+            export(
+                addr,
+                include_bytes!("./tls/exporter.pem"),
+                include_bytes!("./tls/exporter.key"),
+                messages(),
+            )
+        });
+
+        // the limits of the state given apply to the session
+        let (sock, _) = listener.accept().unwrap();
+        let s = state::State::with_limits(state::Limits {
+            max_templates_per_domain: 0,
+            ..Default::default()
+        });
+        let mut conn = acceptor().accept_with_state(sock, s).unwrap();
+        let p = parser::Parser::new();
+        assert!(conn.read_message(&p).is_err());
+        assert!(conn.get_state().templates_len() == 0);
+        exporter.join().unwrap();
+    }
+}
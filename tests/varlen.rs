@@ -0,0 +1,62 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    extern crate rsipfix;
+
+    use self::rsipfix::{parser, state};
+    use common;
+
+    // records of an interfaceName and a sourceTransportPort
+    fn parse(records: &[u8]) -> Vec<(String, Option<u64>)> {
+        let template = common::set(2, &common::template(256, &[(82, 65535), (7, 2)]));
+        let msg = common::message(1000, 0, 1, &[template, common::set(256, records)]);
+        let p = parser::Parser::new();
+        let msg = p.parse_message(&mut state::State::new(), &msg).unwrap();
+        msg.get_dataset_records()
+            .iter()
+            .map(|r| {
+                let name = r.get("interfaceName").unwrap().as_str().unwrap();
+                (
+                    name.to_string(),
+                    r.get("sourceTransportPort").unwrap().as_u64(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn long_form() {
+        // the fields after a long form value stay aligned
+        let mut records = common::varlen(&[b'a'; 300]);
+        records.extend_from_slice(&53u16.to_be_bytes());
+        records.extend_from_slice(&common::varlen(&[b'b'; 255]));
+        records.extend_from_slice(&80u16.to_be_bytes());
+        let parsed = parse(&records);
+        assert!(parsed.len() == 2);
+        assert!(parsed[0] == ("a".repeat(300), Some(53)));
+        assert!(parsed[1] == ("b".repeat(255), Some(80)));
+    }
+
+    #[test]
+    fn short_form() {
+        let mut records = common::varlen(&[b'a'; 254]);
+        records.extend_from_slice(&53u16.to_be_bytes());
+        records.extend_from_slice(&common::varlen(b""));
+        records.extend_from_slice(&80u16.to_be_bytes());
+        let parsed = parse(&records);
+        assert!(parsed.len() == 2);
+        assert!(parsed[0] == ("a".repeat(254), Some(53)));
+        assert!(parsed[1] == (String::new(), Some(80)));
+    }
+
+    #[test]
+    fn long_form_cut_short() {
+        // the record before it is kept, the cut one skipped
+        let mut records = common::varlen(b"eth0");
+        records.extend_from_slice(&53u16.to_be_bytes());
+        records.extend_from_slice(&[255, 1]);
+        let parsed = parse(&records);
+        assert!(parsed == vec![("eth0".to_string(), Some(53))]);
+    }
+}